@@ -0,0 +1,19 @@
+// Round-trip latency against the echo child with and without spinning for
+// the ACK, at a few spin budgets.
+//
+//   cargo bench --bench ack_spin --features bench
+
+use efdstream::bench::measure_rtt;
+use efdstream::ShmParent;
+
+const CHILD: &str = env!("CARGO_BIN_EXE_efdstream");
+const ITERS: usize = 20_000;
+
+fn main() {
+    for spin in [0, 1_000, 10_000, 100_000] {
+        let mut parent = ShmParent::builder(CHILD).child_args(&["-mode", "echo"]).shm_size(4096).ack_spin(spin).build();
+        parent.start().unwrap();
+        let stats = measure_rtt(&mut parent, ITERS).unwrap();
+        println!("ack_spin {:>7}: p50 {:?}, p99 {:?}, max {:?}", spin, stats.p50, stats.p99, stats.max);
+    }
+}
@@ -0,0 +1,48 @@
+// Compares plain and non-temporal copies of multi-megabyte frames: raw copy
+// throughput, and how long the producer then takes to rescan a small hot
+// working set (a proxy for how much of the cache the copy evicted).
+//
+//   cargo bench --bench nontemporal
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use efdstream::copy::copy_nontemporal;
+
+const WORKING_SET: usize = 256 * 1024;
+const ROUNDS: u32 = 20;
+
+fn rescan(ws: &[u8]) -> u64 {
+    ws.iter().step_by(64).map(|&b| b as u64).sum()
+}
+
+fn plain_copy(dst: &mut [u8], src: &[u8]) {
+    dst[..src.len()].copy_from_slice(src);
+}
+
+fn run(label: &str, frame: &[u8], shm: &mut [u8], ws: &[u8], copy: fn(&mut [u8], &[u8])) {
+    let mut copy_time = Duration::ZERO;
+    let mut rescan_time = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        black_box(rescan(ws));
+        let t = Instant::now();
+        copy(black_box(&mut *shm), black_box(frame));
+        copy_time += t.elapsed();
+        let t = Instant::now();
+        black_box(rescan(ws));
+        rescan_time += t.elapsed();
+    }
+    let mb_per_s = (frame.len() as f64 * ROUNDS as f64) / copy_time.as_secs_f64() / 1e6;
+    println!("{:>12} {:>3} MiB: {:>8.0} MB/s, working-set rescan {:?}",
+        label, frame.len() >> 20, mb_per_s, rescan_time / ROUNDS);
+}
+
+fn main() {
+    let ws = vec![1u8; WORKING_SET];
+    for mib in [4, 16, 64] {
+        let frame = vec![0xa5u8; mib << 20];
+        let mut shm = vec![0u8; mib << 20];
+        run("plain", &frame, &mut shm, &ws, plain_copy);
+        run("nontemporal", &frame, &mut shm, &ws, copy_nontemporal);
+    }
+}
@@ -0,0 +1,53 @@
+// Echo throughput for small messages: the lock-step path, which waits for an
+// ACK per message, against ring mode, which keeps a window of them in flight.
+//
+//   cargo bench --bench ring
+
+use std::time::{Duration, Instant};
+
+use efdstream::{RingShmParent, ShmParent};
+
+const CHILD: &str = env!("CARGO_BIN_EXE_efdstream");
+const MESSAGES: usize = 200_000;
+const LEN: usize = 64;
+
+fn report(label: &str, elapsed: Duration) {
+    println!("{:>16}: {:>9.0} msg/s, {:?} per message", label,
+        MESSAGES as f64 / elapsed.as_secs_f64(), elapsed / MESSAGES as u32);
+}
+
+fn lock_step(msg: &[u8]) {
+    let mut parent = ShmParent::builder(CHILD).child_args(&["-mode", "echo"]).shm_size(4096).build();
+    parent.start().unwrap();
+    let start = Instant::now();
+    for _ in 0..MESSAGES {
+        parent.send_to_peer(msg).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap().len(), LEN);
+    }
+    report("lock-step", start.elapsed());
+}
+
+// Sends `window` messages before reading their echoes back, so both rings
+// stay below full.
+fn ring(msg: &[u8], window: usize) {
+    let mut parent = RingShmParent::new(CHILD, window, LEN).child_args(&["-mode", "echo"]);
+    parent.start().unwrap();
+    let start = Instant::now();
+    for _ in 0..MESSAGES / window {
+        for _ in 0..window {
+            parent.send_data(msg).unwrap();
+        }
+        for _ in 0..window {
+            assert_eq!(parent.read_data().unwrap().len(), LEN);
+        }
+    }
+    report(&format!("ring, {} slots", window), start.elapsed());
+}
+
+fn main() {
+    let msg = [0x5au8; LEN];
+    lock_step(&msg);
+    for window in [8, 64, 256] {
+        ring(&msg, window);
+    }
+}
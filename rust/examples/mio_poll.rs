@@ -0,0 +1,35 @@
+// Waits for messages from a child with a mio::Poll instead of blocking in
+// read_data.
+//
+//   cargo run --example mio_poll --features mio -- ./target/debug/efdstream
+
+use mio::{Events, Interest, Poll, Token};
+
+use efdstream::ShmParent;
+
+const CHILD: Token = Token(0);
+
+fn main() {
+    let child_path = std::env::args().nth(1).expect("usage: mio_poll <child binary>");
+
+    let mut parent = ShmParent::new(&child_path, 1024 * 1024);
+    parent.start().unwrap();
+
+    let mut poll = Poll::new().unwrap();
+    poll.registry().register(&mut parent, CHILD, Interest::READABLE).unwrap();
+
+    let mut events = Events::with_capacity(8);
+    for i in 0..5 {
+        parent.send_to_peer(format!("Hello from mio {}", i).as_bytes()).unwrap();
+
+        poll.poll(&mut events, None).unwrap();
+        for event in events.iter() {
+            if event.token() == CHILD {
+                let data = parent.recv_from_peer().unwrap();
+                println!("Received: {}", String::from_utf8_lossy(&data));
+            }
+        }
+    }
+
+    poll.registry().deregister(&mut parent).unwrap();
+}
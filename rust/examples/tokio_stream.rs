@@ -0,0 +1,22 @@
+// Consumes a child's messages as a futures Stream on a Tokio runtime. The
+// child here is this crate's binary, which sends five messages.
+//
+//   cargo run --example tokio_stream --features tokio -- ./target/debug/efdstream
+
+use futures_util::StreamExt;
+
+use efdstream::{AsyncShmParent, ShmParent};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let child_path = std::env::args().nth(1).expect("usage: tokio_stream <child binary>");
+
+    let mut parent = AsyncShmParent::new(ShmParent::new(&child_path, 1024 * 1024));
+    parent.start().unwrap();
+    parent.send_data(b"Hello from tokio").await.unwrap();
+
+    let mut stream = Box::pin(parent.into_stream().take(5));
+    while let Some(msg) = stream.next().await {
+        println!("Received: {}", String::from_utf8_lossy(&msg.unwrap()));
+    }
+}
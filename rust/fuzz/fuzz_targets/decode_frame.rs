@@ -0,0 +1,26 @@
+// cargo +nightly fuzz run decode_frame
+//
+// Input: 8 doorbell bytes, one byte choosing how the claimed shm_size relates
+// to the real buffer, then the region contents. decode_frame must reject or
+// return an in-bounds payload, never panic.
+#![no_main]
+
+use efdstream::frame::{decode_frame, DOORBELL_LEN};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() <= DOORBELL_LEN {
+        let _ = decode_frame(data, &[], 0);
+        return;
+    }
+    let (doorbell, rest) = data.split_at(DOORBELL_LEN);
+    let (mode, shm) = rest.split_first().unwrap();
+    let shm_size = match mode % 3 {
+        0 => shm.len(),
+        1 => shm.len() / 2,
+        _ => shm.len() + *mode as usize,
+    };
+    if let Ok(payload) = decode_frame(doorbell, shm, shm_size) {
+        assert!(payload.len() <= shm_size && payload.len() <= shm.len());
+    }
+});
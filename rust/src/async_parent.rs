@@ -0,0 +1,190 @@
+// Tokio front end for `ShmParent`. The ACK and C2P doorbell eventfds, and the
+// child's pidfd, are registered with the runtime through `AsyncFd`, so a task
+// waiting on them is parked by the reactor instead of blocking its thread.
+//
+// The eventfds stay in blocking mode: the child shares their file
+// descriptions, and O_NONBLOCK would change its reads too. Readiness from the
+// reactor is therefore confirmed with a zero-timeout poll before anything is
+// read, and stale readiness is cleared so the task goes back to sleep.
+//
+// Waiting never consumes anything: a doorbell is only read once the whole
+// receive can finish without another await, which is what makes dropping a
+// future safe.
+
+use std::future::poll_fn;
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
+use std::task::Poll;
+use std::time::Instant;
+
+use futures_core::Stream;
+use tokio::io::unix::AsyncFd;
+
+use crate::efd::{eventfd_write, is_readable, Direction, ShmParent};
+use crate::error::{EfdStreamError, Result};
+
+// A simplex session registers only its direction's eventfd.
+struct Registered {
+    p2c_ack: Option<AsyncFd<RawFd>>,
+    c2p_send: Option<AsyncFd<RawFd>>,
+    // Readable once the child exits; absent for a socket-started peer.
+    exit: Option<AsyncFd<RawFd>>,
+}
+
+// Resolves to true once `fd` holds a count, or false if the child exited.
+async fn readable(fd: &AsyncFd<RawFd>, exit: Option<&AsyncFd<RawFd>>) -> Result<bool> {
+    poll_fn(|cx| loop {
+        match fd.poll_read_ready(cx) {
+            Poll::Ready(Ok(mut guard)) => {
+                if is_readable(unsafe { BorrowedFd::borrow_raw(*fd.get_ref()) })? {
+                    return Poll::Ready(Ok(true));
+                }
+                guard.clear_ready();
+            }
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+            Poll::Pending => {
+                // Checked after the eventfd, so a message the child sent just
+                // before exiting is still delivered.
+                return match exit.map(|exit| exit.poll_read_ready(cx)) {
+                    Some(Poll::Ready(Ok(_))) => Poll::Ready(Ok(false)),
+                    Some(Poll::Ready(Err(e))) => Poll::Ready(Err(e.into())),
+                    _ => Poll::Pending,
+                };
+            }
+        }
+    }).await
+}
+
+/// `ShmParent` whose `send_data` and `read_data` are futures for the Tokio
+/// runtime. The wrapped parent may be started already, e.g. with
+/// `start_with_socket`; the eventfds are registered on first use, which
+/// must happen inside a runtime.
+pub struct AsyncShmParent {
+    inner: ShmParent,
+    fds: Option<Registered>,
+}
+
+impl AsyncShmParent {
+    pub fn new(parent: ShmParent) -> Self {
+        Self { inner: parent, fds: None }
+    }
+
+    /// `ShmParent::start`. The handshake, if any, is still waited for
+    /// synchronously.
+    pub fn start(&mut self) -> Result<()> {
+        self.inner.start()
+    }
+
+    pub fn parent(&self) -> &ShmParent {
+        &self.inner
+    }
+
+    fn register(&mut self) -> Result<()> {
+        if self.fds.is_some() {
+            return Ok(());
+        }
+        let (p2c_ack, c2p_send) = (self.inner.p2c_ack_fd(), self.inner.c2p_send_fd());
+        if p2c_ack.is_none() && c2p_send.is_none() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        self.fds = Some(Registered {
+            p2c_ack: p2c_ack.map(|fd| AsyncFd::new(fd.as_raw_fd())).transpose()?,
+            c2p_send: c2p_send.map(|fd| AsyncFd::new(fd.as_raw_fd())).transpose()?,
+            exit: self.inner.pidfd.as_ref().map(|pidfd| AsyncFd::new(pidfd.as_raw_fd())).transpose()?,
+        });
+        Ok(())
+    }
+
+    // Waits until the child has ACKed the last send, if it hasn't yet. Also
+    // covers a `send_data` future dropped before its ACK arrived.
+    async fn settle_ack(&mut self) -> Result<()> {
+        while self.inner.ack_pending && self.inner.poll_acks()? == 0 {
+            let fds = self.fds.as_ref().unwrap();
+            if !readable(fds.p2c_ack.as_ref().unwrap(), fds.exit.as_ref()).await? {
+                return Err(self.inner.child_died());
+            }
+        }
+        Ok(())
+    }
+
+    /// Cancellation-safe in the sense `select!` needs: dropped before the
+    /// message is written, it sends nothing; dropped while waiting for the
+    /// ACK, the message has been sent and the next call waits for that ACK
+    /// first. Either way nothing is lost or sent twice, but the caller can't
+    /// tell which happened.
+    pub async fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        self.inner.direction.require(Direction::ParentToChild)?;
+        self.register()?;
+        self.settle_ack().await?;
+        self.inner.check_send(data)?;
+        if self.inner.double_buffer {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Double buffering needs ShmParent's own sends").into());
+        }
+        let Some(send) = self.inner.p2c_send_fd().map(|fd| fd.as_raw_fd()) else {
+            return Err(EfdStreamError::NotStarted);
+        };
+
+        let delay = self.inner.pacing_delay();
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        let doorbell = self.inner.write_frame(data);
+        let sent_at = Instant::now();
+        self.inner.last_send = Some(sent_at);
+        eventfd_write(unsafe { BorrowedFd::borrow_raw(send) }, self.inner.endianness.to_wire(doorbell))?;
+        self.inner.ack_pending = true;
+        self.settle_ack().await?;
+        self.inner.metrics.record_send(data.len(), sent_at.elapsed());
+        Ok(())
+    }
+
+    /// `ShmParent::flush`: waits for the ACK a dropped `send_data` future
+    /// left outstanding, if any.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.inner.direction.require(Direction::ParentToChild)?;
+        self.register()?;
+        self.settle_ack().await
+    }
+
+    /// Cancellation-safe: a future dropped at any point, e.g. by a `select!`
+    /// branch that lost, has consumed nothing, and the next call returns the
+    /// message it would have.
+    pub async fn read_data(&mut self) -> Result<Vec<u8>> {
+        self.read_with(|payload| payload.to_vec()).await
+    }
+
+    /// `read_data` without the `Vec`: `f` gets the payload while it is
+    /// still in SHM, and the ACK goes out once it returns, so nothing is
+    /// allocated unless the session is encrypted. The borrow can't outlive
+    /// the call. Cancellation-safe like `read_data`; `f` only runs once the
+    /// message is taken.
+    pub async fn read_with<R>(&mut self, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        self.inner.direction.require(Direction::ChildToParent)?;
+        self.register()?;
+        let mut f = f;
+        loop {
+            match self.inner.try_read_with(f)? {
+                Ok(result) => return Ok(result),
+                Err(back) => f = back,
+            }
+            let fds = self.fds.as_ref().unwrap();
+            if !readable(fds.c2p_send.as_ref().unwrap(), fds.exit.as_ref()).await? {
+                return Err(self.inner.child_died());
+            }
+        }
+    }
+
+    /// Every message the child sends, in order. The stream ends when the
+    /// child calls `ShmChild::shutdown_send`, or after yielding the first
+    /// error, e.g. `ChildDied` as an `io::Error` wrapping it.
+    pub fn into_stream(self) -> impl Stream<Item = std::io::Result<Vec<u8>>> {
+        futures_util::stream::unfold(Some(self), |parent| async move {
+            let mut parent = parent?;
+            match parent.read_data().await {
+                Ok(data) => Some((Ok(data), Some(parent))),
+                Err(EfdStreamError::PeerClosed) => None,
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        })
+    }
+}
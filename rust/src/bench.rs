@@ -0,0 +1,117 @@
+// Timing loops over a started `ShmParent`, so a parent/child pair can be
+// measured the same way across versions. Samples are kept and sorted rather
+// than bucketed: a run is at most a few million sends, and exact percentiles
+// are worth the memory.
+
+use std::time::{Duration, Instant};
+
+use crate::efd::ShmParent;
+use crate::error::Result;
+
+/// Payload size `measure_rtt` sends.
+pub const RTT_PAYLOAD: usize = 64;
+
+/// Latency distribution of one run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RttStats {
+    pub samples: usize,
+    pub min: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl RttStats {
+    fn from_samples(samples: &mut [Duration]) -> Self {
+        samples.sort_unstable();
+        let at = |q: f64| samples[((samples.len() - 1) as f64 * q).round() as usize];
+        let total: Duration = samples.iter().sum();
+        Self {
+            samples: samples.len(),
+            min: samples[0],
+            mean: total / samples.len() as u32,
+            p50: at(0.50),
+            p99: at(0.99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThroughputStats {
+    pub messages: u64,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Payload bytes per second, in units of 10^6.
+    pub mb_per_s: f64,
+    /// How long each `send_to_peer` took: copying the payload in, ringing the
+    /// doorbell and waiting for the child's ACK.
+    pub send_latency: RttStats,
+}
+
+fn no_iterations() -> crate::error::EfdStreamError {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "Nothing to measure").into()
+}
+
+/// Times `iters` round trips of a `RTT_PAYLOAD`-byte message: `send_to_peer`
+/// followed by `recv_from_peer` of the reply. The child must answer every
+/// message, e.g. with `ShmChild::listen_request_response`. A tenth as many
+/// untimed round trips run first to fault in the mappings and warm the
+/// caches.
+pub fn measure_rtt(parent: &mut ShmParent, iters: usize) -> Result<RttStats> {
+    if iters == 0 {
+        return Err(no_iterations());
+    }
+    let payload = [0xa5u8; RTT_PAYLOAD];
+    for _ in 0..iters / 10 {
+        parent.send_to_peer(&payload)?;
+        parent.recv_from_peer()?;
+    }
+
+    let mut samples = Vec::with_capacity(iters);
+    for _ in 0..iters {
+        let start = Instant::now();
+        parent.send_to_peer(&payload)?;
+        parent.recv_from_peer()?;
+        samples.push(start.elapsed());
+    }
+    Ok(RttStats::from_samples(&mut samples))
+}
+
+/// Sends `payload_size`-byte messages back to back for `duration`, after an
+/// untimed warmup of a tenth of that. The child must only consume: a child
+/// that replies blocks once the parent stops reading.
+pub fn measure_throughput(parent: &mut ShmParent, payload_size: usize, duration: Duration) -> Result<ThroughputStats> {
+    if duration.is_zero() {
+        return Err(no_iterations());
+    }
+    let payload = vec![0xa5u8; payload_size];
+    let warmup_end = Instant::now() + duration / 10;
+    while Instant::now() < warmup_end {
+        parent.send_to_peer(&payload)?;
+    }
+
+    let mut samples = Vec::new();
+    let start = Instant::now();
+    let end = start + duration;
+    loop {
+        let sent = Instant::now();
+        parent.send_to_peer(&payload)?;
+        samples.push(sent.elapsed());
+        if sent >= end {
+            break;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let messages = samples.len() as u64;
+    let bytes = messages * payload_size as u64;
+    Ok(ThroughputStats {
+        messages,
+        bytes,
+        elapsed,
+        mb_per_s: bytes as f64 / elapsed.as_secs_f64() / 1e6,
+        send_latency: RttStats::from_samples(&mut samples),
+    })
+}
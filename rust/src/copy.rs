@@ -0,0 +1,75 @@
+// Copy strategies for moving a payload into a SHM region.
+//
+// Payloads much larger than the last-level cache would otherwise evict the
+// producer's working set on their way into SHM, even though the producer never
+// reads them again. Non-temporal (streaming) stores write around the cache.
+
+use std::ptr;
+
+/// Copies `src` into the start of `dst` using non-temporal stores where the
+/// CPU has them (SSE2/AVX on x86-64), and a plain copy elsewhere.
+///
+/// Panics if `dst` is shorter than `src`.
+pub fn copy_nontemporal(dst: &mut [u8], src: &[u8]) {
+    assert!(dst.len() >= src.len(), "destination too small");
+    unsafe { stream_copy(dst.as_mut_ptr(), src) }
+}
+
+// Picks the strategy for one payload: streaming stores at or above
+// `threshold`, `copy_nonoverlapping` below it or when no threshold is set.
+pub(crate) unsafe fn copy_payload(dst: *mut u8, src: &[u8], threshold: Option<usize>) {
+    match threshold {
+        Some(threshold) if src.len() >= threshold => unsafe { stream_copy(dst, src) },
+        _ => unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) },
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn stream_copy(dst: *mut u8, src: &[u8]) {
+    use std::arch::x86_64::{__m128i, _mm_loadu_si128, _mm_sfence, _mm_stream_si128};
+
+    let len = src.len();
+    let src = src.as_ptr();
+
+    // Streaming stores need an aligned destination; copy the unaligned head
+    // normally.
+    let head = dst.align_offset(32).min(len);
+    unsafe { ptr::copy_nonoverlapping(src, dst, head) };
+    let mut i = head;
+
+    if is_x86_feature_detected!("avx") {
+        i = unsafe { stream_avx(dst, src, i, len) };
+    }
+    // SSE2 is part of the x86-64 baseline.
+    while i + 16 <= len {
+        unsafe {
+            let v = _mm_loadu_si128(src.add(i) as *const __m128i);
+            _mm_stream_si128(dst.add(i) as *mut __m128i, v);
+        }
+        i += 16;
+    }
+    // Order the streaming stores before the doorbell write that publishes them.
+    unsafe { _mm_sfence() };
+
+    unsafe { ptr::copy_nonoverlapping(src.add(i), dst.add(i), len - i) };
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn stream_avx(dst: *mut u8, src: *const u8, mut i: usize, len: usize) -> usize {
+    use std::arch::x86_64::{__m256i, _mm256_loadu_si256, _mm256_stream_si256};
+
+    while i + 32 <= len {
+        unsafe {
+            let v = _mm256_loadu_si256(src.add(i) as *const __m256i);
+            _mm256_stream_si256(dst.add(i) as *mut __m256i, v);
+        }
+        i += 32;
+    }
+    i
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+unsafe fn stream_copy(dst: *mut u8, src: &[u8]) {
+    unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst, src.len()) }
+}
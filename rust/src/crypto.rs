@@ -0,0 +1,168 @@
+// Payload encryption for the `crypto` feature.
+//
+// Every frame is sealed with XChaCha20-Poly1305 under the shared key. The nonce
+// is the 16-byte session id the parent picks at random for the handshake,
+// followed by the direction and the frame's sequence number, so nonces never
+// repeat within a session and a reused key doesn't repeat them across
+// sessions either. A sealed frame is the ciphertext followed by the tag.
+//
+// The encrypted handshake offer is the plain record, the session id, and a
+// tag over both (empty plaintext). The child can only verify that tag with
+// the same key, which is how mismatched keys are caught before any data.
+//
+// Without the feature `FrameCipher` is uninhabited, so the data path can hold
+// an `Option<FrameCipher>` unconditionally and the compiler drops the
+// encrypted branches.
+
+#[cfg(feature = "crypto")]
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+#[cfg(feature = "crypto")]
+use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+
+use crate::error::Result;
+#[cfg(feature = "crypto")]
+use crate::error::EfdStreamError;
+#[cfg(feature = "crypto")]
+use crate::handshake::RECORD_LEN;
+
+pub(crate) const TAG_LEN: usize = 16;
+#[cfg(feature = "crypto")]
+pub(crate) const SESSION_LEN: usize = 16;
+#[cfg(feature = "crypto")]
+pub(crate) const OFFER_LEN: usize = RECORD_LEN + SESSION_LEN + TAG_LEN;
+
+#[cfg(feature = "crypto")]
+const DIR_P2C: u64 = 0;
+#[cfg(feature = "crypto")]
+const DIR_C2P: u64 = 1;
+#[cfg(feature = "crypto")]
+const DIR_HANDSHAKE: u64 = 2;
+
+#[cfg(feature = "crypto")]
+pub(crate) struct FrameCipher {
+    aead: XChaCha20Poly1305,
+    session: [u8; SESSION_LEN],
+    send_dir: u64,
+    recv_dir: u64,
+    send_seq: u64,
+    recv_seq: u64,
+}
+
+#[cfg(not(feature = "crypto"))]
+pub(crate) enum FrameCipher {}
+
+#[cfg(feature = "crypto")]
+impl FrameCipher {
+    fn new(key: &[u8; 32], session: [u8; SESSION_LEN], is_parent: bool) -> Self {
+        let (send_dir, recv_dir) = if is_parent { (DIR_P2C, DIR_C2P) } else { (DIR_C2P, DIR_P2C) };
+        Self {
+            aead: XChaCha20Poly1305::new(key.into()),
+            session,
+            send_dir,
+            recv_dir,
+            send_seq: 0,
+            recv_seq: 0,
+        }
+    }
+
+    // Parent side: append the session id and key check after the plain
+    // handshake record in `shm`, returning the cipher and the offer length.
+    pub(crate) fn offer(key: &[u8; 32], shm: &mut [u8]) -> Result<(Self, usize)> {
+        let session = Self::random_session()?;
+        let cipher = Self::new(key, session, true);
+        shm[RECORD_LEN..RECORD_LEN + SESSION_LEN].copy_from_slice(&session);
+        let tag = cipher.key_check(&shm[..RECORD_LEN + SESSION_LEN]);
+        shm[RECORD_LEN + SESSION_LEN..OFFER_LEN].copy_from_slice(&tag);
+        Ok((cipher, OFFER_LEN))
+    }
+
+    // Child side: the cipher for an encrypted `offer`, or None if it isn't
+    // one or was made with a different key.
+    pub(crate) fn accept(key: &[u8; 32], offer: &[u8]) -> Option<Self> {
+        if offer.len() != OFFER_LEN {
+            return None;
+        }
+        let session = offer[RECORD_LEN..RECORD_LEN + SESSION_LEN].try_into().unwrap();
+        let cipher = Self::new(key, session, false);
+        let (signed, tag) = offer.split_at(RECORD_LEN + SESSION_LEN);
+        cipher.verify_key_check(signed, tag).then_some(cipher)
+    }
+
+    fn random_session() -> std::io::Result<[u8; SESSION_LEN]> {
+        let mut session = [0u8; SESSION_LEN];
+        let n = unsafe { libc::getrandom(session.as_mut_ptr() as *mut libc::c_void, SESSION_LEN, 0) };
+        if n != SESSION_LEN as isize {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(session)
+    }
+
+    fn nonce(&self, dir: u64, seq: u64) -> XNonce {
+        let mut nonce = XNonce::default();
+        nonce[..SESSION_LEN].copy_from_slice(&self.session);
+        nonce[SESSION_LEN..].copy_from_slice(&(dir << 62 | seq).to_le_bytes());
+        nonce
+    }
+
+    // Tag proving knowledge of the key, bound to the handshake `record`.
+    fn key_check(&self, record: &[u8]) -> [u8; TAG_LEN] {
+        let tag = self.aead
+            .encrypt_in_place_detached(&self.nonce(DIR_HANDSHAKE, 0), record, &mut [])
+            .expect("empty plaintext always encrypts");
+        tag.into()
+    }
+
+    fn verify_key_check(&self, record: &[u8], tag: &[u8]) -> bool {
+        tag.len() == TAG_LEN && self.aead
+            .decrypt_in_place_detached(&self.nonce(DIR_HANDSHAKE, 0), record, &mut [], Tag::from_slice(tag))
+            .is_ok()
+    }
+
+    // Seals `data` into `dst` (which must hold `data.len() + TAG_LEN` bytes)
+    // and returns the frame length.
+    pub(crate) fn seal(&mut self, dst: &mut [u8], data: &[u8]) -> usize {
+        dst[..data.len()].copy_from_slice(data);
+        self.seal_in_place(dst, data.len())
+    }
+
+    // Seals the first `len` bytes of `dst` where they are, appending the tag.
+    pub(crate) fn seal_in_place(&mut self, dst: &mut [u8], len: usize) -> usize {
+        let (body, tag_out) = dst[..len + TAG_LEN].split_at_mut(len);
+        let tag = self.aead
+            .encrypt_in_place_detached(&self.nonce(self.send_dir, self.send_seq), b"", body)
+            .expect("frame fits the XChaCha20 block counter");
+        tag_out.copy_from_slice(&tag);
+        self.send_seq += 1;
+        len + TAG_LEN
+    }
+
+    // The sequence number advances even on failure, since the sender's did.
+    pub(crate) fn open(&mut self, frame: &[u8]) -> Result<Vec<u8>> {
+        let seq = self.recv_seq;
+        self.recv_seq += 1;
+        if frame.len() < TAG_LEN {
+            return Err(EfdStreamError::DecryptFailed);
+        }
+        let (body, tag) = frame.split_at(frame.len() - TAG_LEN);
+        let mut plain = body.to_vec();
+        self.aead
+            .decrypt_in_place_detached(&self.nonce(self.recv_dir, seq), b"", &mut plain, Tag::from_slice(tag))
+            .map_err(|_| EfdStreamError::DecryptFailed)?;
+        Ok(plain)
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+impl FrameCipher {
+    pub(crate) fn seal(&mut self, _dst: &mut [u8], _data: &[u8]) -> usize {
+        match *self {}
+    }
+
+    pub(crate) fn seal_in_place(&mut self, _dst: &mut [u8], _len: usize) -> usize {
+        match *self {}
+    }
+
+    pub(crate) fn open(&mut self, _frame: &[u8]) -> Result<Vec<u8>> {
+        match *self {}
+    }
+}
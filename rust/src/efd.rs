@@ -1,349 +1,4146 @@
-use std::fs::File;
-use std::io::{Read, Write};
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd, BorrowedFd};
-use std::process::{Child, Command, Stdio};
-use std::os::unix::process::CommandExt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
 use std::ptr::{self, NonNull};
 use std::slice;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Sender, SyncSender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use nix::sys::eventfd::{EventFd, EfdFlags};
-use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use nix::sys::mman::{madvise, mlock, MmapAdvise, ProtFlags};
+use nix::fcntl::{fcntl, FcntlArg, SealFlag};
 use nix::sys::memfd::{memfd_create, MFdFlags};
-use nix::unistd::ftruncate;
+use nix::sys::stat::fstat;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::unistd::{ftruncate, read, write};
 use std::ffi::CString;
 
+use crate::copy::copy_payload;
+use crate::crypto::{self, FrameCipher};
+use crate::error::{EfdStreamError, Result};
+use crate::fdpass::{self, recv_fds, send_fds};
+use crate::frame::{check_length, check_timestamp, decode_frame, monotonic_ns, split_timestamp, Endianness, DOORBELL_LEN, EOF_DOORBELL, ERROR_ACK, NACK, RESET_DOORBELL, RESIZE_DOORBELL, SECOND_BUFFER, TIMESTAMP_LEN};
+use crate::handshake::{self, Hello};
+use crate::metrics::Metrics;
+use crate::region::SharedRegion;
+use crate::retry::Retry;
+use crate::spawn::{check_executable, check_targets, spawn_child, ChildProcess, DropBehavior, FdLayout, SpawnMode};
+#[cfg(feature = "tracing")]
+use crate::trace;
+
+// The C2P memfd carries a trailer after the payload area: a small control
+// block, one metadata sidecar per direction, then the child's error report.
+// It is appended rather than
+// prepended so payloads stay at offset 0 for the Go and C implementations,
+// which map only `shm_size` bytes and never see it.
+const CONTROL_LEN: usize = 64;
+
+/// Largest metadata `send_data_with_meta` carries alongside a payload.
+pub const META_LEN: usize = 64;
+
+/// Longest message `Responder::set_error` passes to the parent; longer ones
+/// are cut at a character boundary.
+pub const ERROR_MESSAGE_LEN: usize = 248;
+
+// Error code and message length as u32s, then the message.
+const ERROR_LEN: usize = 8 + ERROR_MESSAGE_LEN;
+
+const TRAILER_LEN: usize = CONTROL_LEN + 2 * META_LEN + ERROR_LEN;
+
+// Which sidecar a direction's metadata goes in.
+const P2C_META: usize = 0;
+const C2P_META: usize = 1;
+
+fn control_offset(shm_size: usize) -> usize {
+    shm_size.next_multiple_of(CONTROL_LEN)
+}
+
+fn c2p_map_len(shm_size: usize) -> usize {
+    control_offset(shm_size) + TRAILER_LEN
+}
+
+// Sizing: the requested `shm_size` is the least payload capacity the caller
+// gets. The C2P mapping (payload plus trailer) is rounded up to whole pages
+// and the payload area grows into the slack, so
+//   usable size = mapped C2P size - TRAILER_LEN >= requested size.
+// The P2C region is made the same usable size, and that is the value the
+// child receives as `-shm-size`.
+fn usable_size_for(requested: usize) -> usize {
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    (requested + TRAILER_LEN).next_multiple_of(page) - TRAILER_LEN
+}
+
+unsafe fn control_at<'a>(c2p: *mut u8, shm_size: usize) -> &'a AtomicU64 {
+    unsafe { &*(c2p.add(control_offset(shm_size)) as *const AtomicU64) }
+}
+
+// The bits `ShmParent::send_control` raises, in the word after
+// `control_atomic`. They are set before the control eventfd is rung and
+// taken with a swap, so nothing is lost to coalesced wakeups.
+unsafe fn control_events_at<'a>(c2p: *mut u8, shm_size: usize) -> &'a AtomicU64 {
+    unsafe { &*(c2p.add(control_offset(shm_size) + 8) as *const AtomicU64) }
+}
+
+// The status a `ShmChild::listen_with_status` callback returned for the
+// last frame, in the word after the control bits. It is stored before the
+// ACK is written, so the parent reads it once the ACK arrives.
+unsafe fn status_at<'a>(c2p: *mut u8, shm_size: usize) -> &'a AtomicU64 {
+    unsafe { &*(c2p.add(control_offset(shm_size) + 16) as *const AtomicU64) }
+}
+
+// Nonzero while the child asks the parent to hold off sending; see
+// `PauseHandle`.
+unsafe fn pause_at<'a>(c2p: *mut u8, shm_size: usize) -> &'a AtomicU64 {
+    unsafe { &*(c2p.add(control_offset(shm_size) + 24) as *const AtomicU64) }
+}
+
+// The value of the child's last P2C ACK, stored after the eventfd write so
+// a parent using `ShmParentBuilder::ack_spin` can watch for it without a
+// syscall. The parent zeroes it before each send.
+unsafe fn ack_signal_at<'a>(c2p: *mut u8, shm_size: usize) -> &'a AtomicU64 {
+    unsafe { &*(c2p.add(control_offset(shm_size) + 32) as *const AtomicU64) }
+}
+
+unsafe fn clear_control_block(c2p: *mut u8, shm_size: usize) {
+    let words = unsafe { c2p.add(control_offset(shm_size)) } as *const AtomicU64;
+    for i in 0..CONTROL_LEN / 8 {
+        unsafe { &*words.add(i) }.store(0, Ordering::SeqCst);
+    }
+}
+
+unsafe fn meta_at(c2p: *mut u8, shm_size: usize, direction: usize) -> *mut u8 {
+    unsafe { c2p.add(control_offset(shm_size) + CONTROL_LEN + direction * META_LEN) }
+}
+
+// The sidecar is written before the doorbell and read before the ACK, so the
+// eventfd syscalls order it like the payload.
+unsafe fn write_meta(c2p: *mut u8, shm_size: usize, direction: usize, meta: &[u8]) {
+    unsafe { ptr::copy_nonoverlapping(meta.as_ptr(), meta_at(c2p, shm_size, direction), meta.len()) };
+}
+
+unsafe fn read_meta<const N: usize>(c2p: *mut u8, shm_size: usize, direction: usize) -> [u8; N] {
+    let mut meta = [0; N];
+    unsafe { ptr::copy_nonoverlapping(meta_at(c2p, shm_size, direction), meta.as_mut_ptr(), N) };
+    meta
+}
+
+// First payload byte of a frame in a streamed response: `Responder::send_stream`
+// and `ShmChild::send_stream` mark each item, then close with a lone
+// `STREAM_END`. It is carried in-band, so any parent can read the frames.
+const STREAM_PART: u8 = 0;
+const STREAM_END: u8 = 1;
+
+fn stream_frame(part: &[u8]) -> Vec<u8> {
+    [&[STREAM_PART][..], part].concat()
+}
+
+unsafe fn error_at(c2p: *mut u8, shm_size: usize) -> *mut u8 {
+    unsafe { c2p.add(control_offset(shm_size) + CONTROL_LEN + 2 * META_LEN) }
+}
+
+// Written before the ERROR_ACK and read after it, like a sidecar.
+unsafe fn write_child_error(c2p: *mut u8, shm_size: usize, code: u32, message: &str) {
+    let mut len = message.len().min(ERROR_MESSAGE_LEN);
+    while !message.is_char_boundary(len) {
+        len -= 1;
+    }
+    let area = unsafe { error_at(c2p, shm_size) };
+    unsafe {
+        ptr::copy_nonoverlapping(code.to_ne_bytes().as_ptr(), area, 4);
+        ptr::copy_nonoverlapping((len as u32).to_ne_bytes().as_ptr(), area.add(4), 4);
+        ptr::copy_nonoverlapping(message.as_ptr(), area.add(8), len);
+    }
+}
+
+unsafe fn read_child_error(c2p: *mut u8, shm_size: usize) -> (u32, String) {
+    let area = unsafe { slice::from_raw_parts(error_at(c2p, shm_size), ERROR_LEN) };
+    let code = u32::from_ne_bytes(area[..4].try_into().unwrap());
+    let len = (u32::from_ne_bytes(area[4..8].try_into().unwrap()) as usize).min(ERROR_MESSAGE_LEN);
+    (code, String::from_utf8_lossy(&area[8..8 + len]).into_owned())
+}
+
+fn no_meta_area() -> EfdStreamError {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "Parent reserved no metadata area").into()
+}
+
+fn no_control_block() -> EfdStreamError {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "Parent reserved no control block").into()
+}
+
+// eventfd transfers its 8-byte counter all-or-nothing, so a single read/write
+// must move exactly 8 bytes. Anything else means the fd is not behaving like
+// an eventfd and we must not treat the bytes as a length.
+pub(crate) fn eventfd_read(fd: BorrowedFd) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    let n = loop {
+        match read(fd, &mut buf) {
+            Ok(n) => break n,
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    if n == 0 {
+        return Err(EfdStreamError::PeerClosed);
+    }
+    if n != buf.len() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+            format!("eventfd read returned {} bytes, expected 8", n)).into());
+    }
+    Ok(u64::from_ne_bytes(buf))
+}
+
+// The crate's eventfds are blocking, where a write that would take the
+// counter past `u64::MAX - 1` waits for the reader. If the caller made one
+// non-blocking (the file description is shared with the peer), that write
+// fails with EAGAIN instead, which is reported as `WouldBlock` rather than
+// retried: only the reader draining the counter can clear it.
+pub(crate) fn eventfd_write(fd: BorrowedFd, value: u64) -> std::io::Result<()> {
+    let buf = value.to_ne_bytes();
+    let n = loop {
+        match write(fd, &buf) {
+            Ok(n) => break n,
+            Err(Errno::EINTR) => continue,
+            Err(Errno::EAGAIN) => {
+                return Err(std::io::Error::new(std::io::ErrorKind::WouldBlock,
+                    "eventfd counter would overflow; the peer has not drained it"));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    if n != buf.len() {
+        return Err(std::io::Error::new(std::io::ErrorKind::WriteZero,
+            format!("eventfd write transferred {} bytes, expected 8", n)));
+    }
+    Ok(())
+}
+
+// Blocks until `fd` is readable, or returns false if the pidfd `exit` shows
+// the child exited first. A deadline that has already passed fails without
+// polling at all. Any wakeup that reports neither (a signal, an early
+// timeout, a bare error bit) is spurious and polls again, so callers only
+// ever go on to read a doorbell that is there.
+pub(crate) fn wait_readable_or_exit(fd: BorrowedFd, exit: Option<BorrowedFd>, deadline: Option<Instant>) -> Result<bool> {
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN), PollFd::new(exit.unwrap_or(fd), PollFlags::POLLIN)];
+    let watched = if exit.is_some() { 2 } else { 1 };
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(EfdStreamError::Timeout);
+                }
+                // Round up so we never wake just before the deadline and spin.
+                let millis = remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+                PollTimeout::try_from(millis).unwrap()
+            }
+            None => PollTimeout::NONE,
+        };
+        match poll(&mut fds[..watched], timeout) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {
+                let revents = fds[0].revents().unwrap_or(PollFlags::empty());
+                if revents.contains(PollFlags::POLLIN) {
+                    return Ok(true);
+                }
+                if revents.contains(PollFlags::POLLNVAL) {
+                    return Err(std::io::Error::from_raw_os_error(libc::EBADF).into());
+                }
+                if revents.contains(PollFlags::POLLERR) {
+                    return Err(std::io::Error::other("eventfd reported an error").into());
+                }
+                if watched == 2 && fds[1].revents().is_some_and(|revents| !revents.is_empty()) {
+                    return Ok(false);
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Blocks until one of `fds` is readable and returns the index of the first
+// that is, so earlier entries win when several are.
+fn wait_any(fds: &[BorrowedFd]) -> Result<usize> {
+    let mut polled: Vec<PollFd> = fds.iter().map(|fd| PollFd::new(*fd, PollFlags::POLLIN)).collect();
+    loop {
+        match poll(&mut polled, PollTimeout::NONE) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {
+                for (i, fd) in polled.iter().enumerate() {
+                    let revents = fd.revents().unwrap_or(PollFlags::empty());
+                    if revents.intersects(PollFlags::POLLIN | PollFlags::POLLHUP) {
+                        return Ok(i);
+                    }
+                    if revents.contains(PollFlags::POLLNVAL) {
+                        return Err(std::io::Error::from_raw_os_error(libc::EBADF).into());
+                    }
+                    if revents.contains(PollFlags::POLLERR) {
+                        return Err(std::io::Error::other("eventfd reported an error").into());
+                    }
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Takes whatever count `fd` holds without blocking.
+fn drain_eventfd(fd: BorrowedFd) -> Result<()> {
+    if is_readable(fd)? {
+        eventfd_read(fd)?;
+    }
+    Ok(())
+}
+
+// Whether a doorbell is already waiting on `fd`.
+pub(crate) fn is_readable(fd: BorrowedFd) -> Result<bool> {
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    loop {
+        match poll(&mut fds, PollTimeout::ZERO) {
+            Ok(_) => return Ok(fds[0].revents().is_some_and(|revents| revents.contains(PollFlags::POLLIN))),
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// A parent reached through `ShmParent::bind` closed its end of the socket.
+fn parent_gone() -> EfdStreamError {
+    std::io::Error::new(std::io::ErrorKind::ConnectionReset, "Parent went away").into()
+}
+
+// Takes messages from `try_read` while they keep coming, up to `timeout`.
+// The peer shutting down its side ends the drain rather than failing it.
+pub(crate) fn drain_with(timeout: Duration, mut try_read: impl FnMut() -> Result<Option<Vec<u8>>>) -> Result<Vec<Vec<u8>>> {
+    let deadline = Instant::now() + timeout;
+    let mut messages = Vec::new();
+    while Instant::now() < deadline {
+        match try_read() {
+            Ok(Some(data)) => messages.push(data),
+            Ok(None) => break,
+            Err(EfdStreamError::PeerClosed) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(messages)
+}
+
+fn not_timestamped() -> EfdStreamError {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "Timestamps were not agreed on").into()
+}
+
+// Bytes a frame adds to its payload.
+fn frame_overhead(encrypted: bool, timestamps: bool) -> usize {
+    let tag = if encrypted { crypto::TAG_LEN } else { 0 };
+    let stamp = if timestamps { TIMESTAMP_LEN } else { 0 };
+    tag + stamp
+}
+
+// Writes a checked `data` at `shm` as one frame: stamped with the send time
+// in byte order `stamp` if set, then sealed if there is a cipher, so the
+// stamp is authenticated too. Returns the frame length for the doorbell.
+//
+// Safety: `shm` must be a mapped region of `shm_size` bytes, and `data` plus
+// `frame_overhead` must fit in it.
+unsafe fn write_frame_at(shm: *mut u8, shm_size: usize, cipher: Option<&mut FrameCipher>, stamp: Option<Endianness>,
+                         data: &[u8], nontemporal_threshold: Option<usize>) -> usize {
+    let sent = stamp.map(|order| order.encode(monotonic_ns()));
+    match (cipher, sent) {
+        (Some(cipher), None) => cipher.seal(unsafe { slice::from_raw_parts_mut(shm, shm_size) }, data),
+        (Some(cipher), Some(sent)) => {
+            // Laid out in place and sealed there, so the send path doesn't
+            // allocate.
+            let frame = unsafe { slice::from_raw_parts_mut(shm, shm_size) };
+            frame[..TIMESTAMP_LEN].copy_from_slice(&sent);
+            frame[TIMESTAMP_LEN..TIMESTAMP_LEN + data.len()].copy_from_slice(data);
+            cipher.seal_in_place(frame, TIMESTAMP_LEN + data.len())
+        }
+        (None, sent) => {
+            let header = match sent {
+                Some(sent) => {
+                    unsafe { (shm as *mut [u8; TIMESTAMP_LEN]).write_unaligned(sent) };
+                    TIMESTAMP_LEN
+                }
+                None => 0,
+            };
+            unsafe { copy_payload(shm.add(header), data, nontemporal_threshold) };
+            header + data.len()
+        }
+    }
+}
+
+// `write_frame_at` for a payload given in pieces, copied one after another
+// in place after the stamp and sealed there.
+//
+// Safety: as for `write_frame_at`, with `data` the pieces together.
+unsafe fn write_pieces_at(shm: *mut u8, shm_size: usize, cipher: Option<&mut FrameCipher>, stamp: Option<Endianness>,
+                          pieces: &[&[u8]], nontemporal_threshold: Option<usize>) -> usize {
+    let header = if stamp.is_some() { TIMESTAMP_LEN } else { 0 };
+    let mut len = 0;
+    for piece in pieces {
+        unsafe { copy_payload(shm.add(header + len), piece, nontemporal_threshold) };
+        len += piece.len();
+    }
+    if let Some(order) = stamp {
+        unsafe { (shm as *mut [u8; TIMESTAMP_LEN]).write_unaligned(order.encode(monotonic_ns())) };
+    }
+    match cipher {
+        Some(cipher) => cipher.seal_in_place(unsafe { slice::from_raw_parts_mut(shm, shm_size) }, header + len),
+        None => header + len,
+    }
+}
+
+// The inverse of `write_frame_at` after opening: the send time, if stamped,
+// and the payload. A stamp from the future fails with `ClockSkew`.
+fn unstamp(frame: &[u8], stamp: Option<Endianness>) -> Result<(Option<u64>, &[u8])> {
+    let Some(order) = stamp else {
+        return Ok((None, frame));
+    };
+    let (sent, payload) = split_timestamp(frame)?;
+    Ok((Some(check_timestamp(order.from_wire(sent))?), payload))
+}
+
+// One of `ShmChild`'s channel fds, which is -1 for the direction a simplex
+// session leaves out.
+fn borrow_channel_fd<'a>(fd: RawFd) -> Option<BorrowedFd<'a>> {
+    (fd >= 0).then(|| unsafe { BorrowedFd::borrow_raw(fd) })
+}
+
+// A pidfd for the child `pid`, readable once it exits. `None` on kernels
+// before 5.3, which then just don't get death detection.
+fn open_pidfd(pid: u32) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    (fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// Which way messages flow in a session. A simplex session only creates
+/// the eventfds and region for its one direction, half the resources of the
+/// default; calls for the other direction fail with
+/// `EfdStreamError::WrongDirection`. Only a Rust child, which reads the
+/// `-direction` argument, can join one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Bidirectional,
+    ParentToChild,
+    ChildToParent,
+}
+
+impl Direction {
+    fn has_p2c(self) -> bool {
+        self != Direction::ChildToParent
+    }
+
+    fn has_c2p(self) -> bool {
+        self != Direction::ParentToChild
+    }
+
+    // Fails with `WrongDirection` unless the session carries `flow`, or for
+    // `Bidirectional`, both flows.
+    pub(crate) fn require(self, flow: Direction) -> Result<()> {
+        let carried = match flow {
+            Direction::Bidirectional => self == Direction::Bidirectional,
+            Direction::ParentToChild => self.has_p2c(),
+            Direction::ChildToParent => self.has_c2p(),
+        };
+        if !carried {
+            return Err(EfdStreamError::WrongDirection { direction: self });
+        }
+        Ok(())
+    }
+
+    // The `-direction` argument, if any.
+    fn arg(self) -> Option<&'static str> {
+        match self {
+            Direction::Bidirectional => None,
+            Direction::ParentToChild => Some("p2c"),
+            Direction::ChildToParent => Some("c2p"),
+        }
+    }
+}
+
+/// Access-pattern hint passed to `madvise` for each mapped SHM region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    Normal,
+    /// Streaming use: read ahead aggressively, drop pages behind the cursor.
+    Sequential,
+    Random,
+    /// Prefault the region so the first messages don't take page faults.
+    WillNeed,
+    /// Ask the kernel to back the region with transparent huge pages.
+    HugePage,
+}
+
+impl Advice {
+    fn to_mmap_advise(self) -> MmapAdvise {
+        match self {
+            Advice::Normal => MmapAdvise::MADV_NORMAL,
+            Advice::Sequential => MmapAdvise::MADV_SEQUENTIAL,
+            Advice::Random => MmapAdvise::MADV_RANDOM,
+            Advice::WillNeed => MmapAdvise::MADV_WILLNEED,
+            Advice::HugePage => MmapAdvise::MADV_HUGEPAGE,
+        }
+    }
+}
+
+// Whether the kernel lets `MADV_HUGEPAGE` back shmem, memfds included,
+// with huge pages: the bracketed choice in `shmem_enabled` is one that
+// honours the advice or goes further.
+fn shmem_huge_pages_allowed() -> bool {
+    let Ok(setting) = std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/shmem_enabled") else {
+        return false;
+    };
+    ["[always]", "[within_size]", "[advise]", "[force]"].iter().any(|choice| setting.contains(choice))
+}
+
+// A socket file nobody accepts on is what a process that died while bound
+// leaves behind.
+fn bind_replacing_stale(path: &Path) -> std::io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            let is_socket = std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket());
+            match UnixStream::connect(path) {
+                Err(refused) if is_socket && refused.kind() == std::io::ErrorKind::ConnectionRefused => {
+                    std::fs::remove_file(path)?;
+                    UnixListener::bind(path)
+                }
+                _ => Err(e),
+            }
+        }
+        result => result,
+    }
+}
+
+fn advise_region(ptr: *mut u8, len: usize, advice: Option<Advice>) -> std::io::Result<()> {
+    let (Some(advice), Some(ptr)) = (advice, NonNull::new(ptr as *mut std::ffi::c_void)) else {
+        return Ok(());
+    };
+    unsafe { madvise(ptr, len, advice.to_mmap_advise()) }
+        .map_err(std::io::Error::from)
+}
+
+// Refuses a memfd the child is about to map for writing when its seals
+// forbid that, which would otherwise only show when the first write faults.
+// Descriptors that can't carry seals have none.
+fn check_seals(fd: BorrowedFd, region: &str) -> Result<()> {
+    let seals = match fcntl(fd, FcntlArg::F_GET_SEALS) {
+        Ok(seals) => SealFlag::from_bits_truncate(seals),
+        Err(Errno::EINVAL) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if seals.intersects(SealFlag::F_SEAL_WRITE | SealFlag::F_SEAL_FUTURE_WRITE) {
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied,
+            format!("The {} region is sealed against writes", region)).into());
+    }
+    Ok(())
+}
+
+// Pins the regions in RAM for `lock_memory`. The limit is only looked up once
+// mlock has failed, to say why.
+fn lock_regions(regions: &[&SharedRegion]) -> Result<()> {
+    for region in regions {
+        let Some(ptr) = NonNull::new(region.as_ptr() as *mut std::ffi::c_void) else {
+            continue;
+        };
+        match unsafe { mlock(ptr, region.len()) } {
+            Ok(()) => {}
+            Err(Errno::EPERM | Errno::ENOMEM) => {
+                let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+                if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } == -1 {
+                    return Err(std::io::Error::last_os_error().into());
+                }
+                let requested = regions.iter().map(|region| region.len()).sum();
+                return Err(EfdStreamError::MemlockLimit { requested, limit: limit.rlim_cur });
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct ShmParentBuilder {
+    child_path: String,
+    p2c_size: usize,
+    c2p_size: usize,
+    advice: Option<Advice>,
+    handshake: bool,
+    timestamps: bool,
+    endianness: Endianness,
+    nontemporal_threshold: Option<usize>,
+    min_send_interval: Option<Duration>,
+    ack_spin: u32,
+    child_args: Vec<String>,
+    spawn_mode: SpawnMode,
+    inherited_fds: Vec<(RawFd, RawFd)>,
+    fd_layout: FdLayout,
+    memfd_prefix: String,
+    env_prefix: Option<String>,
+    new_session: bool,
+    drop_behavior: DropBehavior,
+    retry: Retry,
+    control_channel: bool,
+    priority_channel: bool,
+    double_buffer: bool,
+    shared_state_size: usize,
+    shared_state_addr: Option<usize>,
+    direction: Direction,
+    lock_memory: bool,
+    seal_memfds: bool,
+    zero_on_drop: bool,
+    startup_timeout: Option<Duration>,
+    #[cfg(feature = "crypto")]
+    key: Option<[u8; 32]>,
+}
+
+impl ShmParentBuilder {
+    pub fn new(child_path: &str) -> Self {
+        Self {
+            child_path: child_path.to_string(),
+            p2c_size: 1024 * 1024,
+            c2p_size: 1024 * 1024,
+            advice: None,
+            handshake: false,
+            timestamps: false,
+            endianness: Endianness::Native,
+            nontemporal_threshold: None,
+            min_send_interval: None,
+            ack_spin: 0,
+            child_args: Vec::new(),
+            spawn_mode: SpawnMode::ForkExec,
+            inherited_fds: Vec::new(),
+            fd_layout: FdLayout::default(),
+            memfd_prefix: "efdstream_shm".to_string(),
+            env_prefix: None,
+            new_session: false,
+            drop_behavior: DropBehavior::Kill,
+            retry: Retry::default(),
+            control_channel: false,
+            priority_channel: false,
+            double_buffer: false,
+            shared_state_size: 0,
+            shared_state_addr: None,
+            direction: Direction::Bidirectional,
+            lock_memory: false,
+            seal_memfds: false,
+            zero_on_drop: false,
+            startup_timeout: Some(DEFAULT_STARTUP_TIMEOUT),
+            #[cfg(feature = "crypto")]
+            key: None,
+        }
+    }
+
+    pub fn shm_size(mut self, shm_size: usize) -> Self {
+        self.p2c_size = shm_size;
+        self.c2p_size = shm_size;
+        self
+    }
+
+    /// Size of the parent-to-child region alone, for when requests and
+    /// replies differ a lot in size. Defaults to `shm_size`.
+    pub fn p2c_size(mut self, size: usize) -> Self {
+        self.p2c_size = size;
+        self
+    }
+
+    /// Size of the child-to-parent region alone. A size different from
+    /// `p2c_size` is passed to the child as `-c2p-size`, which Go and C
+    /// children don't accept.
+    pub fn c2p_size(mut self, size: usize) -> Self {
+        self.c2p_size = size;
+        self
+    }
+
+    /// `madvise` both SHM regions right after they are mapped.
+    pub fn advise(mut self, advice: Advice) -> Self {
+        self.advice = Some(advice);
+        self
+    }
+
+    /// Negotiate the protocol version with the child before `start` returns.
+    /// The child is passed `-handshake` and must enable it on its side; Go
+    /// and C children don't implement it, so it is off by default.
+    pub fn handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
+
+    /// Stamp every frame, in both directions, with the sender's
+    /// `frame::monotonic_ns` so `read_timed` can report when it was sent.
+    /// Turns on the handshake; each frame carries 8 more bytes.
+    pub fn timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled;
+        self.handshake |= enabled;
+        self
+    }
+
+    /// Byte order of lengths, ACKs and SHM header fields; see
+    /// `frame::Endianness`. Anything but `Native` turns on the handshake,
+    /// which fails `start` unless the child was set to the same order, and is
+    /// passed to the child as `-endianness little|big`.
+    pub fn endianness(mut self, order: Endianness) -> Self {
+        self.endianness = order;
+        self.handshake |= order != Endianness::Native;
+        self
+    }
+
+    /// Copy payloads of at least `bytes` into SHM with non-temporal stores,
+    /// so multi-megabyte frames don't evict the sender's cache.
+    pub fn nontemporal_threshold(mut self, bytes: usize) -> Self {
+        self.nontemporal_threshold = Some(bytes);
+        self
+    }
+
+    /// Space sends at least `interval` apart: a send that comes sooner
+    /// sleeps first, so a fast producer is capped at `1 / interval` messages
+    /// a second. The pacing is a `thread::sleep` measured with `Instant`,
+    /// so it is only as precise as the scheduler, not a real-time guarantee.
+    /// `send_priority` is not paced.
+    pub fn min_send_interval(mut self, interval: Duration) -> Self {
+        self.min_send_interval = Some(interval);
+        self
+    }
+
+    /// Before blocking on the ACK eventfd, check a word in the control block
+    /// up to `iterations` times for the child's ACK. A child that answers
+    /// within the budget then costs one eventfd read that returns at once,
+    /// with no sleep and wakeup in between; a slower one is waited for as
+    /// usual, after burning the spin. Go and C children never set the word,
+    /// so against them it only adds the spin. 0, the default, turns it off.
+    pub fn ack_spin(mut self, iterations: u32) -> Self {
+        self.ack_spin = iterations;
+        self
+    }
+
+    /// Extra arguments for the child, placed after the crate's own. The
+    /// crate reserves every flag `start` may pass: `-mode`, the six channel
+    /// `-fd-p2c-*` and `-fd-c2p-*`, `-fd-control`, `-fd-prio-send`,
+    /// `-fd-prio-ack`, `-fd-prio-shm`, `-fd-state`, `-state-addr`,
+    /// `-shm-size`, `-c2p-size`, `-handshake`, `-endianness` and
+    /// `-direction`, plus `-ring` for `RingShmParent`. `SocketParent` also
+    /// uses `-socket` and `-fd-socket`.
+    pub fn child_args(mut self, args: &[&str]) -> Self {
+        self.child_args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// How the child is started; see `SpawnMode`.
+    pub fn spawn_mode(mut self, mode: SpawnMode) -> Self {
+        self.spawn_mode = mode;
+        self
+    }
+
+    /// Give the child `parent_fd` as `child_fd`, e.g. a shared log file or
+    /// an open socket, alongside the channel fds. It is dup2'd into place
+    /// like them, so it survives the exec whatever its close-on-exec flag
+    /// is here; `parent_fd` must stay open until `start`. `start` fails with
+    /// `EfdStreamError::DuplicateFd` if `child_fd` is taken by the layout,
+    /// the control or priority channel, the shared state, or another
+    /// `inherit_fd`. Ignored by `start_with_socket`.
+    pub fn inherit_fd(mut self, parent_fd: RawFd, child_fd: RawFd) -> Self {
+        self.inherited_fds.push((parent_fd, child_fd));
+        self
+    }
+
+    /// Where the child finds its fds; see `FdLayout`. The control channel
+    /// and then the priority channel, if enabled, go just above the highest
+    /// one. `start` fails with
+    /// `EfdStreamError::DuplicateFd` on a layout that reuses a number.
+    pub fn fd_layout(mut self, layout: FdLayout) -> Self {
+        self.fd_layout = layout;
+        self
+    }
+
+    /// Puts the six channel fds at `base` through `base + 5`, for a child
+    /// that already has the default numbers taken; shorthand for
+    /// `fd_layout(FdLayout::from_base(base))`.
+    pub fn fd_base(self, base: RawFd) -> Self {
+        self.fd_layout(FdLayout::from_base(base))
+    }
+
+    /// Names the memfds `<prefix>_p2c`, `<prefix>_c2p` and so on, as they
+    /// show up in `/proc/<pid>/fd`, instead of `efdstream_shm_*`. `start`
+    /// fails with `InvalidInput` on a prefix containing a NUL byte.
+    pub fn memfd_prefix(mut self, prefix: &str) -> Self {
+        self.memfd_prefix = prefix.to_string();
+        self
+    }
+
+    /// Also passes every setting the child gets as an argument in the
+    /// environment, as `<prefix>_FD_P2C_SEND=3`, `<prefix>_SHM_SIZE=...` and
+    /// so on, one variable per `-flag` with a flag that takes no value set to
+    /// `1`. For children run through a wrapper that doesn't forward the
+    /// arguments; `ShmChild::from_env` reads them back.
+    pub fn env_prefix(mut self, prefix: &str) -> Self {
+        self.env_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Start the child in its own session and process group (`setsid`), so
+    /// a Ctrl-C or hangup aimed at the parent's terminal doesn't reach it.
+    /// Dropping the parent then kills the whole group, taking down anything
+    /// the child spawned as well.
+    pub fn new_session(mut self, enabled: bool) -> Self {
+        self.new_session = enabled;
+        self
+    }
+
+    /// What dropping the parent does with the child; see `DropBehavior`.
+    /// Defaults to killing it.
+    pub fn drop_behavior(mut self, behavior: DropBehavior) -> Self {
+        self.drop_behavior = behavior;
+        self
+    }
+
+    /// Add a seventh eventfd for `send_control`, passed to the child as
+    /// `-fd-control 9`. Go and C children don't accept it, so it is off by
+    /// default.
+    pub fn control_channel(mut self, enabled: bool) -> Self {
+        self.control_channel = enabled;
+        self
+    }
+
+    /// Add a second P2C channel for `send_priority`: its own doorbell and ACK
+    /// eventfds and a region of the P2C size, passed to the child as
+    /// `-fd-prio-send`, `-fd-prio-ack` and `-fd-prio-shm` after the control
+    /// fd. Go and C children don't accept them, so it is off by default.
+    pub fn priority_channel(mut self, enabled: bool) -> Self {
+        self.priority_channel = enabled;
+        self
+    }
+
+    /// Give the P2C direction two payload buffers, in one memfd of twice
+    /// the size, and alternate between them: a send returns once it has rung
+    /// the doorbell, and the next one fills the other buffer while the child
+    /// is still reading the last, waiting for that ACK only before it rings.
+    /// A NACK or handler error thus surfaces from the next send, `flush` or
+    /// `poll_acks`, and a send that reports it doesn't send its own message.
+    /// A send is counted in `metrics` when its ACK is read, so its round
+    /// trip runs until then.
+    /// Only a Rust child reads the second buffer, so it is off by default.
+    /// Not available with `encrypt`, `resize_shm`, `send_data_with_status`,
+    /// `AsyncShmParent` or `UringShmParent`.
+    pub fn double_buffer(mut self, enabled: bool) -> Self {
+        self.double_buffer = enabled;
+        self
+    }
+
+    /// Add a memfd of `size` bytes that both sides map read-write for as
+    /// long as the session lasts, passed to the child as `-fd-state` after
+    /// the priority fds. Nothing frames, ACKs or synchronizes it: see
+    /// `ShmParent::shared_state`. 0, the default, leaves it out, and Go and
+    /// C children don't accept it.
+    pub fn shared_state_size(mut self, size: usize) -> Self {
+        self.shared_state_size = size;
+        self
+    }
+
+    /// Map the shared state at `addr` (page-aligned) instead of where the
+    /// kernel chooses, in the parent and, through `-state-addr`, in the
+    /// child, so absolute pointers into it mean the same on both sides.
+    /// `start` fails with `AddrInUse` rather than replace anything already
+    /// mapped there. Needs `shared_state_size`; a `start_with_socket` child
+    /// is given the address with `ShmChild::shared_state_at`. The P2C and C2P
+    /// regions can't be placed: `resize_shm` and `reset` map them again
+    /// wherever the kernel chooses, so `ShmParent::shm_base` only reports
+    /// where they are now.
+    pub fn shared_state_at(mut self, addr: *mut std::ffi::c_void) -> Self {
+        self.shared_state_addr = Some(addr as usize);
+        self
+    }
+
+    /// Make the session one-way; see `Direction`. The child is passed
+    /// `-direction p2c|c2p` and only the `-fd-*` flags for that direction.
+    /// `start` fails with `EfdStreamError::WrongDirection` if another option
+    /// needs the missing direction: the handshake (and so timestamps,
+    /// encryption and a non-native byte order) and the priority channel need
+    /// P2C, the control channel needs the C2P region.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// `mlock` both regions so they are never swapped out. Fails `start` with
+    /// `EfdStreamError::MemlockLimit` when `RLIMIT_MEMLOCK` is too low. The
+    /// child locks its own mappings with `ShmChild::lock_memory`.
+    pub fn lock_memory(mut self, enabled: bool) -> Self {
+        self.lock_memory = enabled;
+        self
+    }
+
+    /// Seal every memfd with `F_SEAL_SHRINK` and `F_SEAL_SEAL` after sizing
+    /// it and before the child gets it. Neither side can then truncate a
+    /// region under the other's mapping, or add seals mid-session; growing
+    /// stays allowed for `resize_shm`. Children need nothing to match, and
+    /// a Rust child's `init` refuses regions sealed against the writes it
+    /// makes, whoever sealed them.
+    pub fn seal_memfds(mut self, enabled: bool) -> Self {
+        self.seal_memfds = enabled;
+        self
+    }
+
+    /// Overwrite both regions with zeros before they are unmapped, on drop
+    /// and for P2C on `shutdown_send`, so payloads don't outlive the session
+    /// in the memfd pages. The memory is shared, so this also clears what the
+    /// child sees. A child left running by `DropBehavior::Detach` is still
+    /// using the regions, so they are not zeroed on drop then.
+    pub fn zero_on_drop(mut self, enabled: bool) -> Self {
+        self.zero_on_drop = enabled;
+        self
+    }
+
+    /// How long `start` waits for the child's handshake answer before
+    /// killing it and failing with `EfdStreamError::ChildSetupFailed`;
+    /// `None` waits indefinitely. Defaults to 10s. Without the handshake
+    /// there is nothing to wait for and this has no effect.
+    pub fn startup_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// How many times `start` retries creating or mapping the SHM and
+    /// eventfds when that fails with ENOMEM or EAGAIN, which can clear up
+    /// on a loaded host. Other errors fail at once. Defaults to 3.
+    pub fn startup_retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// Pause before the first startup retry, doubled for each one after.
+    /// Defaults to 10ms.
+    pub fn startup_backoff(mut self, backoff: Duration) -> Self {
+        self.retry.backoff = backoff;
+        self
+    }
+
+    /// Seal every payload with XChaCha20-Poly1305 under `key`, which the child
+    /// must be given out of band. Turns on the handshake, where a key mismatch
+    /// fails `start` with `EfdStreamError::DecryptFailed`. Each frame carries
+    /// a 16-byte tag, so payloads can be that much smaller than `usable_size`.
+    #[cfg(feature = "crypto")]
+    pub fn encrypt(mut self, key: [u8; 32]) -> Self {
+        self.key = Some(key);
+        self.handshake = true;
+        self
+    }
+
+    pub fn build(self) -> ShmParent {
+        let mut parent = ShmParent::new(&self.child_path, self.p2c_size);
+        parent.c2p_size = usable_size_for(self.c2p_size);
+        parent.advice = self.advice;
+        parent.handshake = self.handshake;
+        parent.timestamps = self.timestamps;
+        parent.endianness = self.endianness;
+        parent.nontemporal_threshold = self.nontemporal_threshold;
+        parent.min_send_interval = self.min_send_interval;
+        parent.ack_spin = self.ack_spin;
+        parent.child_args = self.child_args;
+        parent.spawn_mode = self.spawn_mode;
+        parent.inherited_fds = self.inherited_fds;
+        parent.fd_layout = self.fd_layout;
+        parent.memfd_prefix = self.memfd_prefix;
+        parent.env_prefix = self.env_prefix;
+        parent.new_session = self.new_session;
+        parent.drop_behavior = self.drop_behavior;
+        parent.retry = self.retry;
+        parent.control_channel = self.control_channel;
+        parent.priority_channel = self.priority_channel;
+        parent.double_buffer = self.double_buffer;
+        parent.shared_state_size = self.shared_state_size;
+        parent.shared_state_addr = self.shared_state_addr;
+        parent.direction = self.direction;
+        parent.lock_memory = self.lock_memory;
+        parent.seal_memfds = self.seal_memfds;
+        parent.zero_on_drop = self.zero_on_drop;
+        parent.startup_timeout = self.startup_timeout;
+        #[cfg(feature = "crypto")]
+        {
+            parent.key = self.key;
+        }
+        parent
+    }
+}
+
 pub struct ShmParent {
     child_path: String,
-    shm_size: usize,
+    p2c_size: usize,
+    c2p_size: usize,
+    advice: Option<Advice>,
+    handshake: bool,
+    timestamps: bool,
+    pub(crate) endianness: Endianness,
+    nontemporal_threshold: Option<usize>,
+    min_send_interval: Option<Duration>,
+    ack_spin: u32,
+    pub(crate) child_args: Vec<String>,
+    spawn_mode: SpawnMode,
+    inherited_fds: Vec<(RawFd, RawFd)>,
+    fd_layout: FdLayout,
+    memfd_prefix: String,
+    env_prefix: Option<String>,
+    new_session: bool,
+    drop_behavior: DropBehavior,
+    retry: Retry,
+    control_channel: bool,
+    priority_channel: bool,
+    pub(crate) double_buffer: bool,
+    shared_state_size: usize,
+    shared_state_addr: Option<usize>,
+    pub(crate) direction: Direction,
+    lock_memory: bool,
+    seal_memfds: bool,
+    zero_on_drop: bool,
+    startup_timeout: Option<Duration>,
+    #[cfg(feature = "crypto")]
+    key: Option<[u8; 32]>,
+    // Set by the handshake when encryption was agreed on.
+    cipher: Option<FrameCipher>,
+
+    // Resources
+    pub(crate) file_p2c_send: Option<File>,
+    pub(crate) file_p2c_ack: Option<File>,
+    shm_p2c_file: Option<File>,
+    pub(crate) shm_p2c: SharedRegion,
+
+    pub(crate) file_c2p_send: Option<File>,
+    pub(crate) file_c2p_ack: Option<File>,
+    shm_c2p_file: Option<File>,
+    pub(crate) shm_c2p: SharedRegion,
+
+    file_control: Option<File>,
+
+    // The priority channel keeps the size it was created with.
+    file_priority_send: Option<File>,
+    file_priority_ack: Option<File>,
+    shm_priority_file: Option<File>,
+    shm_priority: SharedRegion,
+    priority_size: usize,
+
+    shm_state_file: Option<File>,
+    shm_state: SharedRegion,
+
+    child: Option<ChildProcess>,
+    // Started with `spawn_thread_child`, whose `listen` ends on EOF.
+    thread_child: bool,
+    // The connection `bind` accepted, held open so the child sees it close
+    // when this parent goes away.
+    rendezvous: Option<UnixStream>,
+    // Readable once the child exits, so blocking waits can notice.
+    pub(crate) pidfd: Option<OwnedFd>,
+    send_shut_down: bool,
+    // The child called `ShmChild::shutdown_send`.
+    recv_shut_down: bool,
+    // A deadline expired before the child ACKed the last send, or
+    // `double_buffer` didn't wait for it.
+    pub(crate) ack_pending: bool,
+    // With `double_buffer`: the next frame goes in the second half, and the
+    // payload length and ring time of the send awaiting its ACK, counted in
+    // the metrics once it comes.
+    second_buffer: bool,
+    unacked: Option<(usize, Instant)>,
+    // The C2P doorbell `peek_frame` took; the frame is still unACKed.
+    peeked: Option<u64>,
+    // When the last paced send rang the doorbell.
+    pub(crate) last_send: Option<Instant>,
+    last_child_error: Option<(u32, String)>,
+    pub(crate) metrics: Metrics,
+}
+
+impl ShmParent {
+    pub fn new(child_path: &str, shm_size: usize) -> Self {
+        Self {
+            child_path: child_path.to_string(),
+            p2c_size: usable_size_for(shm_size),
+            c2p_size: usable_size_for(shm_size),
+            advice: None,
+            handshake: false,
+            timestamps: false,
+            endianness: Endianness::Native,
+            nontemporal_threshold: None,
+            min_send_interval: None,
+            ack_spin: 0,
+            child_args: Vec::new(),
+            spawn_mode: SpawnMode::ForkExec,
+            inherited_fds: Vec::new(),
+            fd_layout: FdLayout::default(),
+            memfd_prefix: "efdstream_shm".to_string(),
+            env_prefix: None,
+            new_session: false,
+            drop_behavior: DropBehavior::Kill,
+            retry: Retry::default(),
+            control_channel: false,
+            priority_channel: false,
+            double_buffer: false,
+            shared_state_size: 0,
+            shared_state_addr: None,
+            direction: Direction::Bidirectional,
+            lock_memory: false,
+            seal_memfds: false,
+            zero_on_drop: false,
+            startup_timeout: Some(DEFAULT_STARTUP_TIMEOUT),
+            #[cfg(feature = "crypto")]
+            key: None,
+            cipher: None,
+            file_p2c_send: None, file_p2c_ack: None, shm_p2c_file: None, shm_p2c: SharedRegion::unmapped(),
+            file_c2p_send: None, file_c2p_ack: None, shm_c2p_file: None, shm_c2p: SharedRegion::unmapped(),
+            file_control: None,
+            file_priority_send: None, file_priority_ack: None, shm_priority_file: None,
+            shm_priority: SharedRegion::unmapped(), priority_size: 0,
+            shm_state_file: None, shm_state: SharedRegion::unmapped(),
+            child: None,
+            thread_child: false,
+            rendezvous: None,
+            pidfd: None,
+            send_shut_down: false,
+            recv_shut_down: false,
+            ack_pending: false,
+            second_buffer: false,
+            unacked: None,
+            peeked: None,
+            last_send: None,
+            last_child_error: None,
+            metrics: Metrics::default(),
+        }
+    }
+
+    pub fn builder(child_path: &str) -> ShmParentBuilder {
+        ShmParentBuilder::new(child_path)
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        self.check_direction()?;
+        self.allocate()?;
+        self.spawn(&[])?;
+
+        if self.handshake {
+            self.exchange_hello().map_err(|e| self.setup_failed(e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `start`, but instead of spawning a child, passes the six
+    /// descriptors over `socket` (SCM_RIGHTS) to an already-running process,
+    /// which picks them up with `ShmChild::from_socket`.
+    pub fn start_with_socket(&mut self, socket: &UnixStream) -> Result<()> {
+        self.check_direction()?;
+        self.allocate()?;
+
+        let raw = |file: &Option<File>| file.as_ref().unwrap().as_raw_fd();
+        let mut fds = self.channel_files().iter().filter_map(|file| file.as_ref().map(|f| f.as_raw_fd())).collect::<Vec<_>>();
+        let mut flags = if self.handshake { fdpass::FLAG_HANDSHAKE } else { 0 };
+        match self.direction {
+            Direction::Bidirectional => {}
+            Direction::ParentToChild => flags |= fdpass::FLAG_P2C_ONLY,
+            Direction::ChildToParent => flags |= fdpass::FLAG_C2P_ONLY,
+        }
+        if self.file_control.is_some() {
+            fds.push(raw(&self.file_control));
+            flags |= fdpass::FLAG_CONTROL;
+        }
+        if self.file_priority_send.is_some() {
+            fds.extend([raw(&self.file_priority_send), raw(&self.file_priority_ack), raw(&self.shm_priority_file)]);
+            flags |= fdpass::FLAG_PRIORITY;
+        }
+        if self.shm_state_file.is_some() {
+            fds.push(raw(&self.shm_state_file));
+            flags |= fdpass::FLAG_STATE;
+        }
+        send_fds(socket, &fds, self.p2c_size, self.c2p_size, flags)?;
+
+        if self.handshake {
+            self.exchange_hello().map_err(|e| self.setup_failed(e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the session's eventfds and memfds like `start`, but spawns
+    /// nothing: the returned set holds copies of the six descriptors for a
+    /// child the caller starts itself, e.g. by `dup2`ing them into place and
+    /// passing the numbers as the `-fd-*` arguments `ShmChild::from_env_args`
+    /// reads. The copies are close-on-exec, and the parent keeps its own.
+    /// Call `attach` once the child is running. Needs a bidirectional session
+    /// without the control or priority channel or shared state, which
+    /// `ShmFdSet` has no room for.
+    pub fn create_fds(&mut self) -> Result<ShmFdSet> {
+        if self.direction != Direction::Bidirectional || self.control_channel || self.priority_channel
+            || self.shared_state_size != 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "create_fds only carries the six channel fds").into());
+        }
+        self.allocate()?;
+        let copy = |file: &Option<File>| -> Result<OwnedFd> {
+            Ok(file.as_ref().ok_or(EfdStreamError::NotStarted)?.as_fd().try_clone_to_owned()?)
+        };
+        Ok(ShmFdSet {
+            p2c_send: copy(&self.file_p2c_send)?,
+            p2c_ack: copy(&self.file_p2c_ack)?,
+            p2c_shm: copy(&self.shm_p2c_file)?,
+            c2p_send: copy(&self.file_c2p_send)?,
+            c2p_ack: copy(&self.file_c2p_ack)?,
+            c2p_shm: copy(&self.shm_c2p_file)?,
+            p2c_size: self.p2c_size,
+            c2p_size: self.c2p_size,
+        })
+    }
+
+    /// Finishes `create_fds` once the caller's child has its descriptors:
+    /// runs the handshake, if enabled, and does nothing otherwise. The parent
+    /// never kills or waits for a child it didn't spawn, whatever
+    /// `drop_behavior` says; `shutdown_send` tells it to stop.
+    pub fn attach(&mut self) -> Result<()> {
+        if self.shm_p2c.is_null() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        if self.handshake {
+            self.exchange_hello().map_err(|e| self.setup_failed(e))?;
+        }
+        Ok(())
+    }
+
+    /// Like `start`, but the child is a `ShmChild` on a new thread of this
+    /// process, running `listen(handler)` on the same eventfds and memfds.
+    /// They reach it the way `start_with_socket` passes them, with no exec.
+    /// The thread returns what `listen` does. It ends when the parent calls
+    /// `shutdown_send`, which dropping the parent also does.
+    pub fn spawn_thread_child<F>(&mut self, handler: F) -> Result<JoinHandle<Result<()>>>
+    where
+        F: Fn(&[u8]) + Send + 'static,
+    {
+        let (parent_end, child_end) = UnixStream::pair()?;
+        let thread = std::thread::spawn(move || ShmChild::from_socket(&child_end)?.listen(handler));
+        self.start_with_socket(&parent_end)?;
+        self.thread_child = true;
+        Ok(thread)
+    }
+
+    /// `start_with_socket` for a child launched on its own, e.g. by a
+    /// container platform: listens on a Unix socket at `path`, blocks until
+    /// one `ShmChild::connect` arrives, and passes it the descriptors. A
+    /// socket left at `path` by a process that has gone is replaced; one
+    /// still being listened on, or a file that isn't a socket, fails with
+    /// `AddrInUse`. The path is removed once the child has connected.
+    pub fn bind(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let listener = bind_replacing_stale(path)?;
+        let accepted = listener.accept();
+        let _ = std::fs::remove_file(path);
+        let (socket, _) = accepted?;
+        self.start_with_socket(&socket)?;
+        self.rendezvous = Some(socket);
+        Ok(())
+    }
+
+    /// Checks without starting anything that this environment allows what
+    /// `start` needs: it creates, maps and closes a small memfd (sealed, with
+    /// `seal_memfds`) and an eventfd, and looks up the child binary the way
+    /// the spawn does. Fails with `EfdStreamError::Unavailable` naming the
+    /// first thing that didn't work, e.g. a memfd_create that a container's
+    /// seccomp profile blocks. The child check doesn't apply to
+    /// `start_with_socket`.
+    pub fn preflight(&self) -> Result<()> {
+        let unavailable = |capability: &'static str| move |e: Errno| EfdStreamError::Unavailable {
+            capability, error: e.into(),
+        };
+        let flags = if self.seal_memfds { MFdFlags::MFD_CLOEXEC | MFdFlags::MFD_ALLOW_SEALING } else { MFdFlags::MFD_CLOEXEC };
+        let memfd = memfd_create(c"efdstream_preflight", flags).map_err(unavailable("memfd_create"))?;
+        ftruncate(&memfd, 4096).map_err(unavailable("ftruncate"))?;
+        if self.seal_memfds {
+            fcntl(&memfd, FcntlArg::F_ADD_SEALS(SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_SEAL)).map_err(unavailable("memfd sealing"))?;
+        }
+        SharedRegion::map(&memfd, 4096, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+            .map_err(|error| EfdStreamError::Unavailable { capability: "mmap", error })?;
+        EventFd::from_value_and_flags(0, EfdFlags::EFD_CLOEXEC).map_err(unavailable("eventfd"))?;
+        check_executable(&self.child_path)
+            .map_err(|error| EfdStreamError::Unavailable { capability: "the child binary", error })?;
+        Ok(())
+    }
+
+    // Options that need the direction a simplex session leaves out.
+    fn check_direction(&self) -> Result<()> {
+        if self.handshake || self.priority_channel || self.double_buffer {
+            self.direction.require(Direction::ParentToChild)?;
+        }
+        if self.control_channel {
+            self.direction.require(Direction::ChildToParent)?;
+        }
+        Ok(())
+    }
+
+    // The six channel fds in `-fd-*` order; a simplex session has only
+    // three of them.
+    fn channel_files(&self) -> [&Option<File>; 6] {
+        [&self.file_p2c_send, &self.file_p2c_ack, &self.shm_p2c_file,
+         &self.file_c2p_send, &self.file_c2p_ack, &self.shm_c2p_file]
+    }
+
+    // Creates the eventfds and memfds and maps both regions, without starting
+    // the child. Split from `spawn` so alternate framings (the ring mode) can
+    // lay out the regions before the child maps them.
+    pub(crate) fn allocate(&mut self) -> Result<()> {
+        if self.shared_state_addr.is_some() && self.shared_state_size == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "shared_state_at needs shared_state_size").into());
+        }
+        // A frame is sealed before the send waits its turn, so one that then
+        // fails would use up a nonce the child never sees.
+        #[cfg(feature = "crypto")]
+        if self.double_buffer && self.key.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "double_buffer with encrypt").into());
+        }
+        // Each step is retried on ENOMEM/EAGAIN, see `startup_retries`.
+        let retry = self.retry;
+        // Everything is close-on-exec; `spawn` dup2s just the child's fds
+        // into place, which clears the flag on the copies.
+        let eventfd = || retry.run(|| EventFd::from_value_and_flags(0, EfdFlags::EFD_CLOEXEC).map_err(std::io::Error::from));
+        // Sized, and sealed for `seal_memfds` before the child can map it.
+        let seal = self.seal_memfds;
+        if self.memfd_prefix.contains('\0') {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "NUL in memfd_prefix").into());
+        }
+        let prefix = &self.memfd_prefix;
+        let memfd = |name: &str, len: usize| -> std::io::Result<OwnedFd> {
+            let name = CString::new(format!("{}_{}", prefix, name)).unwrap();
+            let flags = if seal { MFdFlags::MFD_CLOEXEC | MFdFlags::MFD_ALLOW_SEALING } else { MFdFlags::MFD_CLOEXEC };
+            let memfd = retry.run(|| memfd_create(name.as_c_str(), flags).map_err(std::io::Error::from))?;
+            retry.run(|| ftruncate(&memfd, len as i64).map_err(std::io::Error::from))?;
+            if seal {
+                fcntl(&memfd, FcntlArg::F_ADD_SEALS(SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_SEAL)).map_err(std::io::Error::from)?;
+            }
+            Ok(memfd)
+        };
+
+        // 1. Create P2C resources
+        if self.direction.has_p2c() {
+            let efd_p2c_send = eventfd()?;
+            let efd_p2c_ack = eventfd()?;
+            let p2c_len = if self.double_buffer { 2 * self.p2c_size } else { self.p2c_size };
+            let memfd_p2c = memfd("p2c", p2c_len)?;
+            self.shm_p2c = retry.run(|| SharedRegion::map(&memfd_p2c, p2c_len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE))?;
+            advise_region(self.shm_p2c.as_ptr(), p2c_len, self.advice)?;
+
+            self.file_p2c_send = Some(File::from(OwnedFd::from(efd_p2c_send)));
+            self.file_p2c_ack = Some(File::from(OwnedFd::from(efd_p2c_ack)));
+            self.shm_p2c_file = Some(File::from(memfd_p2c));
+        }
+
+        // 2. Create C2P resources
+        if self.direction.has_c2p() {
+            let efd_c2p_send = eventfd()?;
+            let efd_c2p_ack = eventfd()?;
+            let memfd_c2p = memfd("c2p", c2p_map_len(self.c2p_size))?;
+            self.shm_c2p = retry.run(|| SharedRegion::map(&memfd_c2p, c2p_map_len(self.c2p_size),
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE))?;
+            advise_region(self.shm_c2p.as_ptr(), self.c2p_size, self.advice)?;
+
+            self.file_c2p_send = Some(File::from(OwnedFd::from(efd_c2p_send)));
+            self.file_c2p_ack = Some(File::from(OwnedFd::from(efd_c2p_ack)));
+            self.shm_c2p_file = Some(File::from(memfd_c2p));
+        }
+        // Unmapped regions are skipped.
+        if self.lock_memory {
+            lock_regions(&[&self.shm_p2c, &self.shm_c2p])?;
+        }
+
+        if self.control_channel {
+            self.file_control = Some(File::from(OwnedFd::from(eventfd()?)));
+        }
+
+        if self.priority_channel {
+            let efd_send = eventfd()?;
+            let efd_ack = eventfd()?;
+            let memfd = memfd("priority", self.p2c_size)?;
+            self.shm_priority = retry.run(|| SharedRegion::map(&memfd, self.p2c_size, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE))?;
+            if self.lock_memory {
+                lock_regions(&[&self.shm_priority])?;
+            }
+            self.priority_size = self.p2c_size;
+            self.file_priority_send = Some(File::from(OwnedFd::from(efd_send)));
+            self.file_priority_ack = Some(File::from(OwnedFd::from(efd_ack)));
+            self.shm_priority_file = Some(File::from(memfd));
+        }
+
+        if self.shared_state_size > 0 {
+            let memfd = memfd("state", self.shared_state_size)?;
+            self.shm_state = retry.run(|| SharedRegion::map_at(&memfd, self.shared_state_size,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE, self.shared_state_addr))?;
+            if self.lock_memory {
+                lock_regions(&[&self.shm_state])?;
+            }
+            self.shm_state_file = Some(File::from(memfd));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn spawn(&mut self, extra_args: &[&str]) -> Result<()> {
+        self.fd_layout.validate()?;
+
+        // Start Child
+        let mut args: Vec<String> = vec!["-mode".into(), "child".into()];
+        // We map the FDs to the layout's numbers (3..8 by default) in the child process.
+        // A simplex session leaves the other direction's numbers unused.
+        let layout = self.fd_layout;
+        let mut fds = Vec::new();
+        for ((name, target), file) in layout.targets().into_iter().zip(self.channel_files()) {
+            let Some(file) = file else { continue };
+            args.push(format!("-fd-{}", name));
+            args.push(target.to_string());
+            fds.push((file.as_raw_fd(), target));
+        }
+        if fds.is_empty() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        args.push("-shm-size".into());
+        args.push(self.p2c_size.to_string());
+        if self.c2p_size != self.p2c_size {
+            args.push("-c2p-size".into());
+            args.push(self.c2p_size.to_string());
+        }
+        if self.handshake {
+            args.push("-handshake".into());
+        }
+        match self.endianness {
+            Endianness::Native => {}
+            Endianness::Little => args.extend(["-endianness".into(), "little".into()]),
+            Endianness::Big => args.extend(["-endianness".into(), "big".into()]),
+        }
+        if let Some(direction) = self.direction.arg() {
+            args.extend(["-direction".into(), direction.into()]);
+        }
+        // Optional channels go above the layout, in a fixed order.
+        let mut extra = layout.next_free();
+        if let Some(control) = &self.file_control {
+            args.push("-fd-control".into());
+            args.push(extra.to_string());
+            fds.push((control.as_raw_fd(), extra));
+            extra += 1;
+        }
+        if let (Some(send), Some(ack), Some(shm)) = (&self.file_priority_send, &self.file_priority_ack, &self.shm_priority_file) {
+            for (name, file) in [("prio-send", send), ("prio-ack", ack), ("prio-shm", shm)] {
+                args.push(format!("-fd-{}", name));
+                args.push(extra.to_string());
+                fds.push((file.as_raw_fd(), extra));
+                extra += 1;
+            }
+        }
+        if let Some(state) = &self.shm_state_file {
+            args.push("-fd-state".into());
+            args.push(extra.to_string());
+            fds.push((state.as_raw_fd(), extra));
+            if let Some(addr) = self.shared_state_addr {
+                args.extend(["-state-addr".into(), addr.to_string()]);
+            }
+        }
+        fds.extend(self.inherited_fds.iter().copied());
+        check_targets(&fds.iter().map(|&(_, target)| target).collect::<Vec<_>>())?;
+        args.extend(extra_args.iter().map(|arg| arg.to_string()));
+        // Everything after `-mode child` again, for `env_prefix`.
+        let env = match &self.env_prefix {
+            None => Vec::new(),
+            Some(prefix) => env_vars(prefix, &args[2..]),
+        };
+        args.extend(self.child_args.iter().cloned());
+
+        let child = spawn_child(self.spawn_mode, self.new_session, &self.child_path, &args, &env, &fds)?;
+        self.pidfd = open_pidfd(child.id());
+        self.child = Some(child);
+
+        Ok(())
+    }
+
+    /// Largest payload `send_to_peer` accepts: the requested P2C size rounded
+    /// up so the mapping fills whole pages.
+    pub fn usable_size(&self) -> usize {
+        self.p2c_size
+    }
+
+    /// Largest payload the child can send back, rounded up the same way.
+    pub fn c2p_usable_size(&self) -> usize {
+        self.c2p_size
+    }
+
+    /// What the session is actually running with, for logging at startup
+    /// or working out why it behaves differently than configured. Before
+    /// `start` it shows the configuration as resolved so far.
+    pub fn negotiated(&self) -> SessionInfo {
+        SessionInfo {
+            p2c_size: self.p2c_size,
+            c2p_size: self.c2p_size,
+            direction: self.direction,
+            handshake: self.handshake,
+            endianness: self.endianness,
+            timestamps: self.timestamps,
+            encrypted: self.cipher.is_some(),
+            advice: self.advice,
+            huge_pages: self.advice == Some(Advice::HugePage) && shmem_huge_pages_allowed(),
+            memory_locked: self.lock_memory,
+            control_channel: self.control_channel,
+            priority_channel: self.priority_channel,
+            double_buffer: self.double_buffer,
+            shared_state_size: self.shared_state_size,
+            ack_spin: self.ack_spin,
+        }
+    }
+
+    /// A word shared with the child for flags or counters that don't need a
+    /// message. It lives outside the payload area, so `send_to_peer` never
+    /// touches it. `None` before `start`.
+    pub fn control_atomic(&self) -> Option<&AtomicU64> {
+        if self.shm_c2p.is_null() {
+            return None;
+        }
+        Some(unsafe { control_at(self.shm_c2p.as_ptr(), self.c2p_size) })
+    }
+
+    /// The region added by `ShmParentBuilder::shared_state_size`, the same
+    /// bytes the child sees in `ShmChild::shared_state`. Either side may
+    /// write it at any time, so agreeing on who writes what, and when, is
+    /// up to the caller, e.g. by publishing changes through
+    /// `control_atomic`. `None` before `start` or without the option.
+    /// `resize_shm` and `reset` leave it alone, and it stays at the base
+    /// address `as_ptr` gives, e.g. for `ShmParentBuilder::shared_state_at`.
+    pub fn shared_state(&self) -> Option<&[u8]> {
+        if self.shm_state.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(self.shm_state.as_ptr(), self.shm_state.len()) })
+    }
+
+    pub fn shared_state_mut(&mut self) -> Option<&mut [u8]> {
+        if self.shm_state.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts_mut(self.shm_state.as_ptr(), self.shm_state.len()) })
+    }
+
+    /// Where the P2C (`Direction::ParentToChild`) or C2P
+    /// (`Direction::ChildToParent`) region is mapped in this process, e.g.
+    /// for locating it among other mappings. `None` before `start`, for a
+    /// direction the session doesn't carry and for
+    /// `Direction::Bidirectional`. `resize_shm` and `reset` move it.
+    pub fn shm_base(&self, dir: Direction) -> Option<*const u8> {
+        let region = match dir {
+            Direction::ParentToChild => &self.shm_p2c,
+            Direction::ChildToParent => &self.shm_c2p,
+            Direction::Bidirectional => return None,
+        };
+        (!region.is_null()).then(|| region.as_ptr() as *const u8)
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Process id of the spawned child. `None` before `start`, and for a
+    /// peer started with `start_with_socket`.
+    pub fn child_id(&self) -> Option<u32> {
+        self.child.as_ref().map(|child| child.id())
+    }
+
+    /// How the spawned child exited, reaping it if it just has: `None`
+    /// while it is still running, before `start`, and for a peer started
+    /// with `start_with_socket`. After a `ChildDied` error it gives the same
+    /// status, for logging or deciding whether to restart.
+    pub fn child_status(&mut self) -> Result<Option<std::process::ExitStatus>> {
+        match self.child.as_mut() {
+            Some(child) => Ok(child.try_wait()?),
+            None => Ok(None),
+        }
+    }
+
+    /// Registers `efdstream_messages_total`, `efdstream_bytes_total` and
+    /// `efdstream_rtt_seconds` with `registry`. They are updated by every
+    /// `send_to_peer`/`recv_from_peer` whether or not they are registered.
+    #[cfg(feature = "prometheus")]
+    pub fn register_metrics(&self, registry: &prometheus::Registry) -> prometheus::Result<()> {
+        self.metrics.register(registry)
+    }
+
+    /// The P2C doorbell eventfd the parent writes payload lengths to, for
+    /// registering with `epoll` or another reactor. `None` before `start`
+    /// and after `shutdown_send`, as are the other P2C accessors.
+    pub fn p2c_send_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file_p2c_send.as_ref().map(|f| f.as_fd())
+    }
+
+    /// The P2C eventfd the child ACKs on.
+    pub fn p2c_ack_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file_p2c_ack.as_ref().map(|f| f.as_fd())
+    }
+
+    /// The C2P doorbell eventfd; readable when the child has sent a message.
+    /// It stays readable until the message is read, and the child can't
+    /// send another before then, so an edge-triggered reactor misses
+    /// nothing as long as each wakeup reads with `try_read_data` until it
+    /// returns `None`.
+    pub fn c2p_send_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file_c2p_send.as_ref().map(|f| f.as_fd())
+    }
+
+    /// The C2P eventfd the parent ACKs on.
+    pub fn c2p_ack_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file_c2p_ack.as_ref().map(|f| f.as_fd())
+    }
+
+    /// The control eventfd `send_control` rings. `None` unless built with
+    /// `control_channel`.
+    pub fn control_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file_control.as_ref().map(|f| f.as_fd())
+    }
+
+    /// Raises `bits` for the child and wakes it, even while it is blocked in
+    /// `listen` or `recv_from_peer` with no data on the way; that call then fails
+    /// with `EfdStreamError::Control { bits }`. The meaning of the bits is up
+    /// to the application. Bits raised again before the child wakes are
+    /// merged. Needs `ShmParentBuilder::control_channel`.
+    pub fn send_control(&mut self, bits: u64) -> Result<()> {
+        if bits == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "No control bits to send").into());
+        }
+        let Some(control) = &self.file_control else {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "No control channel").into());
+        };
+        unsafe { control_events_at(self.shm_c2p.as_ptr(), self.c2p_size) }.fetch_or(bits, Ordering::SeqCst);
+        eventfd_write(control.as_fd(), 1)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "mio")]
+    fn c2p_doorbell(&self) -> std::io::Result<RawFd> {
+        self.c2p_send_fd().map(|fd| fd.as_raw_fd())
+            .ok_or_else(|| EfdStreamError::NotStarted.into())
+    }
+
+    fn exchange_hello(&mut self) -> Result<()> {
+        if self.p2c_size < handshake::RECORD_LEN {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "SHM too small for handshake").into());
+        }
+        #[cfg(feature = "crypto")]
+        let mut flags = if self.key.is_some() { handshake::FLAG_ENCRYPTED } else { 0 };
+        #[cfg(not(feature = "crypto"))]
+        let mut flags = 0;
+        if self.timestamps {
+            flags |= handshake::FLAG_TIMESTAMPS;
+        }
+        flags |= handshake::order_flag(self.endianness);
+
+        let offer = Hello::offer(flags, handshake::CODEC_NONE);
+        let shm = unsafe { slice::from_raw_parts_mut(self.shm_p2c.as_ptr(), self.p2c_size) };
+        offer.encode(&mut shm[..handshake::RECORD_LEN]);
+
+        #[cfg(feature = "crypto")]
+        let offer_len = match &self.key {
+            Some(key) => {
+                let (cipher, len) = FrameCipher::offer(key, shm)?;
+                self.cipher = Some(cipher);
+                len
+            }
+            None => handshake::RECORD_LEN,
+        };
+        #[cfg(not(feature = "crypto"))]
+        let offer_len = handshake::RECORD_LEN;
+
+        if let Some(file_send) = &self.file_p2c_send {
+            eventfd_write(file_send.as_fd(), offer_len as u64)?;
+        }
+        let deadline = self.startup_timeout.map(|timeout| Instant::now() + timeout);
+        let reply = match self.file_p2c_ack.as_ref().map(|f| f.as_raw_fd()) {
+            Some(ack) => Hello::decode_answer(self.read_eventfd(ack, deadline)?),
+            None => None,
+        };
+
+        offer.check_answer(reply)
+    }
+
+    /// Tells the child nothing more will be sent, like a TCP half-close: its
+    /// `listen` returns, while `recv_from_peer` keeps receiving what it sends.
+    /// Closes the P2C eventfds and unmaps the P2C region; `send_to_peer` fails
+    /// afterwards. Go and C children log the sentinel as an oversized frame.
+    pub fn shutdown_send(&mut self) -> Result<()> {
+        self.direction.require(Direction::ParentToChild)?;
+        if self.send_shut_down {
+            return Ok(());
+        }
+        // A double-buffered send rings before its ACK; the sentinel mustn't
+        // add up with a doorbell the child hasn't taken yet.
+        let flushed = if self.double_buffer { self.flush() } else { Ok(()) };
+        let Some(file_send) = self.file_p2c_send.take() else {
+            return Err(EfdStreamError::NotStarted);
+        };
+        // The child does not ACK the sentinel.
+        eventfd_write(file_send.as_fd(), self.endianness.to_wire(EOF_DOORBELL))?;
+        self.send_shut_down = true;
+
+        self.file_p2c_ack = None;
+        if self.zero_on_drop {
+            self.shm_p2c.zero();
+            self.shm_priority.zero();
+        }
+        self.shm_p2c.unmap();
+        self.shm_p2c_file = None;
+        self.file_priority_send = None;
+        self.file_priority_ack = None;
+        self.shm_priority.unmap();
+        self.shm_priority_file = None;
+        flushed
+    }
+
+    /// Grows each SHM region that is smaller than `new_size` to it, with the
+    /// child remapping in step; if neither is, this does nothing. Needs a
+    /// `Bidirectional` session and the handshake, since only a Rust child
+    /// that takes part in it understands the resize doorbell, and the child
+    /// must be in `listen`.
+    ///
+    /// The memfds are only ever grown, so both sides' old mappings stay valid
+    /// until each switches over: the child remaps before it ACKs, and the
+    /// parent after the ACK. Payload slices and `control_atomic` references
+    /// taken before the call point into the old mappings and are invalid
+    /// afterwards. The control word is carried over, but a child update racing
+    /// the resize may be lost. A `ShmChild` that doesn't `listen` (e.g. a
+    /// separate sender) keeps its old size.
+    pub fn resize_shm(&mut self, new_size: usize) -> Result<()> {
+        let new_size = usable_size_for(new_size);
+        if new_size <= self.p2c_size.min(self.c2p_size) {
+            return Ok(());
+        }
+        self.direction.require(Direction::Bidirectional)?;
+        if !self.handshake {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Resizing needs the handshake").into());
+        }
+        if self.double_buffer {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Resizing a double-buffered session").into());
+        }
+        if self.send_shut_down {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Send side shut down").into());
+        }
+        let (Some(file_send), Some(file_ack), Some(memfd_p2c), Some(memfd_c2p)) =
+            (&self.file_p2c_send, &self.file_p2c_ack, &self.shm_p2c_file, &self.shm_c2p_file) else {
+            return Err(EfdStreamError::NotStarted);
+        };
+        let (send, ack) = (file_send.as_raw_fd(), file_ack.as_raw_fd());
+        let (memfd_p2c, memfd_c2p) =
+            unsafe { (BorrowedFd::borrow_raw(memfd_p2c.as_raw_fd()), BorrowedFd::borrow_raw(memfd_c2p.as_raw_fd())) };
+
+        // The child may still be reading the previous payload.
+        if self.ack_pending {
+            self.read_eventfd(ack, None)?;
+            self.ack_pending = false;
+        }
+
+        let (p2c_size, c2p_size) = (self.p2c_size.max(new_size), self.c2p_size.max(new_size));
+        ftruncate(memfd_p2c, p2c_size as i64)?;
+        ftruncate(memfd_c2p, c2p_map_len(c2p_size) as i64)?;
+        let shm_p2c = SharedRegion::map(memfd_p2c, p2c_size, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)?;
+        advise_region(shm_p2c.as_ptr(), p2c_size, self.advice)?;
+        let shm_c2p = SharedRegion::map(memfd_c2p, c2p_map_len(c2p_size), ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)?;
+        advise_region(shm_c2p.as_ptr(), c2p_size, self.advice)?;
+        if self.lock_memory {
+            lock_regions(&[&shm_p2c, &shm_c2p])?;
+        }
+
+        // The control block moves with the end of the payload area.
+        let control = self.control_atomic().map_or(0, |control| control.load(Ordering::SeqCst));
+        unsafe { control_at(shm_c2p.as_ptr(), c2p_size) }.store(control, Ordering::SeqCst);
+        let paused = unsafe { pause_at(self.shm_c2p.as_ptr(), self.c2p_size) }.load(Ordering::SeqCst);
+        unsafe { pause_at(shm_c2p.as_ptr(), c2p_size) }.store(paused, Ordering::SeqCst);
+
+        let announce = unsafe { slice::from_raw_parts_mut(shm_p2c.as_ptr(), DOORBELL_LEN) };
+        announce.copy_from_slice(&self.endianness.encode(new_size as u64));
+        eventfd_write(unsafe { BorrowedFd::borrow_raw(send) }, self.endianness.to_wire(RESIZE_DOORBELL))?;
+        if self.endianness.from_wire(self.read_eventfd(ack, None)?) != 1 {
+            return Err(std::io::Error::other("Child failed to remap SHM").into());
+        }
+
+        self.shm_p2c = shm_p2c;
+        self.shm_c2p = shm_c2p;
+        self.p2c_size = p2c_size;
+        self.c2p_size = c2p_size;
+        Ok(())
+    }
+
+    /// Brings a desynced session back to a known state without respawning
+    /// the child: empties all four eventfds, zeroes the control block, and
+    /// has the child answer a fresh handshake, which also starts a new
+    /// encryption session. Needs the handshake and a `Bidirectional`
+    /// session. A child stuck in `send_to_peer` waiting for an ACK is
+    /// released; it then has to get back to `listen` or `recv_from_peer` to
+    /// take the reset doorbell, or this fails once `startup_timeout` passes.
+    /// Messages in flight either way are lost.
+    pub fn reset(&mut self) -> Result<()> {
+        self.direction.require(Direction::Bidirectional)?;
+        if !self.handshake {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Resetting needs the handshake").into());
+        }
+        if self.send_shut_down {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Send side shut down").into());
+        }
+        let (Some(p2c_send), Some(p2c_ack), Some(c2p_send), Some(c2p_ack)) =
+            (&self.file_p2c_send, &self.file_p2c_ack, &self.file_c2p_send, &self.file_c2p_ack) else {
+            return Err(EfdStreamError::NotStarted);
+        };
+        for fd in [p2c_send, p2c_ack, c2p_send, c2p_ack].into_iter().chain(&self.file_priority_send).chain(&self.file_priority_ack) {
+            drain_eventfd(fd.as_fd())?;
+        }
+        // Frees a child waiting on the ACK for a reply nobody read. A child
+        // that isn't drops this count when it takes the reset.
+        eventfd_write(c2p_ack.as_fd(), self.endianness.to_wire(1))?;
+        unsafe { clear_control_block(self.shm_c2p.as_ptr(), self.c2p_size) };
+        self.ack_pending = false;
+        self.unacked = None;
+        self.peeked = None;
+
+        // The child ACKs the reset before it waits for the offer, so the two
+        // doorbells can't add up on the eventfd. Anything it sent before
+        // taking the reset is on `c2p_send` by then.
+        let (ack, c2p_send) = (p2c_ack.as_raw_fd(), c2p_send.as_raw_fd());
+        eventfd_write(p2c_send.as_fd(), self.endianness.to_wire(RESET_DOORBELL))?;
+        let deadline = self.startup_timeout.map(|timeout| Instant::now() + timeout);
+        self.read_eventfd(ack, deadline)?;
+        drain_eventfd(unsafe { BorrowedFd::borrow_raw(c2p_send) })?;
+        self.exchange_hello()
+    }
+
+    /// Sends `data` to the child and waits for its ACK. Named for the
+    /// direction as seen from this end, so it reads the same as
+    /// `ShmChild::send_to_peer`.
+    pub fn send_to_peer(&mut self, data: &[u8]) -> Result<()> {
+        self.send(data, None, None).map(|_| ())
+    }
+
+    #[deprecated(note = "use `send_to_peer`")]
+    pub fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        self.send_to_peer(data)
+    }
+
+    /// `send_to_peer` that also hands the child `meta`, read with
+    /// `ShmChild::read_data_with_meta`. The metadata travels in a sidecar
+    /// next to the C2P control block, outside the payload framing, and is
+    /// neither encrypted nor timestamped. `N` can be at most `META_LEN`.
+    pub fn send_data_with_meta<const N: usize>(&mut self, data: &[u8], meta: &[u8; N]) -> Result<()> {
+        const { assert!(N <= META_LEN, "metadata larger than META_LEN") };
+        self.send(data, None, Some(meta)).map(|_| ())
+    }
+
+    /// `send_to_peer` that returns the status the child's
+    /// `ShmChild::listen_with_status` callback gave for `data`: 0 for success
+    /// by convention, anything else up to the application. The status goes
+    /// in a word of the C2P control block rather than the ACK, which keeps
+    /// its usual values; a child that doesn't set it reports 0. Needs the
+    /// C2P region, so not in a `Direction::ParentToChild` session.
+    pub fn send_data_with_status(&mut self, data: &[u8]) -> Result<u64> {
+        self.direction.require(Direction::Bidirectional)?;
+        if self.double_buffer {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "The status needs the ACK").into());
+        }
+        if self.shm_c2p.is_null() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        unsafe { status_at(self.shm_c2p.as_ptr(), self.c2p_size) }.store(0, Ordering::SeqCst);
+        self.send(data, None, None)?;
+        Ok(unsafe { status_at(self.shm_c2p.as_ptr(), self.c2p_size) }.load(Ordering::SeqCst))
+    }
+
+    /// `send_to_peer` that returns how many bytes were written to SHM: the
+    /// payload length, plus the tag when encryption is on.
+    pub fn send_data_counted(&mut self, data: &[u8]) -> Result<usize> {
+        self.send(data, None, None)
+    }
+
+    /// `send_to_peer` that gives up with `EfdStreamError::Timeout` once
+    /// `deadline` passes. A deadline already in the past, or one that
+    /// `min_send_interval` pacing would overrun, fails before anything is
+    /// written. If it expires waiting for the ACK, the message
+    /// has been sent; the next send first waits for that late ACK.
+    pub fn send_data_by(&mut self, data: &[u8], deadline: Instant) -> Result<()> {
+        self.send(data, Some(deadline), None).map(|_| ())
+    }
+
+    /// `send_data_by` with a timeout from now, for callers that would rather
+    /// branch than match on the error: `Ok(false)` if it passes first. The
+    /// message may have been sent by then, as with `send_data_by`.
+    pub fn send_data_timeout(&mut self, data: &[u8], timeout: Duration) -> Result<bool> {
+        match self.send_data_by(data, Instant::now() + timeout) {
+            Ok(()) => Ok(true),
+            Err(EfdStreamError::Timeout) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends `records` back to back as one message, copying each straight
+    /// into SHM instead of gathering them in a buffer first, and waits for
+    /// the ACK. Returns the payload length and the number of records. If
+    /// they outgrow `usable_size()` the send fails with `DataTooLarge` and
+    /// the child is never signalled; an empty batch is `ReservedLength`, as
+    /// for `send_to_peer`.
+    pub fn send_from_iter<R: AsRef<[u8]>>(&mut self, records: impl IntoIterator<Item = R>) -> Result<(usize, usize)> {
+        self.direction.require(Direction::ParentToChild)?;
+        self.check_open()?;
+        let (doorbell, len, count) = if self.double_buffer {
+            let written = self.write_records(records)?;
+            self.await_turn(None)?;
+            written
+        } else {
+            self.await_turn(None)?;
+            self.write_records(records)?
+        };
+        self.ring_and_wait(doorbell, len, None, None)?;
+        Ok((len, count))
+    }
+
+    /// `send_to_peer` for a message in several slices, e.g. a header and a
+    /// body, copied into SHM one after another so they needn't be joined
+    /// first. Empty slices add nothing. The total is limited as for
+    /// `send_to_peer`; past `usable_size()` the send fails with
+    /// `DataTooLarge` and the child is never signalled.
+    pub fn send_data_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        self.send_from_iter(bufs).map(|_| ())
+    }
+
+    /// Sends `data` on the priority channel and waits for its ACK. A child
+    /// with both doorbells waiting takes this one first, so it overtakes a
+    /// normal send the child hasn't picked up yet, e.g. one `send_data_by`
+    /// left outstanding, whose ACK it doesn't wait for. A message the child
+    /// is already handling is not interrupted. Needs
+    /// `ShmParentBuilder::priority_channel`. Frames on it are not encrypted,
+    /// so it is `Unsupported` with `encrypt`, and its region keeps the size
+    /// it started with across `resize_shm`.
+    pub fn send_priority(&mut self, data: &[u8]) -> Result<()> {
+        if self.send_shut_down {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Send side shut down").into());
+        }
+        let (Some(send), Some(ack)) = (&self.file_priority_send, &self.file_priority_ack) else {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "No priority channel").into());
+        };
+        if self.cipher.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "The priority channel is not encrypted").into());
+        }
+        let overhead = frame_overhead(false, self.timestamps);
+        check_length(data.len() + overhead)?;
+        if data.len() + overhead > self.priority_size {
+            return Err(EfdStreamError::DataTooLarge { len: data.len() + overhead, cap: self.priority_size });
+        }
+        let (send, ack) = (send.as_raw_fd(), ack.as_raw_fd());
+
+        let frame_len = unsafe {
+            write_frame_at(self.shm_priority.as_ptr(), self.priority_size, None, self.stamp(), data, self.nontemporal_threshold)
+        };
+        let sent_at = Instant::now();
+        eventfd_write(unsafe { BorrowedFd::borrow_raw(send) }, self.endianness.to_wire(frame_len as u64))?;
+        let acked = self.read_eventfd(ack, None)?;
+        self.check_ack(acked)?;
+        self.metrics.record_send(data.len(), sent_at.elapsed());
+        Ok(())
+    }
+
+    /// How many outstanding sends the child has ACKed since the last call,
+    /// without blocking. A send is only left outstanding when `send_data_by`
+    /// times out waiting for its ACK; once this reports it, the P2C region
+    /// is free to reuse and the next send doesn't wait first. Fails with
+    /// `EfdStreamError::Rejected` if the child NACKed that send instead.
+    pub fn poll_acks(&mut self) -> Result<u64> {
+        self.direction.require(Direction::ParentToChild)?;
+        if self.send_shut_down {
+            return Ok(0);
+        }
+        let Some(file_ack) = &self.file_p2c_ack else {
+            return Err(EfdStreamError::NotStarted);
+        };
+        if !self.ack_pending || !is_readable(file_ack.as_fd())? {
+            return Ok(0);
+        }
+        let acks = eventfd_read(file_ack.as_fd())?;
+        self.ack_pending = false;
+        self.settle_ack(acks)?;
+        Ok(self.endianness.from_wire(acks))
+    }
+
+    /// Blocks until the child has ACKed every send, i.e. consumed everything
+    /// sent so far. `send_to_peer` waits for its own ACK, so the only send
+    /// left outstanding is one `send_data_by` gave up on; the P2C region
+    /// holds a single frame, so there is never more than one. Without it
+    /// this returns straight away. Fails with `EfdStreamError::Rejected` if
+    /// the child NACKed that send.
+    pub fn flush(&mut self) -> Result<()> {
+        self.direction.require(Direction::ParentToChild)?;
+        if !self.ack_pending {
+            return Ok(());
+        }
+        let Some(ack) = self.file_p2c_ack.as_ref().map(|f| f.as_raw_fd()) else {
+            return Err(EfdStreamError::NotStarted);
+        };
+        let acked = self.read_eventfd(ack, None)?;
+        self.ack_pending = false;
+        self.settle_ack(acked)
+    }
+
+    /// The code and message from the last `EfdStreamError::HandlerFailed`,
+    /// kept until the next one.
+    pub fn last_child_error(&self) -> Option<(u32, String)> {
+        self.last_child_error.clone()
+    }
+
+    // Turns the child's ACK for a send, as read off the eventfd, into the
+    // send's outcome.
+    pub(crate) fn check_ack(&mut self, acked: u64) -> Result<()> {
+        match self.endianness.from_wire(acked) {
+            NACK => Err(EfdStreamError::Rejected),
+            ERROR_ACK if self.shm_c2p.len() >= c2p_map_len(self.c2p_size) => {
+                let (code, message) = unsafe { read_child_error(self.shm_c2p.as_ptr(), self.c2p_size) };
+                self.last_child_error = Some((code, message.clone()));
+                Err(EfdStreamError::HandlerFailed { code, message })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // `check_ack` for a send left awaiting its ACK. One `double_buffer` left
+    // is only counted in the metrics now.
+    fn settle_ack(&mut self, acked: u64) -> Result<()> {
+        let unacked = self.unacked.take();
+        self.check_ack(acked)?;
+        if let Some((len, sent_at)) = unacked {
+            self.metrics.record_send(len, sent_at.elapsed());
+            #[cfg(feature = "tracing")]
+            trace::record_rtt(sent_at.elapsed());
+        }
+        Ok(())
+    }
+
+    // Returns the frame length written to SHM.
+    fn send(&mut self, data: &[u8], deadline: Option<Instant>, meta: Option<&[u8]>) -> Result<usize> {
+        #[cfg(feature = "tracing")]
+        let _span = trace::send_span("p2c", data.len()).entered();
+        let result = self.send_frame(data, deadline, meta);
+        #[cfg(feature = "tracing")]
+        trace::finish(&result);
+        result
+    }
+
+    fn send_frame(&mut self, data: &[u8], deadline: Option<Instant>, meta: Option<&[u8]>) -> Result<usize> {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(EfdStreamError::Timeout);
+        }
+        self.check_send(data)?;
+
+        // Write to SHM; with `double_buffer`, while the child may still be
+        // reading the other buffer.
+        let doorbell = if self.double_buffer {
+            let doorbell = self.write_frame(data);
+            self.await_turn(deadline)?;
+            doorbell
+        } else {
+            self.await_turn(deadline)?;
+            self.write_frame(data)
+        };
+        self.ring_and_wait(doorbell, data.len(), deadline, meta)
+    }
+
+    // Paces the send and waits out an ACK an earlier send left pending, after
+    // which the P2C region is free to write.
+    fn await_turn(&mut self, deadline: Option<Instant>) -> Result<()> {
+        let delay = self.pacing_delay();
+        if !delay.is_zero() {
+            if deadline.is_some_and(|deadline| Instant::now() + delay > deadline) {
+                return Err(EfdStreamError::Timeout);
+            }
+            std::thread::sleep(delay);
+        }
+
+        // The child may still be reading the previous payload.
+        if self.ack_pending {
+            if let Some(ack) = self.file_p2c_ack.as_ref().map(|f| f.as_raw_fd()) {
+                let acked = self.read_eventfd(ack, deadline)?;
+                self.ack_pending = false;
+                if self.double_buffer {
+                    self.settle_ack(acked)?;
+                }
+            }
+            self.ack_pending = false;
+        }
+        Ok(())
+    }
+
+    // Announces the frame already in SHM and waits for its ACK. `len` is the
+    // payload length, for the metrics.
+    fn ring_and_wait(&mut self, doorbell: u64, len: usize, deadline: Option<Instant>, meta: Option<&[u8]>) -> Result<usize> {
+        if let Some(meta) = meta {
+            if self.shm_c2p.len() < c2p_map_len(self.c2p_size) {
+                return Err(no_meta_area());
+            }
+            unsafe { write_meta(self.shm_c2p.as_ptr(), self.c2p_size, P2C_META, meta) };
+        }
+
+        // Send Length
+        let ack_signal = self.ack_signal();
+        if let Some(signal) = ack_signal {
+            signal.store(0, Ordering::SeqCst);
+        }
+        let sent_at = Instant::now();
+        self.last_send = Some(sent_at);
+        let rung = if self.second_buffer { doorbell | SECOND_BUFFER } else { doorbell };
+        if let Some(file_send) = &self.file_p2c_send {
+            eventfd_write(file_send.as_fd(), self.endianness.to_wire(rung))?;
+        }
+        // The next send waits for this ACK before it rings.
+        if self.double_buffer {
+            self.ack_pending = true;
+            self.unacked = Some((len, sent_at));
+            self.second_buffer = !self.second_buffer;
+            return Ok(doorbell as usize);
+        }
+
+        // Wait for ACK
+        if let Some(ack) = self.file_p2c_ack.as_ref().map(|f| f.as_raw_fd()) {
+            // The eventfd read below is what counts; the spin only spares it
+            // from sleeping when the ACK is quick.
+            if let Some(signal) = ack_signal {
+                for _ in 0..self.ack_spin {
+                    if signal.load(Ordering::Acquire) != 0 {
+                        break;
+                    }
+                    std::hint::spin_loop();
+                }
+            }
+            let acked = self.read_eventfd(ack, deadline);
+            self.ack_pending = matches!(acked, Err(EfdStreamError::Timeout));
+            self.check_ack(acked?)?;
+        }
+        self.metrics.record_send(len, sent_at.elapsed());
+        #[cfg(feature = "tracing")]
+        trace::record_rtt(sent_at.elapsed());
+
+        Ok(doorbell as usize)
+    }
+
+    // The word `ack_spin` watches, if it is on and there is a control block.
+    fn ack_signal(&self) -> Option<&'static AtomicU64> {
+        (self.ack_spin > 0 && !self.shm_c2p.is_null())
+            .then(|| unsafe { ack_signal_at(self.shm_c2p.as_ptr(), self.c2p_size) })
+    }
+
+    // How much longer the next send has to wait to keep to
+    // `min_send_interval`.
+    pub(crate) fn pacing_delay(&self) -> Duration {
+        match (self.min_send_interval, self.last_send) {
+            (Some(interval), Some(last)) => (last + interval).saturating_duration_since(Instant::now()),
+            _ => Duration::ZERO,
+        }
+    }
+
+    // Everything that can reject `data` before the P2C region is touched.
+    pub(crate) fn check_send(&self, data: &[u8]) -> Result<()> {
+        self.direction.require(Direction::ParentToChild)?;
+        let overhead = frame_overhead(self.cipher.is_some(), self.timestamps);
+        check_length(data.len() + overhead)?;
+        if data.len() + overhead > self.p2c_size {
+            return Err(EfdStreamError::DataTooLarge { len: data.len() + overhead, cap: self.p2c_size });
+        }
+        self.check_open()
+    }
+
+    // The checks of `check_send` that don't depend on the message.
+    fn check_open(&self) -> Result<()> {
+        if self.send_shut_down {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Send side shut down").into());
+        }
+        if self.shm_p2c.is_null() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        if self.is_paused() {
+            return Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, "Child requested a pause").into());
+        }
+        Ok(())
+    }
+
+    /// Whether the child has asked for a pause through a `PauseHandle` and
+    /// not resumed yet. Sends fail with `WouldBlock` meanwhile, apart from
+    /// `send_priority`; retry after it clears. This is separate from the
+    /// ACK, which only paces one message at a time.
+    pub fn is_paused(&self) -> bool {
+        !self.shm_c2p.is_null() && unsafe { pause_at(self.shm_c2p.as_ptr(), self.c2p_size) }.load(Ordering::SeqCst) != 0
+    }
+
+    fn stamp(&self) -> Option<Endianness> {
+        self.timestamps.then_some(self.endianness)
+    }
+
+    // Where the next frame goes: the P2C region, or the half of it
+    // `double_buffer` isn't waiting on.
+    fn p2c_frame(&self) -> *mut u8 {
+        let offset = if self.second_buffer { self.p2c_size } else { 0 };
+        unsafe { self.shm_p2c.as_ptr().add(offset) }
+    }
+
+    // Writes a checked `data` into the P2C region; returns the doorbell value.
+    pub(crate) fn write_frame(&mut self, data: &[u8]) -> u64 {
+        let stamp = self.stamp();
+        let frame_len = unsafe {
+            write_frame_at(self.p2c_frame(), self.p2c_size, self.cipher.as_mut(), stamp, data, self.nontemporal_threshold)
+        };
+        frame_len as u64
+    }
+
+    // `write_frame` for a payload given in pieces, each copied in place after
+    // the stamp. Returns the doorbell value, the payload length and the
+    // number of pieces.
+    fn write_records<R: AsRef<[u8]>>(&mut self, records: impl IntoIterator<Item = R>) -> Result<(u64, usize, usize)> {
+        let shm = self.p2c_frame();
+        let overhead = frame_overhead(self.cipher.is_some(), self.timestamps);
+        let header = if self.timestamps { TIMESTAMP_LEN } else { 0 };
+        let capacity = self.p2c_size.saturating_sub(overhead);
+        let (mut len, mut count) = (0, 0);
+        for record in records {
+            let record = record.as_ref();
+            if record.len() > capacity - len {
+                return Err(EfdStreamError::DataTooLarge { len: len + record.len() + overhead, cap: self.p2c_size });
+            }
+            unsafe { copy_payload(shm.add(header + len), record, self.nontemporal_threshold) };
+            len += record.len();
+            count += 1;
+        }
+        check_length(len + overhead)?;
+        if let Some(order) = self.stamp() {
+            unsafe { (shm as *mut [u8; TIMESTAMP_LEN]).write_unaligned(order.encode(monotonic_ns())) };
+        }
+        let frame_len = match self.cipher.as_mut() {
+            Some(cipher) => cipher.seal_in_place(unsafe { slice::from_raw_parts_mut(shm, self.p2c_size) }, header + len),
+            None => header + len,
+        };
+        Ok((frame_len as u64, len, count))
+    }
+
+    /// Blocks for the next message from the child.
+    pub fn recv_from_peer(&mut self) -> Result<Vec<u8>> {
+        self.receive(None, |payload, _| Ok(payload.to_vec()))
+    }
+
+    #[deprecated(note = "use `recv_from_peer`")]
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        self.recv_from_peer()
+    }
+
+    /// `recv_from_peer` without the copy: the guard derefs to the payload
+    /// where it sits in the C2P region, and the child's send stays blocked
+    /// until the guard is dropped (or `FrameGuard::ack`ed), so the region
+    /// can't be overwritten while it is read. `Unsupported` with `encrypt`,
+    /// whose payload only exists as a decrypted copy.
+    pub fn read_data_ref(&mut self) -> Result<FrameGuard<'_>> {
+        self.direction.require(Direction::ChildToParent)?;
+        if self.cipher.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Encrypted frames can't be borrowed").into());
+        }
+        if self.shm_c2p.is_null() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        if self.recv_shut_down {
+            return Err(EfdStreamError::PeerClosed);
+        }
+        let wire = match self.peeked.take() {
+            Some(wire) => wire,
+            None => self.wait_c2p_doorbell(None)?,
+        };
+        let doorbell = self.endianness.from_wire(wire);
+        if doorbell == EOF_DOORBELL {
+            self.recv_shut_down = true;
+            return Err(EfdStreamError::PeerClosed);
+        }
+        // The child doesn't write the region again until it is ACKed.
+        let shm: &[u8] = unsafe { slice::from_raw_parts(self.shm_c2p.as_ptr(), self.c2p_size) };
+        let payload = match decode_frame(&doorbell.to_ne_bytes(), shm, self.c2p_size).and_then(|frame| unstamp(frame, self.stamp())) {
+            Ok((_, payload)) => payload,
+            Err(e) => {
+                self.nack_bad_frame()?;
+                return Err(e);
+            }
+        };
+        self.metrics.record_receive(payload.len());
+        let ack = self.file_c2p_ack.as_ref().expect("c2p channel is open").as_fd();
+        Ok(FrameGuard { payload, ack, signal: None, order: self.endianness, value: 1, acked: false })
+    }
+
+    /// `recv_from_peer` into an `Arc<[u8]>`, copied out of SHM once, for
+    /// handing one message to several threads by cloning the `Arc`.
+    pub fn read_data_arc(&mut self) -> Result<Arc<[u8]>> {
+        self.receive(None, |payload, _| Ok(Arc::from(payload)))
+    }
+
+    /// `recv_from_peer` that also returns the metadata the child passed to
+    /// `ShmChild::send_data_with_meta`. After a plain `send_to_peer` it holds
+    /// whatever was last written there.
+    pub fn read_data_with_meta<const N: usize>(&mut self) -> Result<(Vec<u8>, [u8; N])> {
+        const { assert!(N <= META_LEN, "metadata larger than META_LEN") };
+        let (c2p, size) = (self.shm_c2p.as_ptr(), self.c2p_size);
+        self.receive(None, |payload, _| Ok((payload.to_vec(), unsafe { read_meta(c2p, size, C2P_META) })))
+    }
+
+    /// Reads one streamed response from the child, as sent by
+    /// `Responder::send_stream` or `ShmChild::send_stream`, yielding each
+    /// item until the end marker. A frame that isn't part of a stream fails
+    /// with `InvalidData`; the iterator ends after the first error.
+    pub fn read_stream(&mut self) -> impl Iterator<Item = Result<Vec<u8>>> + '_ {
+        let mut done = false;
+        std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            let item = self.receive(None, |payload, _| match payload.split_first() {
+                Some((&STREAM_PART, part)) => Ok(Some(part.to_vec())),
+                Some((&STREAM_END, [])) => Ok(None),
+                _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame is not part of a stream").into()),
+            });
+            done = !matches!(item, Ok(Some(_)));
+            item.transpose()
+        })
+    }
+
+    /// `recv_from_peer` that returns `None` instead of blocking when the child
+    /// hasn't sent anything.
+    pub fn try_read_data(&mut self) -> Result<Option<Vec<u8>>> {
+        self.try_read_with(|payload| payload.to_vec()).map(|read| read.ok())
+    }
+
+    // `try_read_data` that hands the payload to `f` in place. `f` is given
+    // back when nothing is waiting.
+    pub(crate) fn try_read_with<R, F: FnOnce(&[u8]) -> R>(&mut self, f: F) -> Result<std::result::Result<R, F>> {
+        self.direction.require(Direction::ChildToParent)?;
+        if self.recv_shut_down {
+            return Err(EfdStreamError::PeerClosed);
+        }
+        match self.c2p_send_fd() {
+            Some(fd) if self.peeked.is_none() && !is_readable(fd)? => Ok(Err(f)),
+            _ => self.receive(None, |payload, _| Ok(f(payload))).map(Ok),
+        }
+    }
+
+    /// Collects the messages the child has already sent, so a shutdown
+    /// doesn't drop results. Never waits for one that hasn't been sent: it
+    /// stops as soon as none is waiting, at the child's `shutdown_send`, or
+    /// once `timeout` has passed.
+    pub fn drain(&mut self, timeout: Duration) -> Result<Vec<Vec<u8>>> {
+        drain_with(timeout, || self.try_read_data())
+    }
+
+    /// `recv_from_peer` that also returns the child's `frame::monotonic_ns`
+    /// reading from when it sent the message; subtract it from
+    /// `monotonic_ns()` for the one-way latency. Needs
+    /// `ShmParentBuilder::timestamps`. A send time in the future fails with
+    /// `EfdStreamError::ClockSkew` instead of giving a negative latency.
+    pub fn read_timed(&mut self) -> Result<(Vec<u8>, u64)> {
+        if !self.timestamps {
+            return Err(not_timestamped());
+        }
+        self.receive(None, |payload, sent| Ok((payload.to_vec(), sent.expect("frames are timestamped"))))
+    }
+
+    /// `recv_from_peer` that gives up with `EfdStreamError::Timeout` once
+    /// `deadline` passes; nothing is consumed in that case.
+    pub fn read_data_by(&mut self, deadline: Instant) -> Result<Vec<u8>> {
+        self.receive(Some(deadline), |payload, _| Ok(payload.to_vec()))
+    }
+
+    /// `read_data_by` with a timeout from now: `Ok(None)` if no message
+    /// arrives in time, and nothing is consumed.
+    pub fn read_data_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        match self.read_data_by(Instant::now() + timeout) {
+            Ok(data) => Ok(Some(data)),
+            Err(EfdStreamError::Timeout) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copies the next message across `bufs` in order, filling each before
+    /// moving to the next, and returns the number of bytes written. A message
+    /// larger than the buffers' combined capacity is acknowledged and
+    /// discarded with an `InvalidInput` error.
+    pub fn read_data_scattered(&mut self, bufs: &mut [&mut [u8]]) -> Result<usize> {
+        self.receive(None, |payload, _| {
+            let capacity: usize = bufs.iter().map(|buf| buf.len()).sum();
+            if payload.len() > capacity {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Payload exceeds buffer capacity").into());
+            }
+            let mut rest = payload;
+            for buf in bufs.iter_mut() {
+                if rest.is_empty() {
+                    break;
+                }
+                let n = buf.len().min(rest.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                rest = &rest[n..];
+            }
+            Ok(payload.len())
+        })
+    }
+
+    /// Copies the next message into an array on the stack and returns it
+    /// with the payload length, so receiving allocates nothing (without
+    /// encryption, which decrypts into a buffer). `N` must be at least the
+    /// largest message expected: a longer one is acknowledged and discarded
+    /// with an `InvalidInput` error, as by `read_data_scattered`.
+    pub fn read_data_into_array<const N: usize>(&mut self) -> Result<([u8; N], usize)> {
+        let mut buf = [0; N];
+        let len = self.read_data_scattered(&mut [&mut buf])?;
+        Ok((buf, len))
+    }
+
+    /// Appends the next message to the file at `path`, creating it if
+    /// needed, and returns how many bytes were written. The payload is
+    /// written to the file straight out of SHM rather than through a `Vec`
+    /// (an encrypted one is decrypted into a buffer first), so a large result
+    /// can be streamed to disk with one call per chunk. The frame is ACKed
+    /// even if the write fails, and is then lost.
+    pub fn read_data_to_file(&mut self, path: &Path) -> Result<u64> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.receive(None, |payload, _| {
+            file.write_all(payload)?;
+            Ok(payload.len() as u64)
+        })
+    }
+
+    // Waits for the next C2P frame, hands the payload (and its send time, if
+    // stamped) to `f` while it is still in SHM, then ACKs so the child may
+    // overwrite it.
+    fn receive<R>(&mut self, deadline: Option<Instant>, f: impl FnOnce(&[u8], Option<u64>) -> Result<R>) -> Result<R> {
+        #[cfg(feature = "tracing")]
+        let _span = trace::receive_span("c2p").entered();
+        #[cfg(feature = "tracing")]
+        let f = |payload: &[u8], sent| {
+            trace::record_size(payload.len());
+            f(payload, sent)
+        };
+        let result = self.receive_frame(deadline, f);
+        #[cfg(feature = "tracing")]
+        trace::finish(&result);
+        result
+    }
+
+    fn receive_frame<R>(&mut self, deadline: Option<Instant>, f: impl FnOnce(&[u8], Option<u64>) -> Result<R>) -> Result<R> {
+        self.direction.require(Direction::ChildToParent)?;
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(EfdStreamError::Timeout);
+        }
+        if self.shm_c2p.is_null() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        if self.recv_shut_down {
+            return Err(EfdStreamError::PeerClosed);
+        }
+
+        // Wait for Signal
+        let doorbell = match self.peeked.take() {
+            Some(doorbell) => doorbell,
+            None => self.wait_c2p_doorbell(deadline)?,
+        };
+
+        // Read from SHM
+        let result = match self.take_frame(doorbell, f) {
+            Ok(result) => result,
+            Err(e) => {
+                self.nack_bad_frame()?;
+                return Err(e);
+            }
+        };
+
+        // Send ACK
+        if let Some(file_write) = &self.file_c2p_ack {
+            eventfd_write(file_write.as_fd(), self.endianness.to_wire(1))?;
+        }
+
+        result
+    }
+
+    fn wait_c2p_doorbell(&mut self, deadline: Option<Instant>) -> Result<u64> {
+        match self.file_c2p_send.as_ref().map(|f| f.as_raw_fd()) {
+            Some(doorbell) => self.read_eventfd(doorbell, deadline),
+            None => Err(EfdStreamError::NotStarted),
+        }
+    }
+
+    /// Blocks for the next message from the child like `recv_from_peer`,
+    /// but only reads its length and first bytes, leaving it in SHM and
+    /// unACKed. The child stays blocked in its send until the message is
+    /// taken with `recv_from_peer` (or `try_read_data`, or any other
+    /// receive) or dropped with `skip_frame`, so one of them must follow.
+    /// Peeking again before that returns the same message. `Unsupported`
+    /// with `encrypt`, since a frame can only be opened once.
+    pub fn peek_frame(&mut self) -> Result<FrameHeader> {
+        self.direction.require(Direction::ChildToParent)?;
+        if self.cipher.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Encrypted frames can't be peeked").into());
+        }
+        if self.shm_c2p.is_null() {
+            return Err(EfdStreamError::NotStarted);
+        }
+        if self.recv_shut_down {
+            return Err(EfdStreamError::PeerClosed);
+        }
+        let wire = match self.peeked.take() {
+            Some(wire) => wire,
+            None => self.wait_c2p_doorbell(None)?,
+        };
+        let doorbell = self.endianness.from_wire(wire);
+        if doorbell == EOF_DOORBELL {
+            self.recv_shut_down = true;
+            return Err(EfdStreamError::PeerClosed);
+        }
+        let shm = unsafe { slice::from_raw_parts(self.shm_c2p.as_ptr(), self.c2p_size) };
+        let header = decode_frame(&doorbell.to_ne_bytes(), shm, self.c2p_size)
+            .and_then(|frame| unstamp(frame, self.stamp()))
+            .map(|(sent, payload)| FrameHeader::new(payload, sent));
+        if header.is_err() {
+            self.nack_bad_frame()?;
+        } else {
+            self.peeked = Some(wire);
+        }
+        header
+    }
+
+    /// Drops the message `peek_frame` looked at, or the next one if none
+    /// was peeked, ACKing it without copying the payload out.
+    pub fn skip_frame(&mut self) -> Result<()> {
+        self.receive(None, |_, _| Ok(()))
+    }
+
+    // After `take_frame` refused a doorbell: a length that doesn't fit is
+    // NACKed so the child's send fails instead of waiting forever. The EOF
+    // sentinel is never answered.
+    pub(crate) fn nack_bad_frame(&self) -> Result<()> {
+        if let (false, Some(ack)) = (self.recv_shut_down, &self.file_c2p_ack) {
+            eventfd_write(ack.as_fd(), self.endianness.to_wire(NACK))?;
+        }
+        Ok(())
+    }
+
+    // Reads one of our eventfds, failing with `ChildDied` instead of blocking
+    // forever if the child exits first. Without a deadline or a pidfd (a
+    // socket-started peer, an old kernel) this is a plain blocking read.
+    fn read_eventfd(&mut self, fd: RawFd, deadline: Option<Instant>) -> Result<u64> {
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        let exit = self.pidfd.as_ref().map(|pidfd| pidfd.as_fd());
+        if (exit.is_some() || deadline.is_some()) && !wait_readable_or_exit(fd, exit, deadline)? {
+            return Err(self.child_died());
+        }
+        eventfd_read(fd)
+    }
+
+    // A child that died or stayed silent during the handshake never got
+    // going; one that is still running is killed and reaped here rather
+    // than left to `Drop`.
+    fn setup_failed(&mut self, e: EfdStreamError) -> EfdStreamError {
+        match e {
+            EfdStreamError::ChildDied { status, .. } => EfdStreamError::ChildSetupFailed { status: Some(status) },
+            EfdStreamError::Timeout => {
+                if let Some(mut child) = self.child.take() {
+                    kill_child(&mut child, self.new_session);
+                    let _ = child.wait();
+                }
+                self.pidfd = None;
+                EfdStreamError::ChildSetupFailed { status: None }
+            }
+            e => e,
+        }
+    }
+
+    pub(crate) fn child_died(&mut self) -> EfdStreamError {
+        match self.child.as_mut().map(|child| child.wait()) {
+            Some(Ok(status)) => EfdStreamError::ChildDied { status, signal: status.signal() },
+            Some(Err(e)) => e.into(),
+            None => std::io::Error::other("Child exited").into(),
+        }
+    }
+
+    // Hands the C2P payload announced by `doorbell`, as read from the eventfd,
+    // to `f`. The outer error means the EOF sentinel, or a frame that doesn't
+    // fit, open or unstamp, which the caller NACKs; otherwise the caller ACKs,
+    // whatever `f` returned.
+    pub(crate) fn take_frame<R>(&mut self, doorbell: u64, f: impl FnOnce(&[u8], Option<u64>) -> Result<R>) -> Result<Result<R>> {
+        let doorbell = self.endianness.from_wire(doorbell);
+        if doorbell == EOF_DOORBELL {
+            self.recv_shut_down = true;
+            return Err(EfdStreamError::PeerClosed);
+        }
+        let shm = unsafe { slice::from_raw_parts(self.shm_c2p.as_ptr(), self.c2p_size) };
+        let frame = decode_frame(&doorbell.to_ne_bytes(), shm, self.c2p_size)?;
+        let plain;
+        let frame = match &mut self.cipher {
+            Some(cipher) => {
+                plain = cipher.open(frame)?;
+                &plain[..]
+            }
+            None => frame,
+        };
+        let (sent, payload) = unstamp(frame, self.stamp())?;
+        let result = f(payload, sent);
+        if result.is_ok() {
+            self.metrics.record_receive(payload.len());
+        }
+        Ok(result)
+    }
+}
+
+// Readable when the child has rung the C2P doorbell; follow up with
+// `recv_from_peer`, which then does not block.
+#[cfg(feature = "mio")]
+impl mio::event::Source for ShmParent {
+    fn register(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.c2p_doorbell()?).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &mio::Registry, token: mio::Token, interests: mio::Interest) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.c2p_doorbell()?).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> std::io::Result<()> {
+        mio::unix::SourceFd(&self.c2p_doorbell()?).deregister(registry)
+    }
+}
+
+// `-fd-p2c-send 3` becomes `<prefix>_FD_P2C_SEND=3`, and a flag without a
+// value, such as `-handshake`, `<prefix>_HANDSHAKE=1`. No value starts with `-`.
+fn env_vars(prefix: &str, args: &[String]) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+    let mut args = args.iter().peekable();
+    while let Some(flag) = args.next() {
+        let name = format!("{}_{}", prefix, flag.trim_start_matches('-').replace('-', "_").to_uppercase());
+        let value = args.next_if(|value| !value.starts_with('-')).cloned().unwrap_or_else(|| "1".into());
+        env.push((name, value));
+    }
+    env
+}
+
+fn kill_child(child: &mut ChildProcess, new_session: bool) {
+    // The child's pid is its process group id, and the kernel won't hand
+    // that pid out again while anyone in the group is alive.
+    if new_session {
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    }
+    let _ = child.kill();
+}
+
+impl Drop for ShmParent {
+    fn drop(&mut self) {
+        let detached = self.child.is_some() && self.drop_behavior == DropBehavior::Detach;
+        if let Some(mut child) = self.child.take() {
+            match self.drop_behavior {
+                DropBehavior::Kill => kill_child(&mut child, self.new_session),
+                DropBehavior::Wait => {
+                    let _ = self.shutdown_send();
+                    let _ = child.wait();
+                }
+                DropBehavior::Detach => {}
+            }
+        }
+        if self.thread_child {
+            let _ = self.shutdown_send();
+        }
+        if self.zero_on_drop && !detached {
+            self.shm_p2c.zero();
+            self.shm_c2p.zero();
+            self.shm_priority.zero();
+            self.shm_state.zero();
+        }
+    }
+}
+
+/// What `ShmChild` does with a doorbell announcing more bytes than its P2C
+/// mapping holds. The parent is NACKed either way, and its `send_to_peer` fails
+/// with `EfdStreamError::Rejected`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Drop the frame and keep listening. With the `log` feature the length
+    /// is logged at warn level.
+    #[default]
+    Skip,
+    /// End `listen` or `recv_from_peer` with an `InvalidData` error.
+    Fail,
+}
+
+/// Copies of a session's six channel descriptors and its region sizes, from
+/// `ShmParent::create_fds`. The sizes are what the child's `-shm-size` and
+/// `-c2p-size` arguments take.
+#[derive(Debug)]
+pub struct ShmFdSet {
+    pub p2c_send: OwnedFd,
+    pub p2c_ack: OwnedFd,
+    pub p2c_shm: OwnedFd,
+    pub c2p_send: OwnedFd,
+    pub c2p_ack: OwnedFd,
+    pub c2p_shm: OwnedFd,
+    pub p2c_size: usize,
+    pub c2p_size: usize,
+}
+
+/// The settings a session ended up with, from `ShmParent::negotiated`. A
+/// `start` that couldn't honour one of them fails rather than falling back,
+/// apart from huge pages, which the kernel may decline silently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// Largest message to the child, after rounding to whole pages.
+    pub p2c_size: usize,
+    /// Largest message from the child, rounded the same way.
+    pub c2p_size: usize,
+    pub direction: Direction,
+    /// Whether the startup handshake ran, so the child agreed to the
+    /// framing options below.
+    pub handshake: bool,
+    pub endianness: Endianness,
+    pub timestamps: bool,
+    /// Whether the handshake set up encryption.
+    pub encrypted: bool,
+    /// What each region was `madvise`d with.
+    pub advice: Option<Advice>,
+    /// `Advice::HugePage` was given and the kernel's
+    /// `transparent_hugepage/shmem_enabled` setting lets it take effect.
+    pub huge_pages: bool,
+    /// The parent's mappings are pinned with `lock_memory`.
+    pub memory_locked: bool,
+    pub control_channel: bool,
+    pub priority_channel: bool,
+    pub double_buffer: bool,
+    /// Bytes of the shared state region; 0 without one.
+    pub shared_state_size: usize,
+    /// Iterations each send spins on the ACK word; 0 when off.
+    pub ack_spin: u32,
+}
+
+/// The length and first bytes of a message `ShmParent::peek_frame` looked
+/// at without taking it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+    /// Payload length, as `recv_from_peer` will return it.
+    pub len: usize,
+    /// The child's send time, with `timestamps`.
+    pub sent: Option<u64>,
+    head: [u8; FrameHeader::HEAD_LEN],
+}
+
+impl FrameHeader {
+    /// How many leading payload bytes are kept.
+    pub const HEAD_LEN: usize = 16;
+
+    fn new(payload: &[u8], sent: Option<u64>) -> Self {
+        let mut head = [0; Self::HEAD_LEN];
+        let kept = payload.len().min(Self::HEAD_LEN);
+        head[..kept].copy_from_slice(&payload[..kept]);
+        FrameHeader { len: payload.len(), sent, head }
+    }
+
+    /// The payload's first `HEAD_LEN` bytes, or all of it if shorter.
+    pub fn head(&self) -> &[u8] {
+        &self.head[..self.len.min(Self::HEAD_LEN)]
+    }
+}
+
+/// Replies to the request a `ShmChild::listen_with_responder` callback is
+/// handling. They are queued and sent, in order, once the request has been
+/// ACKed: a parent blocked in `send_to_peer` only starts reading after the ACK,
+/// so a reply sent from inside the callback would deadlock. The handle owns
+/// its queue and borrows nothing from the child.
+pub struct Responder {
+    replies: Vec<Vec<u8>>,
+    // Largest payload the C2P region takes, after framing overhead.
+    capacity: usize,
+    pause: Option<PauseHandle>,
+    // Whether the parent reserved the trailer the error goes in.
+    error_area: bool,
+    error: Option<(u32, String)>,
+}
+
+impl Responder {
+    /// `PauseHandle::request_pause`, for a handler that sees its backlog
+    /// grow. The resume has to come from elsewhere, e.g. a worker holding
+    /// `ShmChild::pause_handle`, since no request arrives while paused.
+    /// Fails with `Unsupported` under a Go or C parent.
+    pub fn request_pause(&self) -> Result<()> {
+        self.pause.as_ref().ok_or_else(no_control_block)?.request_pause()
+    }
+
+    pub fn request_resume(&self) -> Result<()> {
+        self.pause.as_ref().ok_or_else(no_control_block)?.request_resume()
+    }
+
+    /// Queues `data` as a reply. Fails with `EfdStreamError::DataTooLarge`
+    /// straight away if it can't fit in the C2P region, rather than when it
+    /// is sent.
+    pub fn send(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > self.capacity {
+            return Err(EfdStreamError::DataTooLarge { len: data.len(), cap: self.capacity });
+        }
+        self.replies.push(data.to_vec());
+        Ok(())
+    }
+
+    /// Queues `items` as one streamed response, which the parent reads with
+    /// `ShmParent::read_stream`: each item goes out as its own frame, one
+    /// byte longer than the item, followed by an end marker. Nothing is
+    /// queued if an item doesn't fit.
+    pub fn send_stream(&mut self, items: impl IntoIterator<Item = Vec<u8>>) -> Result<()> {
+        let mut frames = Vec::new();
+        for item in items {
+            if item.len() + 1 > self.capacity {
+                return Err(EfdStreamError::DataTooLarge { len: item.len() + 1, cap: self.capacity });
+            }
+            frames.push(stream_frame(&item));
+        }
+        frames.push(vec![STREAM_END]);
+        self.replies.append(&mut frames);
+        Ok(())
+    }
+
+    /// Reports that handling the request failed: the parent's `send_to_peer`
+    /// fails with `EfdStreamError::HandlerFailed` carrying `code` and
+    /// `message`, which `ShmParent::last_child_error` also returns. Replies
+    /// queued for the request are dropped. A later call replaces the error.
+    /// Fails with `Unsupported` under a Go or C parent.
+    pub fn set_error(&mut self, code: u32, message: &str) -> Result<()> {
+        if !self.error_area {
+            return Err(no_control_block());
+        }
+        self.error = Some((code, message.to_string()));
+        Ok(())
+    }
+}
+
+/// Asks the parent to stop sending until resumed, for a child whose
+/// workers fall behind, e.g. under `ShmChild::listen_async`. It raises a
+/// flag in the C2P control block that `ShmParent::is_paused` reports and
+/// that makes the parent's sends fail with `WouldBlock`. Clones can be
+/// used from any thread; once the `ShmChild` is dropped their calls fail
+/// with `BrokenPipe`.
+#[derive(Clone)]
+pub struct PauseHandle {
+    // Address of the child's pause word, 0 once the child is gone. Moved
+    // under the lock when a resize remaps the control block.
+    word: Arc<Mutex<usize>>,
+}
+
+impl PauseHandle {
+    pub fn request_pause(&self) -> Result<()> {
+        self.set(1)
+    }
+
+    pub fn request_resume(&self) -> Result<()> {
+        self.set(0)
+    }
+
+    fn set(&self, paused: u64) -> Result<()> {
+        let word = self.word.lock().unwrap_or_else(PoisonError::into_inner);
+        if *word == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Child dropped").into());
+        }
+        unsafe { &*(*word as *const AtomicU64) }.store(paused, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+// ACKs a P2C frame, then raises the word a parent's `ack_spin` watches.
+fn write_ack(fd: BorrowedFd, order: Endianness, value: u64, signal: Option<&AtomicU64>) -> std::io::Result<()> {
+    eventfd_write(fd, order.to_wire(value))?;
+    if let Some(signal) = signal {
+        signal.store(value, Ordering::Release);
+    }
+    Ok(())
+}
+
+/// A message `ShmChild::listen_guarded` hands out, borrowed straight from
+/// the P2C region (or from the decrypted copy, under `encrypt`). The
+/// parent's `send_to_peer` stays blocked until the guard is dropped, which
+/// ACKs the message, so hold it only across short work. It is `Send`, so
+/// the callback can pass it to a scoped thread. `ShmParent::read_data_ref`
+/// returns one over the C2P region, holding up the child's send the same way.
+pub struct FrameGuard<'a> {
+    payload: &'a [u8],
+    ack: BorrowedFd<'a>,
+    signal: Option<&'a AtomicU64>,
+    order: Endianness,
+    // 1, or `ERROR_ACK` for a failed handler.
+    value: u64,
+    acked: bool,
+}
+
+impl FrameGuard<'_> {
+    /// ACKs now instead of on drop, returning the error a drop would
+    /// discard.
+    pub fn ack(mut self) -> Result<()> {
+        self.acked = true;
+        write_ack(self.ack, self.order, self.value, self.signal)?;
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for FrameGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.payload
+    }
+}
+
+impl Drop for FrameGuard<'_> {
+    fn drop(&mut self) {
+        if !self.acked {
+            let _ = write_ack(self.ack, self.order, self.value, self.signal);
+        }
+    }
+}
+
+// What a P2C doorbell turned out to announce.
+enum Doorbell<R> {
+    Frame(R),
+    Eof,
+    // A resize, a reset, or a length the child couldn't use.
+    Skipped,
+}
+
+/// The child end. It only borrows the fds it is given (except those from
+/// `from_socket`), so it can be used for any number of calls:
+///
+/// - `init` maps the regions and answers the handshake. It runs at most
+///   once; the receive and send methods call it on first use, and calling
+///   it again fails with `AlreadyExists`.
+/// - `listen` (and its variants) and `recv_from_peer` can be mixed freely with
+///   `send_to_peer`, in any order and as often as needed.
+/// - Once the parent has called `shutdown_send`, `listen` returns `Ok(())`
+///   straight away and `recv_from_peer` fails with `PeerClosed`, every time;
+///   `send_to_peer` still works.
+/// - After this side's `shutdown_send`, `send_to_peer` fails with `BrokenPipe`.
+/// - `ShmParent::reset` is handled inside `listen` or `recv_from_peer`, which
+///   answer the new handshake and keep waiting. A `send_to_peer` still waiting
+///   for its ACK returns `Ok` and the reply is dropped.
+pub struct ShmChild {
+    pub(crate) fd_p2c_send: RawFd,
+    pub(crate) fd_p2c_ack: RawFd,
+    fd_p2c_shm: RawFd,
+    pub(crate) fd_c2p_send: RawFd,
+    pub(crate) fd_c2p_ack: RawFd,
+    fd_c2p_shm: RawFd,
+    direction: Direction,
+    pub(crate) p2c_size: usize,
+    pub(crate) c2p_size: usize,
+    advice: Option<Advice>,
+    handshake: bool,
+    // Agreed on in the handshake: the parent built with `timestamps`.
+    timestamps: bool,
+    endianness: Endianness,
+    nontemporal_threshold: Option<usize>,
+    retry: Retry,
+    lock_memory: bool,
+    // The ring consumer writes its tail into the P2C region.
+    pub(crate) p2c_writable: bool,
+    pub(crate) shm_p2c: SharedRegion,
+    pub(crate) shm_c2p: SharedRegion,
+    #[cfg(feature = "crypto")]
+    key: Option<[u8; 32]>,
+    cipher: Option<FrameCipher>,
+    send_shut_down: bool,
+    // The parent called `ShmParent::shutdown_send`.
+    recv_shut_down: bool,
+    oversize_policy: OversizePolicy,
+    fd_control: Option<RawFd>,
+    // The priority channel's send, ack and shm fds, and its region, which
+    // keeps the size it was mapped with.
+    fd_priority: Option<[RawFd; 3]>,
+    shm_priority: SharedRegion,
+    priority_size: usize,
+    fd_state: Option<RawFd>,
+    state_addr: Option<usize>,
+    shm_state: SharedRegion,
+    // Shared with every `PauseHandle` given out.
+    pause: Option<Arc<Mutex<usize>>>,
+    // Descriptors this child received itself (e.g. over a socket) and must
+    // close. Inherited fds from `new` are left alone.
+    owned_fds: Vec<OwnedFd>,
+    // The socket `connect` went through and its path; the parent's end
+    // closing means it has gone.
+    rendezvous: Option<(UnixStream, PathBuf)>,
+    reconnect: Option<Reconnect>,
+}
+
+// How `ShmChild::reconnect` retries.
+struct Reconnect {
+    attempts: u32,
+    backoff: Duration,
+    on_reconnect: Box<dyn FnMut() + Send>,
+}
+
+impl ShmChild {
+    /// In a simplex session, pass -1 for the other direction's three fds
+    /// and set `direction`.
+    pub fn new(fd_p2c_send: RawFd, fd_p2c_ack: RawFd, fd_p2c_shm: RawFd,
+               fd_c2p_send: RawFd, fd_c2p_ack: RawFd, fd_c2p_shm: RawFd,
+               shm_size: usize) -> Self {
+        Self { 
+            fd_p2c_send, fd_p2c_ack, fd_p2c_shm,
+            fd_c2p_send, fd_c2p_ack, fd_c2p_shm,
+            direction: Direction::Bidirectional,
+            p2c_size: shm_size,
+            c2p_size: shm_size,
+            advice: None,
+            handshake: false,
+            timestamps: false,
+            endianness: Endianness::Native,
+            nontemporal_threshold: None,
+            retry: Retry::default(),
+            lock_memory: false,
+            p2c_writable: false,
+            shm_p2c: SharedRegion::unmapped(),
+            shm_c2p: SharedRegion::unmapped(),
+            #[cfg(feature = "crypto")]
+            key: None,
+            cipher: None,
+            send_shut_down: false,
+            recv_shut_down: false,
+            oversize_policy: OversizePolicy::Skip,
+            fd_control: None,
+            fd_priority: None,
+            shm_priority: SharedRegion::unmapped(),
+            priority_size: 0,
+            fd_state: None,
+            state_addr: None,
+            shm_state: SharedRegion::unmapped(),
+            pause: None,
+            owned_fds: Vec::new(),
+            rendezvous: None,
+            reconnect: None,
+        }
+    }
+
+    /// Builds the child from the arguments `ShmParent::start` passes: the six
+    /// `-fd-*` flags (three under `-direction`) and `-shm-size` are required,
+    /// `-c2p-size`, `-handshake`, `-endianness`, `-fd-control`, the
+    /// `-fd-prio-*` flags, `-fd-state` and `-state-addr` are applied when
+    /// present, and anything else is left to the caller.
+    pub fn from_env_args() -> Result<Self> {
+        Self::from_args(std::env::args().skip(1).collect())
+    }
+
+    /// Like `from_env_args`, but reads the settings from the variables a
+    /// parent built with `ShmParentBuilder::env_prefix(prefix)` sets, so the
+    /// fd numbers are whatever the parent's layout put them at.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let prefix = format!("{}_", prefix);
+        let mut args = Vec::new();
+        for (key, value) in std::env::vars() {
+            if let Some(name) = key.strip_prefix(&prefix) {
+                args.push(format!("-{}", name.to_lowercase().replace('_', "-")));
+                args.push(value);
+            }
+        }
+        Self::from_args(args)
+    }
+
+    fn from_args(args: Vec<String>) -> Result<Self> {
+        let invalid = |msg: String| EfdStreamError::from(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+        let value = |flag: &str| -> Result<Option<&str>> {
+            match args.iter().position(|arg| arg == flag) {
+                None => Ok(None),
+                Some(i) => args.get(i + 1).map(|v| Some(v.as_str()))
+                    .ok_or_else(|| invalid(format!("Missing value for {}", flag))),
+            }
+        };
+        let number = |flag: &str| -> Result<Option<usize>> {
+            value(flag)?.map(|v| v.parse().map_err(|_| invalid(format!("Invalid value for {}: {:?}", flag, v))))
+                .transpose()
+        };
+        let required = |flag: &str| -> Result<usize> {
+            number(flag)?.ok_or_else(|| invalid(format!("Missing {}", flag)))
+        };
+
+        let fd = |flag: &str| -> Result<RawFd> {
+            RawFd::try_from(required(flag)?).map_err(|_| invalid(format!("Invalid value for {}", flag)))
+        };
+        let direction = match value("-direction")? {
+            None => Direction::Bidirectional,
+            Some("p2c") => Direction::ParentToChild,
+            Some("c2p") => Direction::ChildToParent,
+            Some(v) => return Err(invalid(format!("Invalid value for -direction: {:?}", v))),
+        };
+        let channel = |carried: bool, flags: [&str; 3]| -> Result<[RawFd; 3]> {
+            if !carried {
+                return Ok([-1; 3]);
+            }
+            Ok([fd(flags[0])?, fd(flags[1])?, fd(flags[2])?])
+        };
+        let [p2c_send, p2c_ack, p2c_shm] = channel(direction.has_p2c(), ["-fd-p2c-send", "-fd-p2c-ack", "-fd-p2c-shm"])?;
+        let [c2p_send, c2p_ack, c2p_shm] = channel(direction.has_c2p(), ["-fd-c2p-send", "-fd-c2p-ack", "-fd-c2p-shm"])?;
+        let mut child = Self::new(p2c_send, p2c_ack, p2c_shm, c2p_send, c2p_ack, c2p_shm, required("-shm-size")?)
+            .direction(direction)
+            .handshake(args.iter().any(|arg| arg == "-handshake"));
+        if let Some(size) = number("-c2p-size")? {
+            child = child.c2p_size(size);
+        }
+        match value("-endianness")? {
+            None => {}
+            Some("little") => child = child.endianness(Endianness::Little),
+            Some("big") => child = child.endianness(Endianness::Big),
+            Some(v) => return Err(invalid(format!("Invalid value for -endianness: {:?}", v))),
+        }
+        if number("-fd-control")?.is_some() {
+            child = child.control_channel(fd("-fd-control")?);
+        }
+        if number("-fd-prio-send")?.is_some() {
+            child = child.priority_channel(fd("-fd-prio-send")?, fd("-fd-prio-ack")?, fd("-fd-prio-shm")?);
+        }
+        if number("-fd-state")?.is_some() {
+            child = child.shared_state_memfd(fd("-fd-state")?);
+        }
+        if let Some(addr) = number("-state-addr")? {
+            child.state_addr = Some(addr);
+        }
+        Ok(child)
+    }
+
+    /// Receives the six descriptors and the SHM sizes from a parent that
+    /// called `ShmParent::start_with_socket`. The handshake, direction,
+    /// control and priority channel and shared state settings follow the
+    /// parent's, and the
+    /// fds are closed when the child is dropped.
+    pub fn from_socket(socket: &UnixStream) -> Result<Self> {
+        let (fds, p2c_size, c2p_size, flags) = recv_fds(socket)?;
+        let direction = if flags & fdpass::FLAG_P2C_ONLY != 0 {
+            Direction::ParentToChild
+        } else if flags & fdpass::FLAG_C2P_ONLY != 0 {
+            Direction::ChildToParent
+        } else {
+            Direction::Bidirectional
+        };
+        let mut raw = fds.iter().map(|fd| fd.as_raw_fd());
+        let mut channel = |carried: bool| if carried { [(); 3].map(|_| raw.next().unwrap()) } else { [-1; 3] };
+        let [p2c_send, p2c_ack, p2c_shm] = channel(direction.has_p2c());
+        let [c2p_send, c2p_ack, c2p_shm] = channel(direction.has_c2p());
+        let mut child = Self::new(p2c_send, p2c_ack, p2c_shm, c2p_send, c2p_ack, c2p_shm, p2c_size)
+            .direction(direction)
+            .c2p_size(c2p_size)
+            .handshake(flags & fdpass::FLAG_HANDSHAKE != 0);
+        if flags & fdpass::FLAG_CONTROL != 0 {
+            child.fd_control = raw.next();
+        }
+        if flags & fdpass::FLAG_PRIORITY != 0 {
+            child.fd_priority = Some([raw.next().unwrap(), raw.next().unwrap(), raw.next().unwrap()]);
+        }
+        if flags & fdpass::FLAG_STATE != 0 {
+            child.fd_state = raw.next();
+        }
+        child.owned_fds = fds;
+        Ok(child)
+    }
+
+    /// The child half of `ShmParent::bind`: connects to the socket at `path`
+    /// and takes the descriptors as `from_socket` does. Fails with `NotFound`
+    /// or `ConnectionRefused` while the parent isn't listening yet, so a
+    /// child started first can retry.
+    /// The connection is kept open, so once the parent is gone, `listen`,
+    /// `recv_from_peer` and `send_to_peer` fail with `ConnectionReset`
+    /// rather than wait forever.
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let socket = UnixStream::connect(path)?;
+        let mut child = Self::from_socket(&socket)?;
+        child.rendezvous = Some((socket, path.to_path_buf()));
+        Ok(child)
+    }
+
+    /// For a child made with `connect`: when the parent goes away while
+    /// `listen` or `recv_from_peer` is waiting, connect to the same path
+    /// again, up to `attempts` times with `backoff` doubling in between,
+    /// take the new session's descriptors and answer its handshake, then
+    /// call `on_reconnect` and keep waiting. Messages in flight are lost,
+    /// and a `send_to_peer` waiting for its ACK still fails with
+    /// `ConnectionReset`. Once the attempts run out, the last connect error
+    /// is returned.
+    pub fn reconnect(mut self, attempts: u32, backoff: Duration, on_reconnect: impl FnMut() + Send + 'static) -> Self {
+        self.reconnect = Some(Reconnect { attempts, backoff, on_reconnect: Box::new(on_reconnect) });
+        self
+    }
+
+    // Replaces the session with a new one from the parent listening at the
+    // rendezvous path, keeping this child's own settings.
+    fn reconnect_now(&mut self) -> Result<()> {
+        let (Some(_), Some((_, path))) = (&self.reconnect, &self.rendezvous) else {
+            return Err(parent_gone());
+        };
+        let path = path.clone();
+        let mut reconnect = self.reconnect.take().unwrap();
+        let mut backoff = reconnect.backoff;
+        let mut attempt = 0;
+        let mut fresh = loop {
+            match Self::connect(&path) {
+                Ok(fresh) => break fresh,
+                Err(_) if attempt < reconnect.attempts => {
+                    std::thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        fresh.advice = self.advice;
+        fresh.nontemporal_threshold = self.nontemporal_threshold;
+        fresh.retry = self.retry;
+        fresh.lock_memory = self.lock_memory;
+        fresh.oversize_policy = self.oversize_policy;
+        #[cfg(feature = "crypto")]
+        {
+            fresh.key = self.key;
+            fresh.handshake |= self.key.is_some();
+        }
+        *self = fresh;
+        self.init()?;
+        (reconnect.on_reconnect)();
+        self.reconnect = Some(reconnect);
+        Ok(())
+    }
+
+    fn rendezvous_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.rendezvous.as_ref().map(|(socket, _)| socket.as_fd())
+    }
+
+    /// `madvise` both SHM regions when `init` maps them.
+    pub fn advise(mut self, advice: Advice) -> Self {
+        self.advice = Some(advice);
+        self
+    }
+
+    /// The child half of `ShmParentBuilder::direction`, passed as
+    /// `-direction`. Calls for the other direction fail with
+    /// `EfdStreamError::WrongDirection`.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Expect the parent's handshake during `init` (the parent passes
+    /// `-handshake` when it was built with it).
+    pub fn handshake(mut self, enabled: bool) -> Self {
+        self.handshake = enabled;
+        self
+    }
+
+    /// Copy payloads of at least `bytes` into SHM with non-temporal stores.
+    pub fn nontemporal_threshold(mut self, bytes: usize) -> Self {
+        self.nontemporal_threshold = Some(bytes);
+        self
+    }
+
+    /// The control eventfd the parent passed as `-fd-control`. Receives
+    /// then also wake for `ShmParent::send_control`.
+    pub fn control_channel(mut self, fd: RawFd) -> Self {
+        self.fd_control = Some(fd);
+        self
+    }
+
+    /// The priority channel the parent passed as `-fd-prio-send`,
+    /// `-fd-prio-ack` and `-fd-prio-shm`. Receives then take a waiting
+    /// `ShmParent::send_priority` message before a normal one.
+    pub fn priority_channel(mut self, send: RawFd, ack: RawFd, shm: RawFd) -> Self {
+        self.fd_priority = Some([send, ack, shm]);
+        self
+    }
+
+    /// The shared state memfd the parent passed as `-fd-state`, mapped
+    /// read-write at its full size by `init`.
+    pub fn shared_state_memfd(mut self, fd: RawFd) -> Self {
+        self.fd_state = Some(fd);
+        self
+    }
+
+    /// The child half of `ShmParentBuilder::shared_state_at`, passed as
+    /// `-state-addr`; `init` fails with `AddrInUse` if `addr` is taken.
+    pub fn shared_state_at(mut self, addr: *mut std::ffi::c_void) -> Self {
+        self.state_addr = Some(addr as usize);
+        self
+    }
+
+    /// See `ShmParentBuilder::startup_retries`; applies to mapping the
+    /// regions in `init`.
+    pub fn startup_retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// The child half of `ShmParentBuilder::endianness`. It must match the
+    /// parent's, which the handshake checks when it is on.
+    pub fn endianness(mut self, order: Endianness) -> Self {
+        self.endianness = order;
+        self
+    }
+
+    fn stamp(&self) -> Option<Endianness> {
+        self.timestamps.then_some(self.endianness)
+    }
 
-    // Resources
-    file_p2c_send: Option<File>,
-    file_p2c_ack: Option<File>,
-    shm_p2c_file: Option<File>,
-    shm_p2c_ptr: *mut u8,
+    /// The C2P size, when the parent passed `-c2p-size`; `new` takes the
+    /// shared (or P2C) size.
+    pub fn c2p_size(mut self, size: usize) -> Self {
+        self.c2p_size = size;
+        self
+    }
 
-    file_c2p_send: Option<File>,
-    file_c2p_ack: Option<File>,
-    shm_c2p_file: Option<File>,
-    shm_c2p_ptr: *mut u8,
+    /// See `ShmParentBuilder::lock_memory`; applies to this side's mappings.
+    pub fn lock_memory(mut self, enabled: bool) -> Self {
+        self.lock_memory = enabled;
+        self
+    }
 
-    child: Option<Child>,
-}
+    /// What to do when the parent announces a frame larger than the P2C
+    /// region; see `OversizePolicy`. Defaults to `Skip`.
+    pub fn oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize_policy = policy;
+        self
+    }
 
-unsafe impl Send for ShmParent {}
+    /// See `ShmParentBuilder::startup_backoff`.
+    pub fn startup_backoff(mut self, backoff: Duration) -> Self {
+        self.retry.backoff = backoff;
+        self
+    }
 
-impl ShmParent {
-    pub fn new(child_path: &str, shm_size: usize) -> Self {
-        Self {
-            child_path: child_path.to_string(),
-            shm_size,
-            file_p2c_send: None, file_p2c_ack: None, shm_p2c_file: None, shm_p2c_ptr: ptr::null_mut(),
-            file_c2p_send: None, file_c2p_ack: None, shm_c2p_file: None, shm_c2p_ptr: ptr::null_mut(),
-            child: None,
-        }
+    /// The child half of `ShmParentBuilder::encrypt`; `key` must match the
+    /// parent's. Turns on the handshake.
+    #[cfg(feature = "crypto")]
+    pub fn encrypt(mut self, key: [u8; 32]) -> Self {
+        self.key = Some(key);
+        self.handshake = true;
+        self
     }
 
-    pub fn start(&mut self) -> std::io::Result<()> {
-        // 1. Create P2C resources
-        let efd_p2c_send = EventFd::from_value_and_flags(0, EfdFlags::empty())?;
-        let efd_p2c_ack = EventFd::from_value_and_flags(0, EfdFlags::empty())?;
-        let name_p2c = CString::new("efdstream_shm_p2c").unwrap();
-        let memfd_p2c = memfd_create(name_p2c.as_c_str(), MFdFlags::empty())
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
-        ftruncate(&memfd_p2c, self.shm_size as i64)
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
-        let ptr_p2c = unsafe {
-            mmap(None, std::num::NonZeroUsize::new(self.shm_size).unwrap(),
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE, MapFlags::MAP_SHARED, &memfd_p2c, 0)
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?
-        };
-        self.shm_p2c_ptr = ptr_p2c.as_ptr() as *mut u8;
+    pub fn init(&mut self) -> Result<()> {
+        if self.initialized() {
+            return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, "Already initialized").into());
+        }
+        if self.handshake {
+            self.direction.require(Direction::ParentToChild)?;
+        }
+        let (shm_p2c, shm_c2p) = self.map_regions(self.p2c_size, self.c2p_size)?;
+        self.shm_p2c = shm_p2c;
+        self.shm_c2p = shm_c2p;
 
-        // 2. Create C2P resources
-        let efd_c2p_send = EventFd::from_value_and_flags(0, EfdFlags::empty())?;
-        let efd_c2p_ack = EventFd::from_value_and_flags(0, EfdFlags::empty())?;
-        let name_c2p = CString::new("efdstream_shm_c2p").unwrap();
-        let memfd_c2p = memfd_create(name_c2p.as_c_str(), MFdFlags::empty())
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
-        ftruncate(&memfd_c2p, self.shm_size as i64)
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
-        let ptr_c2p = unsafe {
-            mmap(None, std::num::NonZeroUsize::new(self.shm_size).unwrap(),
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE, MapFlags::MAP_SHARED, &memfd_c2p, 0)
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?
-        };
-        self.shm_c2p_ptr = ptr_c2p.as_ptr() as *mut u8;
-
-        // Raw FDs for dup2
-        let raw_p2c_send = efd_p2c_send.as_raw_fd();
-        let raw_p2c_ack = efd_p2c_ack.as_raw_fd();
-        let raw_p2c_shm = memfd_p2c.as_raw_fd();
-        let raw_c2p_send = efd_c2p_send.as_raw_fd();
-        let raw_c2p_ack = efd_c2p_ack.as_raw_fd();
-        let raw_c2p_shm = memfd_c2p.as_raw_fd();
-
-        // 3. Start Child
-        let mut cmd = Command::new(&self.child_path);
-        cmd.arg("-mode").arg("child");
-        // We map the FDs to 3, 4, 5, 6, 7, 8 in the child process.
-        cmd.arg("-fd-p2c-send").arg("3");
-        cmd.arg("-fd-p2c-ack").arg("4");
-        cmd.arg("-fd-p2c-shm").arg("5");
-        cmd.arg("-fd-c2p-send").arg("6");
-        cmd.arg("-fd-c2p-ack").arg("7");
-        cmd.arg("-fd-c2p-shm").arg("8");
-        cmd.arg("-shm-size").arg(self.shm_size.to_string());
-        cmd.stdin(Stdio::inherit());
-        cmd.stdout(Stdio::inherit());
-        cmd.stderr(Stdio::inherit());
-
-        let target_p2c_send = 3;
-        let target_p2c_ack = 4;
-        let target_p2c_shm = 5;
-        let target_c2p_send = 6;
-        let target_c2p_ack = 7;
-        let target_c2p_shm = 8;
-
-        unsafe {
-            cmd.pre_exec(move || {
-                if libc::dup2(raw_p2c_send, target_p2c_send) == -1 { return Err(std::io::Error::last_os_error()); }
-                if libc::dup2(raw_p2c_ack, target_p2c_ack) == -1 { return Err(std::io::Error::last_os_error()); }
-                if libc::dup2(raw_p2c_shm, target_p2c_shm) == -1 { return Err(std::io::Error::last_os_error()); }
-                if libc::dup2(raw_c2p_send, target_c2p_send) == -1 { return Err(std::io::Error::last_os_error()); }
-                if libc::dup2(raw_c2p_ack, target_c2p_ack) == -1 { return Err(std::io::Error::last_os_error()); }
-                if libc::dup2(raw_c2p_shm, target_c2p_shm) == -1 { return Err(std::io::Error::last_os_error()); }
-                Ok(())
-            });
+        if let Some([_, _, fd_shm]) = self.fd_priority {
+            let borrowed = unsafe { BorrowedFd::borrow_raw(fd_shm) };
+            self.shm_priority = self.retry.run(|| SharedRegion::map(borrowed, self.p2c_size, ProtFlags::PROT_READ))?;
+            if self.lock_memory {
+                lock_regions(&[&self.shm_priority])?;
+            }
+            self.priority_size = self.p2c_size;
         }
 
-        let child = cmd.spawn()?;
-        self.child = Some(child);
+        if let Some(fd_state) = self.fd_state {
+            let borrowed = unsafe { BorrowedFd::borrow_raw(fd_state) };
+            check_seals(borrowed, "shared state")?;
+            let len = fstat(borrowed)?.st_size as usize;
+            self.shm_state = self.retry.run(|| SharedRegion::map_at(borrowed, len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE, self.state_addr))?;
+            if self.lock_memory {
+                lock_regions(&[&self.shm_state])?;
+            }
+        }
+
+        if self.handshake {
+            self.answer_hello()?;
+        }
+
+        Ok(())
+    }
+
+    // A simplex session maps only one region.
+    fn initialized(&self) -> bool {
+        !self.shm_p2c.is_null() || !self.shm_c2p.is_null()
+    }
+
+    // The region of a direction the session doesn't carry is left unmapped.
+    fn map_regions(&self, p2c_size: usize, c2p_size: usize) -> Result<(SharedRegion, SharedRegion)> {
+        let mut shm_p2c = SharedRegion::unmapped();
+        let mut shm_c2p = SharedRegion::unmapped();
+        // Mmap P2C (Read)
+        if self.direction.has_p2c() {
+            let borrowed_p2c = unsafe { BorrowedFd::borrow_raw(self.fd_p2c_shm) };
+            let prot_p2c = if self.p2c_writable {
+                check_seals(borrowed_p2c, "P2C")?;
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE
+            } else {
+                ProtFlags::PROT_READ
+            };
+            let p2c_len = fstat(borrowed_p2c)?.st_size as usize;
+            // Twice the size is a parent with `ShmParentBuilder::double_buffer`.
+            let double_buffered = !self.p2c_writable && p2c_len == 2 * p2c_size;
+            if p2c_len != p2c_size && !double_buffered {
+                return Err(EfdStreamError::ShmSizeMismatch { parent: p2c_len, child: p2c_size });
+            }
+            shm_p2c = self.retry.run(|| SharedRegion::map(borrowed_p2c, p2c_len, prot_p2c))?;
+            advise_region(shm_p2c.as_ptr(), p2c_len, self.advice)?;
+        }
 
-        // 4. Wrap FDs
-        self.file_p2c_send = Some(File::from(OwnedFd::from(efd_p2c_send)));
-        self.file_p2c_ack = Some(File::from(OwnedFd::from(efd_p2c_ack)));
-        self.shm_p2c_file = Some(File::from(OwnedFd::from(memfd_p2c)));
+        // Mmap C2P (Write), including the control block if the parent made one
+        if self.direction.has_c2p() {
+            let borrowed_c2p = unsafe { BorrowedFd::borrow_raw(self.fd_c2p_shm) };
+            check_seals(borrowed_c2p, "C2P")?;
+            let c2p_len = fstat(borrowed_c2p)?.st_size as usize;
+            // Exactly the payload area (a Go or C parent), or that plus the trailer.
+            if c2p_len != c2p_size && c2p_len != c2p_map_len(c2p_size) {
+                return Err(EfdStreamError::ShmSizeMismatch { parent: c2p_len, child: c2p_size });
+            }
+            let map_len = c2p_len;
+            shm_c2p = self.retry.run(|| SharedRegion::map(borrowed_c2p, map_len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE))?;
+            advise_region(shm_c2p.as_ptr(), c2p_size, self.advice)?;
+        }
+        if self.lock_memory {
+            lock_regions(&[&shm_p2c, &shm_c2p])?;
+        }
 
-        self.file_c2p_send = Some(File::from(OwnedFd::from(efd_c2p_send)));
-        self.file_c2p_ack = Some(File::from(OwnedFd::from(efd_c2p_ack)));
-        self.shm_c2p_file = Some(File::from(OwnedFd::from(memfd_c2p)));
+        Ok((shm_p2c, shm_c2p))
+    }
 
+    // Switches to the regions `ShmParent::resize_shm` grew. The new size is
+    // at P2C offset 0; the mappings are left alone unless both remap.
+    fn follow_resize(&mut self) -> Result<()> {
+        let announce = unsafe { slice::from_raw_parts(self.shm_p2c.as_ptr(), DOORBELL_LEN) };
+        let new_size = self.endianness.decode(announce.try_into().unwrap()) as usize;
+        if new_size < self.p2c_size.min(self.c2p_size) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Resize would shrink SHM").into());
+        }
+        let (p2c_size, c2p_size) = (self.p2c_size.max(new_size), self.c2p_size.max(new_size));
+        let (shm_p2c, shm_c2p) = self.map_regions(p2c_size, c2p_size)?;
+        // Handles are pointed at the new control block before the old
+        // mapping goes away.
+        let pause = self.pause.clone();
+        let mut word = pause.as_ref().map(|word| word.lock().unwrap_or_else(PoisonError::into_inner));
+        self.shm_p2c = shm_p2c;
+        let _old_c2p = std::mem::replace(&mut self.shm_c2p, shm_c2p);
+        self.p2c_size = p2c_size;
+        self.c2p_size = c2p_size;
+        if let Some(word) = word.as_mut() {
+            **word = unsafe { pause_at(self.shm_c2p.as_ptr(), self.c2p_size) } as *const AtomicU64 as usize;
+        }
         Ok(())
     }
 
-    pub fn send_data(&mut self, data: &[u8]) -> std::io::Result<()> {
-        if data.len() > self.shm_size {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Data too large for SHM"));
+    /// A handle for asking the parent to pause sending, usable from worker
+    /// threads while this child is in `listen`; see `PauseHandle`. Maps the
+    /// regions first if `init` hasn't run. Fails with `Unsupported` under a
+    /// Go or C parent, which reserves no control block.
+    pub fn pause_handle(&mut self) -> Result<PauseHandle> {
+        if !self.initialized() {
+            self.init()?;
         }
-        if self.shm_p2c_ptr.is_null() {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Not started"));
+        if self.control_atomic().is_none() {
+            return Err(no_control_block());
         }
+        let (c2p, size) = (self.shm_c2p.as_ptr(), self.c2p_size);
+        let word = self.pause.get_or_insert_with(|| {
+            Arc::new(Mutex::new(unsafe { pause_at(c2p, size) } as *const AtomicU64 as usize))
+        });
+        Ok(PauseHandle { word: Arc::clone(word) })
+    }
 
-        // Write to SHM
-        unsafe {
-            ptr::copy_nonoverlapping(data.as_ptr(), self.shm_p2c_ptr, data.len());
+    /// Largest payload `send_to_peer` accepts: the C2P size, i.e. `-c2p-size`
+    /// if the parent passed one and `-shm-size` otherwise.
+    pub fn usable_size(&self) -> usize {
+        self.c2p_size
+    }
+
+    /// The word `ShmParent::control_atomic` exposes. `None` before `init`,
+    /// or when the parent did not reserve a control block (Go and C parents).
+    pub fn control_atomic(&self) -> Option<&AtomicU64> {
+        if self.shm_c2p.is_null() || self.shm_c2p.len() < c2p_map_len(self.c2p_size) {
+            return None;
         }
+        Some(unsafe { control_at(self.shm_c2p.as_ptr(), self.c2p_size) })
+    }
 
-        // Send Length
-        if let Some(file_send) = &mut self.file_p2c_send {
-            let len = data.len() as u64;
-            let bytes = len.to_ne_bytes();
-            file_send.write_all(&bytes)?;
+    /// The child half of `ShmParent::shared_state`. `None` before `init`, or
+    /// when the parent passed no `-fd-state`.
+    pub fn shared_state(&self) -> Option<&[u8]> {
+        if self.shm_state.is_null() {
+            return None;
         }
+        Some(unsafe { slice::from_raw_parts(self.shm_state.as_ptr(), self.shm_state.len()) })
+    }
 
-        // Wait for ACK
-        if let Some(file_ack) = &mut self.file_p2c_ack {
-            let mut buf = [0u8; 8];
-            file_ack.read_exact(&mut buf)?;
+    pub fn shared_state_mut(&mut self) -> Option<&mut [u8]> {
+        if self.shm_state.is_null() {
+            return None;
         }
+        Some(unsafe { slice::from_raw_parts_mut(self.shm_state.as_ptr(), self.shm_state.len()) })
+    }
 
-        Ok(())
+    /// The child half of `ShmParent::shm_base`: where this side mapped the
+    /// region. `None` before `init`, for a direction the session doesn't
+    /// carry and for `Direction::Bidirectional`.
+    pub fn shm_base(&self, dir: Direction) -> Option<*const u8> {
+        let region = match dir {
+            Direction::ParentToChild => &self.shm_p2c,
+            Direction::ChildToParent => &self.shm_c2p,
+            Direction::Bidirectional => return None,
+        };
+        (!region.is_null()).then(|| region.as_ptr() as *const u8)
+    }
+
+    /// The P2C doorbell eventfd `listen` waits on. The child's descriptors
+    /// are whatever it was given, so these are available before `init`.
+    /// Each is `None` for the direction a simplex session doesn't carry.
+    pub fn p2c_send_fd(&self) -> Option<BorrowedFd<'_>> {
+        borrow_channel_fd(self.fd_p2c_send)
+    }
+
+    pub fn p2c_ack_fd(&self) -> Option<BorrowedFd<'_>> {
+        borrow_channel_fd(self.fd_p2c_ack)
     }
 
-    pub fn read_data(&mut self) -> std::io::Result<Vec<u8>> {
-        if self.shm_c2p_ptr.is_null() {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Not started"));
+    pub fn c2p_send_fd(&self) -> Option<BorrowedFd<'_>> {
+        borrow_channel_fd(self.fd_c2p_send)
+    }
+
+    pub fn c2p_ack_fd(&self) -> Option<BorrowedFd<'_>> {
+        borrow_channel_fd(self.fd_c2p_ack)
+    }
+
+    /// The memfd behind the P2C (`Direction::ParentToChild`) or C2P
+    /// (`Direction::ChildToParent`) region, for handing on to a process this
+    /// child spawns. Borrowed from the child; dup it to keep it past the
+    /// child's lifetime, as the spawn's dup2 does. The grandchild must be
+    /// given the same sizes, and sees a resize only by mapping again.
+    /// Fails with `InvalidInput` for `Direction::Bidirectional`, which names
+    /// no single region, and with `WrongDirection` for a direction the
+    /// session doesn't carry.
+    pub fn shm_fd(&self, dir: Direction) -> Result<BorrowedFd<'_>> {
+        match dir {
+            Direction::ParentToChild => self.channel_fd(borrow_channel_fd(self.fd_p2c_shm)),
+            Direction::ChildToParent => self.channel_fd(borrow_channel_fd(self.fd_c2p_shm)),
+            Direction::Bidirectional => {
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "shm_fd takes the direction of one region").into())
+            }
         }
+    }
 
-        // Wait for Signal
-        let length = if let Some(file_read) = &mut self.file_c2p_send {
-            let mut buf = [0u8; 8];
-            file_read.read_exact(&mut buf)?;
-            u64::from_ne_bytes(buf) as usize
-        } else {
-            return Err(std::io::Error::new(std::io::ErrorKind::Other, "Not started"));
+    // A channel fd the caller needs, or `WrongDirection` if the session
+    // leaves it out.
+    fn channel_fd<'a>(&self, fd: Option<BorrowedFd<'a>>) -> Result<BorrowedFd<'a>> {
+        fd.ok_or(EfdStreamError::WrongDirection { direction: self.direction })
+    }
+
+    /// The control eventfd, if the child was given one.
+    pub fn control_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.fd_control.map(|fd| unsafe { BorrowedFd::borrow_raw(fd) })
+    }
+
+    // Takes the bits `ShmParent::send_control` raised, if any. A wakeup
+    // whose bits were taken with an earlier one is drained first, so the
+    // word is read after the last ring that could belong to it.
+    fn take_control(&self) -> Result<u64> {
+        let Some(control) = self.control_fd() else {
+            return Ok(0);
+        };
+        if self.shm_c2p.is_null() || self.shm_c2p.len() < c2p_map_len(self.c2p_size) {
+            return Ok(0);
+        }
+        if is_readable(control)? {
+            eventfd_read(control)?;
+        }
+        Ok(unsafe { control_events_at(self.shm_c2p.as_ptr(), self.c2p_size) }.swap(0, Ordering::SeqCst))
+    }
+
+    fn answer_hello(&mut self) -> Result<()> {
+        let fd_read = unsafe { BorrowedFd::borrow_raw(self.fd_p2c_send) };
+        let fd_write = unsafe { BorrowedFd::borrow_raw(self.fd_p2c_ack) };
+
+        let length = eventfd_read(fd_read)? as usize;
+        let record = (length <= self.p2c_size)
+            .then(|| unsafe { slice::from_raw_parts(self.shm_p2c.as_ptr(), length) });
+        // An offer without an order flag is in the parent's native order;
+        // spelling that out makes a mismatch with ours an unsupported flag.
+        let offer = record.and_then(Hello::decode).map(|mut offer| {
+            offer.flags |= handshake::order_flag(handshake::offered_order(offer.flags));
+            offer
+        });
+        let order = self.endianness.resolve();
+
+        #[cfg(feature = "crypto")]
+        let (supported_flags, key_ok) = match &self.key {
+            Some(key) => {
+                self.cipher = record.and_then(|record| FrameCipher::accept(key, record));
+                (handshake::FLAG_ENCRYPTED, self.cipher.is_some())
+            }
+            None => (0, true),
         };
+        #[cfg(not(feature = "crypto"))]
+        let (supported_flags, key_ok) = (0, true);
+
+        // Always answer, even on mismatch, so the parent never blocks.
+        let supported_flags = supported_flags | handshake::FLAG_TIMESTAMPS | handshake::order_flag(order);
+        let (reply, outcome) = Hello::answer(offer, supported_flags, key_ok);
+        eventfd_write(fd_write, reply.encode_answer())?;
+
+        self.timestamps = outcome?.flags & handshake::FLAG_TIMESTAMPS != 0;
+        Ok(())
+    }
 
-        if length > self.shm_size {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Received length exceeds SHM size"));
+    pub fn listen<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: Fn(&[u8]),
+    {
+        self.deliver_until(|payload| {
+            callback(payload);
+            true
+        })
+    }
+
+    /// `listen` whose callback gets each message as a `FrameGuard` rather
+    /// than a slice. The message is ACKed when the guard is dropped, not when
+    /// the callback returns, so the callback may hand it to a scoped thread
+    /// instead of copying it. The parent stays blocked meanwhile.
+    pub fn listen_guarded<F>(&mut self, mut callback: F) -> Result<()>
+    where
+        F: FnMut(FrameGuard<'_>),
+    {
+        if !self.initialized() {
+            self.init()?;
         }
 
-        // Read from SHM
-        let data = unsafe { slice::from_raw_parts(self.shm_c2p_ptr, length).to_vec() };
+        loop {
+            match self.take_guarded(|frame, _| callback(frame))? {
+                Doorbell::Frame(()) | Doorbell::Skipped => {}
+                Doorbell::Eof => return Ok(()),
+            }
+        }
+    }
 
-        // Send ACK
-        if let Some(file_write) = &mut self.file_c2p_ack {
-            let ack_val: u64 = 1;
-            let bytes = ack_val.to_ne_bytes();
-            file_write.write_all(&bytes)?;
+    /// `listen` whose callback returns a status for each message, which the
+    /// parent's `ShmParent::send_data_with_status` returns once the message
+    /// is ACKed. Fails at once if the parent reserved no control block to
+    /// carry it (Go and C parents).
+    pub fn listen_with_status<F>(&mut self, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> u64,
+    {
+        if !self.initialized() {
+            self.init()?;
+        }
+        if self.control_atomic().is_none() {
+            return Err(no_control_block());
         }
 
-        Ok(data)
+        loop {
+            // Looked up each time, as a resize moves the control block.
+            let status = unsafe { status_at(self.shm_c2p.as_ptr(), self.c2p_size) };
+            // The guard ACKs when it is dropped, after the store.
+            match self.take_guarded(|frame, _| status.store(callback(&frame), Ordering::SeqCst))? {
+                Doorbell::Frame(()) | Doorbell::Skipped => {}
+                Doorbell::Eof => return Ok(()),
+            }
+        }
     }
-}
 
-impl Drop for ShmParent {
-    fn drop(&mut self) {
-        if !self.shm_p2c_ptr.is_null() {
-            unsafe {
-                if let Some(ptr) = NonNull::new(self.shm_p2c_ptr as *mut std::ffi::c_void) {
-                    let _ = munmap(ptr, self.shm_size);
+    /// `listen` for RPC-style use: whatever `handler` returns for a request
+    /// is sent back as the reply, which the parent picks up with `recv_from_peer`
+    /// after its `send_to_peer`. The request is ACKed before the reply is sent,
+    /// since the parent only starts reading once its send has returned.
+    pub fn listen_request_response<F>(&mut self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> Vec<u8>,
+    {
+        if !self.initialized() {
+            self.init()?;
+        }
+
+        loop {
+            match self.take_doorbell(|request, _| handler(request))? {
+                Doorbell::Frame(reply) => self.send_to_peer(&reply)?,
+                Doorbell::Eof => return Ok(()),
+                Doorbell::Skipped => {}
+            }
+        }
+    }
+
+    /// `listen` whose callback can answer the request it is handling through
+    /// the `Responder`, with any number of replies, or none. They go out
+    /// after the request's ACK, each like a `send_to_peer`.
+    pub fn listen_with_responder<F>(&mut self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(&[u8], &mut Responder),
+    {
+        if !self.initialized() {
+            self.init()?;
+        }
+
+        let pause = self.pause_handle().ok();
+        let mut responder = Responder { replies: Vec::new(), capacity: 0, pause, error_area: false, error: None };
+        loop {
+            // A resize may have grown the region since the last request.
+            let overhead = frame_overhead(self.cipher.is_some(), self.timestamps);
+            responder.capacity = self.c2p_size.saturating_sub(overhead);
+            let (c2p, size) = (self.shm_c2p.as_ptr(), self.c2p_size);
+            responder.error_area = self.shm_c2p.len() >= c2p_map_len(size);
+            let handled = self.take_guarded(|mut request, _| {
+                handler(&request, &mut responder);
+                if let Some((code, message)) = responder.error.take() {
+                    unsafe { write_child_error(c2p, size, code, &message) };
+                    request.value = ERROR_ACK;
+                    responder.replies.clear();
+                }
+            });
+            match handled? {
+                Doorbell::Frame(()) => {
+                    for reply in responder.replies.drain(..) {
+                        self.send_to_peer(&reply)?;
+                    }
+                }
+                Doorbell::Eof => return Ok(()),
+                Doorbell::Skipped => {}
+            }
+        }
+    }
+
+    /// `listen` that sends each payload down `tx` instead of calling back,
+    /// so worker threads can process at their own pace. The ACK follows the
+    /// copy straight away, which removes back-pressure: the parent can send
+    /// as fast as the child copies, and unprocessed messages pile up in the
+    /// channel. `listen_bounded` keeps it. Fails with `BrokenPipe` once the
+    /// receiver is dropped.
+    pub fn listen_async(&mut self, tx: Sender<Vec<u8>>) -> Result<()> {
+        self.deliver_until(|payload| tx.send(payload.to_vec()).is_ok())
+    }
+
+    /// `listen_async` over a bounded channel: each payload is ACKed only once
+    /// the channel has room for it, so a backlog throttles the parent the
+    /// way a slow `listen` callback does.
+    pub fn listen_bounded(&mut self, tx: SyncSender<Vec<u8>>) -> Result<()> {
+        self.deliver_until(|payload| tx.send(payload.to_vec()).is_ok())
+    }
+
+    // The `listen` loop. `deliver` runs before each ACK and returns false
+    // once it can't take any more.
+    fn deliver_until(&mut self, mut deliver: impl FnMut(&[u8]) -> bool) -> Result<()> {
+        if !self.initialized() {
+            self.init()?;
+        }
+
+        loop {
+            match self.take_doorbell(|payload, _| deliver(payload))? {
+                // The parent called `shutdown_send`; sending still works.
+                Doorbell::Eof => return Ok(()),
+                Doorbell::Frame(false) => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Channel receiver dropped").into());
                 }
+                Doorbell::Frame(true) | Doorbell::Skipped => {}
+            }
+        }
+    }
+
+    /// Blocks for the next message from the parent, for callers that want to
+    /// pull messages instead of handing `listen` a callback. Fails with
+    /// `EfdStreamError::PeerClosed` once the parent has called `shutdown_send`.
+    pub fn recv_from_peer(&mut self) -> Result<Vec<u8>> {
+        if !self.initialized() {
+            self.init()?;
+        }
+        loop {
+            match self.take_doorbell(|payload, _| payload.to_vec())? {
+                Doorbell::Frame(data) => return Ok(data),
+                Doorbell::Eof => return Err(EfdStreamError::PeerClosed),
+                Doorbell::Skipped => {}
+            }
+        }
+    }
+
+    #[deprecated(note = "use `recv_from_peer`")]
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        self.recv_from_peer()
+    }
+
+    /// The child half of `ShmParent::skip_frame`: waits for the next
+    /// message and ACKs it without copying the payload out.
+    pub fn skip_frame(&mut self) -> Result<()> {
+        if !self.initialized() {
+            self.init()?;
+        }
+        loop {
+            match self.take_doorbell(|_, _| ())? {
+                Doorbell::Frame(()) => return Ok(()),
+                Doorbell::Eof => return Err(EfdStreamError::PeerClosed),
+                Doorbell::Skipped => {}
             }
         }
-        if !self.shm_c2p_ptr.is_null() {
-            unsafe {
-                if let Some(ptr) = NonNull::new(self.shm_c2p_ptr as *mut std::ffi::c_void) {
-                    let _ = munmap(ptr, self.shm_size);
+    }
+
+    /// The child half of `ShmParent::read_data_into_array`. A message
+    /// longer than `N` is ACKed and discarded with `InvalidInput`.
+    pub fn read_data_into_array<const N: usize>(&mut self) -> Result<([u8; N], usize)> {
+        if !self.initialized() {
+            self.init()?;
+        }
+        loop {
+            let copied = self.take_doorbell(|payload, _| {
+                let mut buf = [0; N];
+                buf.get_mut(..payload.len())?.copy_from_slice(payload);
+                Some((buf, payload.len()))
+            })?;
+            match copied {
+                Doorbell::Frame(Some(read)) => return Ok(read),
+                Doorbell::Frame(None) => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Payload exceeds buffer capacity").into());
                 }
+                Doorbell::Eof => return Err(EfdStreamError::PeerClosed),
+                Doorbell::Skipped => {}
             }
         }
-        if let Some(mut child) = self.child.take() {
-            let _ = child.kill();
+    }
+
+    /// The child half of `ShmParent::read_data_arc`. A `FrameGuard` from
+    /// `listen_guarded` avoids the copy, but keeps the parent blocked.
+    pub fn read_data_arc(&mut self) -> Result<Arc<[u8]>> {
+        if !self.initialized() {
+            self.init()?;
+        }
+        loop {
+            match self.take_doorbell(|payload, _| Arc::from(payload))? {
+                Doorbell::Frame(data) => return Ok(data),
+                Doorbell::Eof => return Err(EfdStreamError::PeerClosed),
+                Doorbell::Skipped => {}
+            }
         }
     }
-}
 
-pub struct ShmChild {
-    fd_p2c_send: RawFd,
-    fd_p2c_ack: RawFd,
-    fd_p2c_shm: RawFd,
-    fd_c2p_send: RawFd,
-    fd_c2p_ack: RawFd,
-    fd_c2p_shm: RawFd,
-    shm_size: usize,
-    shm_p2c_ptr: *mut u8,
-    shm_c2p_ptr: *mut u8,
-}
+    /// The child half of `ShmParent::send_data_with_meta`. Fails with
+    /// `Unsupported` under a Go or C parent, which reserves no sidecar.
+    pub fn read_data_with_meta<const N: usize>(&mut self) -> Result<(Vec<u8>, [u8; N])> {
+        const { assert!(N <= META_LEN, "metadata larger than META_LEN") };
+        self.direction.require(Direction::ParentToChild)?;
+        if !self.initialized() {
+            self.init()?;
+        }
+        if self.shm_c2p.len() < c2p_map_len(self.c2p_size) {
+            return Err(no_meta_area());
+        }
+        loop {
+            // Looked up per frame: a resize moves the sidecar.
+            let (c2p, size) = (self.shm_c2p.as_ptr(), self.c2p_size);
+            match self.take_doorbell(|payload, _| (payload.to_vec(), unsafe { read_meta(c2p, size, P2C_META) }))? {
+                Doorbell::Frame(framed) => return Ok(framed),
+                Doorbell::Eof => return Err(EfdStreamError::PeerClosed),
+                Doorbell::Skipped => {}
+            }
+        }
+    }
 
-unsafe impl Send for ShmChild {}
+    /// `recv_from_peer` that also returns the parent's `frame::monotonic_ns`
+    /// reading from when it sent the message. Needs a parent built with
+    /// `ShmParentBuilder::timestamps`. Checked like `ShmParent::read_timed`.
+    pub fn read_timed(&mut self) -> Result<(Vec<u8>, u64)> {
+        if !self.initialized() {
+            self.init()?;
+        }
+        if !self.timestamps {
+            return Err(not_timestamped());
+        }
+        loop {
+            match self.take_doorbell(|payload, sent| (payload.to_vec(), sent.expect("frames are timestamped")))? {
+                Doorbell::Frame((data, sent)) => return Ok((data, sent)),
+                Doorbell::Eof => return Err(EfdStreamError::PeerClosed),
+                Doorbell::Skipped => {}
+            }
+        }
+    }
 
-impl ShmChild {
-    pub fn new(fd_p2c_send: RawFd, fd_p2c_ack: RawFd, fd_p2c_shm: RawFd,
-               fd_c2p_send: RawFd, fd_c2p_ack: RawFd, fd_c2p_shm: RawFd,
-               shm_size: usize) -> Self {
-        Self { 
-            fd_p2c_send, fd_p2c_ack, fd_p2c_shm,
-            fd_c2p_send, fd_c2p_ack, fd_c2p_shm,
-            shm_size, 
-            shm_p2c_ptr: ptr::null_mut(),
-            shm_c2p_ptr: ptr::null_mut(),
+    /// `recv_from_peer` that returns `None` instead of blocking when the parent
+    /// hasn't sent anything.
+    pub fn try_read_data(&mut self) -> Result<Option<Vec<u8>>> {
+        self.direction.require(Direction::ParentToChild)?;
+        if !self.initialized() {
+            self.init()?;
+        }
+        if !self.recv_shut_down && !self.doorbell_waiting()? {
+            return match self.take_control()? {
+                0 => Ok(None),
+                bits => Err(EfdStreamError::Control { bits }),
+            };
+        }
+        match self.take_doorbell(|payload, _| payload.to_vec())? {
+            Doorbell::Frame(data) => Ok(Some(data)),
+            Doorbell::Eof => Err(EfdStreamError::PeerClosed),
+            Doorbell::Skipped => Ok(None),
         }
     }
 
-    pub fn init(&mut self) -> std::io::Result<()> {
-        // Mmap P2C (Read)
-        let borrowed_p2c = unsafe { BorrowedFd::borrow_raw(self.fd_p2c_shm) };
-        let ptr_p2c = unsafe {
-            mmap(None, std::num::NonZeroUsize::new(self.shm_size).unwrap(),
-                ProtFlags::PROT_READ, MapFlags::MAP_SHARED, borrowed_p2c, 0)
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?
+    // Whether either P2C doorbell is ringing.
+    fn doorbell_waiting(&self) -> Result<bool> {
+        if let Some([send, _, _]) = self.fd_priority
+            && is_readable(unsafe { BorrowedFd::borrow_raw(send) })? {
+            return Ok(true);
+        }
+        is_readable(self.channel_fd(self.p2c_send_fd())?)
+    }
+
+    // Reads one P2C doorbell and, if it announces a frame, hands the payload
+    // and its send time to `f` before ACKing.
+    fn take_doorbell<R>(&mut self, f: impl FnOnce(&[u8], Option<u64>) -> R) -> Result<Doorbell<R>> {
+        self.take_guarded(|frame, sent| f(&frame, sent))
+    }
+
+    // `take_doorbell` that leaves the ACK to the guard `f` is given. A
+    // waiting priority doorbell is taken before the normal one. Once the EOF
+    // sentinel has been seen it is reported again without blocking. Control
+    // bits raised while waiting end the wait with `EfdStreamError::Control`.
+    fn take_guarded<R>(&mut self, f: impl FnOnce(FrameGuard<'_>, Option<u64>) -> R) -> Result<Doorbell<R>> {
+        #[cfg(feature = "tracing")]
+        let _span = trace::receive_span("p2c").entered();
+        #[cfg(feature = "tracing")]
+        let f = |frame: FrameGuard<'_>, sent| {
+            trace::record_size(frame.len());
+            f(frame, sent)
+        };
+        let result = self.wait_frame(f);
+        #[cfg(feature = "tracing")]
+        trace::finish(&result);
+        result
+    }
+
+    fn wait_frame<R>(&mut self, f: impl FnOnce(FrameGuard<'_>, Option<u64>) -> R) -> Result<Doorbell<R>> {
+        self.direction.require(Direction::ParentToChild)?;
+        if self.recv_shut_down {
+            return Ok(Doorbell::Eof);
+        }
+        let fd_read = unsafe { BorrowedFd::borrow_raw(self.fd_p2c_send) };
+        let fd_write = unsafe { BorrowedFd::borrow_raw(self.fd_p2c_ack) };
+
+        // With only the one doorbell, the read below does the waiting.
+        let priority = self.fd_priority.map(|[send, _, _]| unsafe { BorrowedFd::borrow_raw(send) });
+        let rendezvous = self.rendezvous_fd().map(|fd| unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) });
+        if priority.is_some() || self.fd_control.is_some() || rendezvous.is_some() {
+            // Listed by precedence: priority, normal, control, then the
+            // rendezvous socket.
+            let control = self.fd_control.map(|fd| unsafe { BorrowedFd::borrow_raw(fd) });
+            let watched: Vec<BorrowedFd> = priority.into_iter().chain([fd_read]).chain(control).chain(rendezvous).collect();
+            let normal = usize::from(priority.is_some());
+            loop {
+                match self.take_control()? {
+                    0 => {}
+                    bits => return Err(EfdStreamError::Control { bits }),
+                }
+                match wait_any(&watched)? {
+                    ready if ready < normal => {
+                        let length = self.endianness.from_wire(eventfd_read(watched[ready])?);
+                        return self.deliver_frame(length, true, f);
+                    }
+                    ready if ready == normal => break,
+                    ready if rendezvous.is_some() && ready == watched.len() - 1 => {
+                        self.reconnect_now()?;
+                        return self.wait_frame(f);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let order = self.endianness;
+        match order.from_wire(eventfd_read(fd_read)?) {
+            EOF_DOORBELL => {
+                self.recv_shut_down = true;
+                Ok(Doorbell::Eof)
+            }
+            // ACK 2 tells the parent to keep its old mappings too.
+            RESIZE_DOORBELL => {
+                let resized = self.follow_resize();
+                eventfd_write(fd_write, order.to_wire(if resized.is_ok() { 1 } else { 2 }))?;
+                resized.map(|_| Doorbell::Skipped)
+            }
+            // The parent has emptied the other eventfds; a stale ACK for our
+            // own sends is the only count left to drop.
+            RESET_DOORBELL => {
+                if let Some(fd) = self.c2p_ack_fd() {
+                    drain_eventfd(fd)?;
+                }
+                eventfd_write(fd_write, order.to_wire(1))?;
+                self.answer_hello()?;
+                Ok(Doorbell::Skipped)
+            }
+            length => self.deliver_frame(length, false, f),
+        }
+    }
+
+    // Hands the frame of `length` bytes to `f` in a guard that ACKs it, on
+    // the priority channel or the normal one.
+    fn deliver_frame<R>(&mut self, length: u64, priority: bool, f: impl FnOnce(FrameGuard<'_>, Option<u64>) -> R) -> Result<Doorbell<R>> {
+        // Without a second buffer the flag is left in, so the length is
+        // refused as too large.
+        let second = !priority && length & SECOND_BUFFER != 0 && self.shm_p2c.len() >= 2 * self.p2c_size;
+        let (shm, size, ack) = match self.fd_priority {
+            Some([_, ack, _]) if priority => (self.shm_priority.as_ptr(), self.priority_size, ack),
+            _ if second => (unsafe { self.shm_p2c.as_ptr().add(self.p2c_size) }, self.p2c_size, self.fd_p2c_ack),
+            _ => (self.shm_p2c.as_ptr(), self.p2c_size, self.fd_p2c_ack),
         };
-        self.shm_p2c_ptr = ptr_p2c.as_ptr() as *mut u8;
+        let length = if second { length & !SECOND_BUFFER } else { length };
+        let fd_write = unsafe { BorrowedFd::borrow_raw(ack) };
+        let order = self.endianness;
+        // Only a Rust parent's normal channel has a spin word.
+        let signal = (!priority && self.shm_c2p.len() >= c2p_map_len(self.c2p_size))
+            .then(|| unsafe { ack_signal_at(self.shm_c2p.as_ptr(), self.c2p_size) });
 
-        // Mmap C2P (Write)
-        let borrowed_c2p = unsafe { BorrowedFd::borrow_raw(self.fd_c2p_shm) };
-        let ptr_c2p = unsafe {
-            mmap(None, std::num::NonZeroUsize::new(self.shm_size).unwrap(),
-                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE, MapFlags::MAP_SHARED, borrowed_c2p, 0)
-            .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?
+        // Read from SHM
+        let shm = unsafe { slice::from_raw_parts(shm, size) };
+        let data = match decode_frame(&length.to_ne_bytes(), shm, size) {
+            Ok(data) => data,
+            // Either way the parent gets a NACK, so its send fails
+            // rather than waiting for an ACK that never comes.
+            Err(e) => {
+                write_ack(fd_write, order, NACK, signal)?;
+                return match self.oversize_policy {
+                    OversizePolicy::Skip => {
+                        #[cfg(feature = "log")]
+                        log::warn!("Skipped a {}-byte frame larger than the {}-byte region", length, size);
+                        #[cfg(feature = "tracing")]
+                        trace::oversize_skipped(length, size);
+                        Ok(Doorbell::Skipped)
+                    }
+                    OversizePolicy::Fail => Err(e),
+                };
+            }
+        };
+        let stamp = self.stamp();
+        let deliver = |frame: &[u8]| unstamp(frame, stamp).map(|(sent, payload)| {
+            f(FrameGuard { payload, ack: fd_write, signal, order, value: 1, acked: false }, sent)
+        });
+        // Priority frames are never sealed; see `ShmParent::send_priority`.
+        let result = match self.cipher.as_mut().filter(|_| !priority) {
+            Some(cipher) => cipher.open(data).and_then(|plain| deliver(&plain)),
+            None => deliver(data),
         };
-        self.shm_c2p_ptr = ptr_c2p.as_ptr() as *mut u8;
 
+        // The guard has ACKed a delivered frame; one that failed to open or
+        // unstamp never reached `f`, and the parent's send fails.
+        if result.is_err() {
+            write_ack(fd_write, order, NACK, signal)?;
+        }
+        result.map(Doorbell::Frame)
+    }
+
+    /// The child half of `ShmParent::shutdown_send`: the parent's reads fail
+    /// with `PeerClosed` once they reach it, while `listen` and
+    /// `recv_from_peer` keep working. Go and C parents log the sentinel as an
+    /// oversized frame.
+    pub fn shutdown_send(&mut self) -> Result<()> {
+        self.direction.require(Direction::ChildToParent)?;
+        if self.send_shut_down {
+            return Ok(());
+        }
+        // The parent does not ACK the sentinel.
+        eventfd_write(self.channel_fd(self.c2p_send_fd())?, self.endianness.to_wire(EOF_DOORBELL))?;
+        self.send_shut_down = true;
         Ok(())
     }
 
-    pub fn listen<F>(&mut self, callback: F) -> std::io::Result<()>
-    where
-        F: Fn(&[u8]),
-    {
-        if self.shm_p2c_ptr.is_null() {
+    /// Sends `data` to the parent and waits for its ACK.
+    pub fn send_to_peer(&mut self, data: &[u8]) -> Result<()> {
+        self.send_data_counted(data).map(|_| ())
+    }
+
+    #[deprecated(note = "use `send_to_peer`")]
+    pub fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        self.send_to_peer(data)
+    }
+
+    /// The child half of `ShmParent::read_data_with_meta`; see
+    /// `ShmParent::send_data_with_meta`.
+    pub fn send_data_with_meta<const N: usize>(&mut self, data: &[u8], meta: &[u8; N]) -> Result<()> {
+        const { assert!(N <= META_LEN, "metadata larger than META_LEN") };
+        self.direction.require(Direction::ChildToParent)?;
+        if !self.initialized() {
             self.init()?;
         }
+        if self.shm_c2p.len() < c2p_map_len(self.c2p_size) {
+            return Err(no_meta_area());
+        }
+        // The parent has ACKed our last frame, so it is done with the sidecar.
+        unsafe { write_meta(self.shm_c2p.as_ptr(), self.c2p_size, C2P_META, meta) };
+        self.send_to_peer(data)
+    }
 
-        let mut file_read = unsafe { File::from_raw_fd(self.fd_p2c_send) };
-        let mut file_write = unsafe { File::from_raw_fd(self.fd_p2c_ack) };
+    /// Sends `items` as one streamed response, each as its own frame and
+    /// then an end marker, for `ShmParent::read_stream`. An item too large
+    /// for the region fails the stream partway, with no end marker sent.
+    pub fn send_stream(&mut self, items: impl IntoIterator<Item = Vec<u8>>) -> Result<()> {
+        for item in items {
+            self.send_to_peer(&stream_frame(&item))?;
+        }
+        self.send_to_peer(&[STREAM_END])
+    }
 
-        loop {
-            let mut buf = [0u8; 8];
-            match file_read.read_exact(&mut buf) {
-                Ok(_) => {
-                    let length = u64::from_ne_bytes(buf) as usize;
-                    if length > self.shm_size {
-                        eprintln!("Received length {} exceeds SHM size {}", length, self.shm_size);
-                        continue;
-                    }
+    /// `send_to_peer` that returns how many bytes were written to SHM.
+    pub fn send_data_counted(&mut self, data: &[u8]) -> Result<usize> {
+        self.send_pieces(&[data])
+    }
 
-                    // Read from SHM
-                    let data = unsafe { slice::from_raw_parts(self.shm_p2c_ptr, length) };
-                    callback(data);
+    /// The child half of `ShmParent::send_data_vectored`: one message from
+    /// several slices, checked as a whole before any is copied.
+    pub fn send_data_vectored(&mut self, bufs: &[&[u8]]) -> Result<()> {
+        self.send_pieces(bufs).map(|_| ())
+    }
 
-                    // Send Ack (1)
-                    let ack_val: u64 = 1;
-                    let bytes = ack_val.to_ne_bytes();
-                    file_write.write_all(&bytes)?;
-                }
-                Err(e) => return Err(e),
-            }
-        }
+    fn send_pieces(&mut self, pieces: &[&[u8]]) -> Result<usize> {
+        #[cfg(feature = "tracing")]
+        let _span = trace::send_span("c2p", pieces.iter().map(|piece| piece.len()).sum()).entered();
+        let result = self.send_frame(pieces);
+        #[cfg(feature = "tracing")]
+        trace::finish(&result);
+        result
     }
 
-    pub fn send_data(&mut self, data: &[u8]) -> std::io::Result<()> {
-        if self.shm_c2p_ptr.is_null() {
+    fn send_frame(&mut self, pieces: &[&[u8]]) -> Result<usize> {
+        self.direction.require(Direction::ChildToParent)?;
+        if !self.initialized() {
             self.init()?;
         }
-        if data.len() > self.shm_size {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Data too large for SHM"));
+        let len = pieces.iter().try_fold(0usize, |len, piece| len.checked_add(piece.len()))
+            .ok_or(EfdStreamError::DataTooLarge { len: usize::MAX, cap: self.c2p_size })?;
+        let overhead = frame_overhead(self.cipher.is_some(), self.timestamps);
+        check_length(len + overhead)?;
+        if len + overhead > self.c2p_size {
+            return Err(EfdStreamError::DataTooLarge { len: len + overhead, cap: self.c2p_size });
+        }
+        if self.send_shut_down {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Send side shut down").into());
         }
 
         // Write to SHM
-        unsafe {
-            ptr::copy_nonoverlapping(data.as_ptr(), self.shm_c2p_ptr, data.len());
-        }
+        let (shm, size, stamp) = (self.shm_c2p.as_ptr(), self.c2p_size, self.stamp());
+        let frame_len = match pieces {
+            [data] => unsafe { write_frame_at(shm, size, self.cipher.as_mut(), stamp, data, self.nontemporal_threshold) },
+            _ => unsafe { write_pieces_at(shm, size, self.cipher.as_mut(), stamp, pieces, self.nontemporal_threshold) },
+        };
 
         // Send Length
-        let mut file_send = unsafe { File::from_raw_fd(self.fd_c2p_send) };
-        let len = data.len() as u64;
-        let bytes = len.to_ne_bytes();
-        file_send.write_all(&bytes)?;
-        // Prevent closing fd when file_send drops
-        let _ = file_send.into_raw_fd();
+        let fd_send = unsafe { BorrowedFd::borrow_raw(self.fd_c2p_send) };
+        eventfd_write(fd_send, self.endianness.to_wire(frame_len as u64))?;
 
         // Wait for ACK
-        let mut file_ack = unsafe { File::from_raw_fd(self.fd_c2p_ack) };
-        let mut buf = [0u8; 8];
-        file_ack.read_exact(&mut buf)?;
-        let _ = file_ack.into_raw_fd();
+        #[cfg(feature = "tracing")]
+        let sent_at = Instant::now();
+        let fd_ack = unsafe { BorrowedFd::borrow_raw(self.fd_c2p_ack) };
+        if let Some(rendezvous) = self.rendezvous_fd()
+            && !wait_readable_or_exit(fd_ack, Some(rendezvous), None)? {
+            return Err(parent_gone());
+        }
+        if self.endianness.from_wire(eventfd_read(fd_ack)?) == NACK {
+            return Err(EfdStreamError::Rejected);
+        }
+        #[cfg(feature = "tracing")]
+        trace::record_rtt(sent_at.elapsed());
 
-        Ok(())
+        Ok(frame_len)
     }
 }
 
 impl Drop for ShmChild {
+    // Runs before the regions are unmapped, so no handle writes after.
     fn drop(&mut self) {
-        if !self.shm_p2c_ptr.is_null() {
-            unsafe {
-                if let Some(ptr) = NonNull::new(self.shm_p2c_ptr as *mut std::ffi::c_void) {
-                    let _ = munmap(ptr, self.shm_size);
-                }
-            }
-        }
-        if !self.shm_c2p_ptr.is_null() {
-            unsafe {
-                if let Some(ptr) = NonNull::new(self.shm_c2p_ptr as *mut std::ffi::c_void) {
-                    let _ = munmap(ptr, self.shm_size);
-                }
-            }
+        if let Some(word) = &self.pause {
+            *word.lock().unwrap_or_else(PoisonError::into_inner) = 0;
         }
     }
 }
@@ -0,0 +1,177 @@
+use std::fmt;
+
+use crate::efd::Direction;
+
+#[derive(Debug)]
+pub enum EfdStreamError {
+    Io(std::io::Error),
+    /// The call needs the session's descriptors, and `start` (or another
+    /// way of setting them up) hasn't run, or they have been shut down.
+    NotStarted,
+    /// A message of `len` bytes, with its framing, doesn't fit the `cap`
+    /// bytes the transport holds. Nothing was sent.
+    DataTooLarge { len: usize, cap: usize },
+    /// The peer shut down its sending side, or closed its end of the
+    /// doorbell or socket: a clean end of stream rather than a fault.
+    PeerClosed,
+    /// The peer announced a frame of `len` bytes, more than the `cap` bytes
+    /// of the region it was to be read from.
+    LengthExceedsShm { len: u64, cap: usize },
+    /// The peer speaks a different protocol version. A peer that does not
+    /// answer the handshake at all is reported as version 0.
+    VersionMismatch { local: u32, peer: u32 },
+    /// The peer rejected framing flags (or a codec) it does not implement.
+    UnsupportedOption { flags: u32, codec: u32 },
+    /// A ring slot's generation word didn't match the sequence being read,
+    /// i.e. the slot was rewritten while (or before) it was copied out.
+    StaleRead { seq: u64 },
+    /// A deadline passed before the operation completed.
+    Timeout,
+    /// The payload length collides with a doorbell sentinel, or is 0, which
+    /// the doorbell can't carry. An empty message needs a framing option
+    /// that adds bytes, such as timestamps.
+    ReservedLength { len: u64 },
+    /// A frame failed authentication, or the peer's key doesn't match.
+    DecryptFailed,
+    /// The child exited while the parent was waiting on it. `signal` is the
+    /// signal that killed it, e.g. `SIGABRT` (6) for a Rust panic with
+    /// `panic = "abort"` or `SIGSEGV` (11) for a crash.
+    ChildDied { status: std::process::ExitStatus, signal: Option<i32> },
+    /// The parent raised `bits` with `ShmParent::send_control`, waking a
+    /// blocked receive. The bits are taken; the receive can simply be retried.
+    Control { bits: u64 },
+    /// `lock_memory` couldn't lock `requested` bytes because the process's
+    /// `RLIMIT_MEMLOCK` is `limit` bytes (`u64::MAX` if unlimited). Raise it,
+    /// e.g. with `ulimit -l`, or grant `CAP_IPC_LOCK`.
+    MemlockLimit { requested: usize, limit: u64 },
+    /// The child never completed the startup handshake: it exited with
+    /// `status` first, or, when `status` is `None`, was still silent after
+    /// `ShmParentBuilder::startup_timeout` and has been killed and reaped.
+    ChildSetupFailed { status: Option<std::process::ExitStatus> },
+    /// A ring send would leave more than `max` messages unread by the peer;
+    /// `in_flight` were outstanding. Nothing was sent.
+    TooManyInFlight { in_flight: usize, max: usize },
+    /// Two channels in the `FdLayout` were given `fd`, or `fd` is stdin,
+    /// stdout or stderr.
+    DuplicateFd { fd: i32 },
+    /// The peer NACKed the frame: the length it was announced with didn't
+    /// fit the peer's mapping of the region, or the frame failed to decrypt
+    /// or carried a bad timestamp, so nothing was delivered.
+    Rejected,
+    /// A timestamped frame claims to have been sent after `now`, the
+    /// receiver's `frame::monotonic_ns` reading, by more than
+    /// `frame::CLOCK_SKEW_TOLERANCE_NS`. The message was consumed and NACKed,
+    /// so the sender's send fails with `Rejected`.
+    ClockSkew { sent: u64, now: u64 },
+    /// The call needs a direction the session was not set up with, e.g. a
+    /// receive on a `Direction::ParentToChild` parent.
+    WrongDirection { direction: Direction },
+    /// `ShmParent::preflight` found `capability` (e.g. `"memfd_create"`)
+    /// unusable in this environment; `error` is what the attempt returned.
+    Unavailable { capability: &'static str, error: std::io::Error },
+    /// The child took the message, but its handler failed with `code` and
+    /// `message`, as given to `Responder::set_error`. The message may have
+    /// been cut short to `efd::ERROR_MESSAGE_LEN` bytes.
+    HandlerFailed { code: u32, message: String },
+    /// The child was configured for a `child`-byte region, but the memfd the
+    /// parent created is `parent` bytes long. For C2P, `parent` includes the
+    /// trailer a Rust parent appends after the payload area.
+    ShmSizeMismatch { parent: usize, child: usize },
+}
+
+pub type Result<T> = std::result::Result<T, EfdStreamError>;
+
+impl fmt::Display for EfdStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EfdStreamError::Io(e) => write!(f, "I/O error: {}", e),
+            EfdStreamError::NotStarted => write!(f, "not started"),
+            EfdStreamError::DataTooLarge { len, cap } => write!(f, "data of {} bytes too large for {}", len, cap),
+            EfdStreamError::PeerClosed => write!(f, "peer closed its end"),
+            EfdStreamError::LengthExceedsShm { len, cap } => {
+                write!(f, "received length {} exceeds SHM size {}", len, cap)
+            }
+            EfdStreamError::VersionMismatch { local, peer } => {
+                write!(f, "protocol version mismatch: local {}, peer {}", local, peer)
+            }
+            EfdStreamError::UnsupportedOption { flags, codec } => {
+                write!(f, "peer does not support options (flags {:#x}, codec {})", flags, codec)
+            }
+            EfdStreamError::StaleRead { seq } => write!(f, "ring slot for message {} was stale", seq),
+            EfdStreamError::Timeout => write!(f, "deadline exceeded"),
+            EfdStreamError::ReservedLength { len } => write!(f, "length {} is reserved for signalling", len),
+            EfdStreamError::DecryptFailed => write!(f, "payload failed to decrypt or authenticate"),
+            EfdStreamError::ChildDied { status, .. } => write!(f, "child died ({})", status),
+            EfdStreamError::Control { bits } => write!(f, "control event {:#x} from parent", bits),
+            EfdStreamError::ChildSetupFailed { status: Some(status) } => {
+                write!(f, "child exited before completing setup ({})", status)
+            }
+            EfdStreamError::ChildSetupFailed { status: None } => write!(f, "child did not complete setup in time"),
+            EfdStreamError::MemlockLimit { requested, limit } => {
+                write!(f, "cannot lock {} bytes of SHM: RLIMIT_MEMLOCK is {} bytes", requested, limit)
+            }
+            EfdStreamError::TooManyInFlight { in_flight, max } => {
+                write!(f, "{} messages in flight, at most {} allowed", in_flight, max)
+            }
+            EfdStreamError::Rejected => write!(f, "peer rejected the frame"),
+            EfdStreamError::DuplicateFd { fd } => write!(f, "child fd {} is assigned twice or is a standard stream", fd),
+            EfdStreamError::ClockSkew { sent, now } => {
+                write!(f, "frame stamped {} ns ahead of the receiver's clock", sent - now)
+            }
+            EfdStreamError::WrongDirection { direction } => write!(f, "not available in a {:?} session", direction),
+            EfdStreamError::Unavailable { capability, error } => {
+                write!(f, "{} is unavailable: {}", capability, error)?;
+                // What a seccomp filter or a missing kernel feature returns.
+                if matches!(error.raw_os_error(), Some(libc::EPERM | libc::ENOSYS)) {
+                    write!(f, " (blocked by seccomp or not supported by the kernel?)")?;
+                }
+                Ok(())
+            }
+            EfdStreamError::HandlerFailed { code, message } => write!(f, "child handler failed ({}): {}", code, message),
+            EfdStreamError::ShmSizeMismatch { parent, child } => {
+                write!(f, "shared memory is {} bytes but the child expects {}", parent, child)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EfdStreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EfdStreamError::Io(e) | EfdStreamError::Unavailable { error: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for EfdStreamError {
+    fn from(e: std::io::Error) -> Self {
+        EfdStreamError::Io(e)
+    }
+}
+
+// For `?` on nix calls. The errno survives as the `raw_os_error` of the
+// wrapped error.
+impl From<nix::errno::Errno> for EfdStreamError {
+    fn from(e: nix::errno::Errno) -> Self {
+        EfdStreamError::Io(e.into())
+    }
+}
+
+// For `std::io` trait impls: I/O errors pass through unchanged, the rest are
+// wrapped so the original can still be recovered with `downcast`.
+impl From<EfdStreamError> for std::io::Error {
+    fn from(e: EfdStreamError) -> Self {
+        match e {
+            EfdStreamError::Io(e) => e,
+            EfdStreamError::NotStarted => std::io::Error::new(std::io::ErrorKind::NotConnected, e),
+            EfdStreamError::DataTooLarge { .. } => std::io::Error::new(std::io::ErrorKind::InvalidInput, e),
+            EfdStreamError::PeerClosed => std::io::Error::new(std::io::ErrorKind::UnexpectedEof, e),
+            EfdStreamError::Timeout => std::io::Error::new(std::io::ErrorKind::TimedOut, e),
+            EfdStreamError::ChildDied { .. } => std::io::Error::new(std::io::ErrorKind::BrokenPipe, e),
+            EfdStreamError::WrongDirection { .. } => std::io::Error::new(std::io::ErrorKind::Unsupported, e),
+            EfdStreamError::Unavailable { ref error, .. } => std::io::Error::new(error.kind(), e),
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
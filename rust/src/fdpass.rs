@@ -0,0 +1,91 @@
+// Handing the six channel descriptors to a process that was not spawned by
+// the parent, over a unix domain socket with SCM_RIGHTS.
+//
+// One message carries everything: the fds as ancillary data in channel order
+// (p2c send, p2c ack, p2c shm, c2p send, c2p ack, c2p shm, then the control
+// eventfd if FLAG_CONTROL is set, then the priority send, ack and shm if
+// FLAG_PRIORITY is, then the shared state memfd if FLAG_STATE is) and a
+// 12-byte little-endian body holding the P2C size (u64) and option flags
+// (u32). With FLAG_C2P_SIZE the body is 20 bytes and ends with the C2P
+// size (u64). A simplex session, flagged FLAG_P2C_ONLY or FLAG_C2P_ONLY,
+// sends only its direction's three channel fds.
+
+use std::io::{IoSlice, IoSliceMut};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use nix::sys::socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags};
+
+use crate::error::Result;
+
+pub(crate) const FLAG_HANDSHAKE: u32 = 1;
+pub(crate) const FLAG_CONTROL: u32 = 2;
+const FLAG_C2P_SIZE: u32 = 4;
+pub(crate) const FLAG_PRIORITY: u32 = 8;
+pub(crate) const FLAG_P2C_ONLY: u32 = 16;
+pub(crate) const FLAG_C2P_ONLY: u32 = 32;
+pub(crate) const FLAG_STATE: u32 = 64;
+
+const BODY_LEN: usize = 12;
+const SIZED_BODY_LEN: usize = 20;
+
+// Sets FLAG_C2P_SIZE itself when the sizes differ.
+pub(crate) fn send_fds(socket: &UnixStream, fds: &[RawFd], p2c_size: usize, c2p_size: usize, mut flags: u32) -> Result<()> {
+    let mut body = [0u8; SIZED_BODY_LEN];
+    let mut body_len = BODY_LEN;
+    if c2p_size != p2c_size {
+        flags |= FLAG_C2P_SIZE;
+        body[12..20].copy_from_slice(&(c2p_size as u64).to_le_bytes());
+        body_len = SIZED_BODY_LEN;
+    }
+    body[0..8].copy_from_slice(&(p2c_size as u64).to_le_bytes());
+    body[8..12].copy_from_slice(&flags.to_le_bytes());
+
+    let iov = [IoSlice::new(&body[..body_len])];
+    let cmsgs = [ControlMessage::ScmRights(fds)];
+    let sent = sendmsg::<()>(socket.as_raw_fd(), &iov, &cmsgs, MsgFlags::empty(), None)?;
+    if sent != body_len {
+        return Err(std::io::Error::new(std::io::ErrorKind::WriteZero, "Short write while passing fds").into());
+    }
+    Ok(())
+}
+
+// Returns the fds, the P2C and C2P sizes, and the flags.
+pub(crate) fn recv_fds(socket: &UnixStream) -> Result<(Vec<OwnedFd>, usize, usize, u32)> {
+    let mut body = [0u8; SIZED_BODY_LEN];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 11]);
+    let mut iov = [IoSliceMut::new(&mut body)];
+    let msg = recvmsg::<()>(socket.as_raw_fd(), &mut iov, Some(&mut cmsg_buf), MsgFlags::MSG_CMSG_CLOEXEC)?;
+
+    // Take ownership of whatever arrived first, so nothing leaks on the error paths.
+    let mut received = Vec::new();
+    for cmsg in msg.cmsgs()? {
+        if let ControlMessageOwned::ScmRights(fds) = cmsg {
+            received.extend(fds.into_iter().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+        }
+    }
+    let truncated = msg.flags.contains(MsgFlags::MSG_CTRUNC);
+    let bytes = msg.bytes;
+
+    let malformed = || std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed fd-passing message");
+    if bytes < BODY_LEN || truncated {
+        return Err(malformed().into());
+    }
+    let flags = u32::from_le_bytes(body[8..12].try_into().unwrap());
+    if bytes != if flags & FLAG_C2P_SIZE != 0 { SIZED_BODY_LEN } else { BODY_LEN } {
+        return Err(malformed().into());
+    }
+    let p2c_size = u64::from_le_bytes(body[0..8].try_into().unwrap()) as usize;
+    let c2p_size = if flags & FLAG_C2P_SIZE != 0 {
+        u64::from_le_bytes(body[12..20].try_into().unwrap()) as usize
+    } else {
+        p2c_size
+    };
+    let channels = if flags & (FLAG_P2C_ONLY | FLAG_C2P_ONLY) != 0 { 3 } else { 6 };
+    let expected = channels + if flags & FLAG_CONTROL != 0 { 1 } else { 0 } + if flags & FLAG_PRIORITY != 0 { 3 } else { 0 }
+        + if flags & FLAG_STATE != 0 { 1 } else { 0 };
+    if received.len() != expected {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Expected exactly {} fds", expected)).into());
+    }
+    Ok((received, p2c_size, c2p_size, flags))
+}
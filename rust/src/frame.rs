@@ -0,0 +1,158 @@
+// Decoding of a received frame, kept free of fds and raw pointers so it can be
+// fuzzed: the doorbell bytes read from the send eventfd and the mapped region
+// go in, the payload slice comes out.
+
+use crate::error::{EfdStreamError, Result};
+
+/// Bytes carried by one doorbell write on the send eventfd.
+pub const DOORBELL_LEN: usize = 8;
+
+/// Doorbell value meaning the sender has shut its direction down. It can't
+/// be mistaken for a length, and it is the largest value an eventfd holds.
+pub const EOF_DOORBELL: u64 = u64::MAX - 1;
+
+/// Doorbell value announcing that the parent grew the SHM regions; the new
+/// size is at offset 0 of the P2C region. See `ShmParent::resize_shm`.
+pub const RESIZE_DOORBELL: u64 = u64::MAX - 2;
+
+/// Doorbell value asking the child to drop whatever state it has and answer
+/// a fresh handshake. See `ShmParent::reset`.
+pub const RESET_DOORBELL: u64 = u64::MAX - 3;
+
+/// Doorbell values that are never payload lengths. Add new sentinels here so
+/// every sender rejects them.
+pub const RESERVED_DOORBELLS: &[u64] = &[EOF_DOORBELL, RESIZE_DOORBELL, RESET_DOORBELL];
+
+/// Set in a doorbell length when the frame is in the second half of a
+/// double-buffered P2C region; see `ShmParentBuilder::double_buffer`. No
+/// frame is long enough to carry it, and it leaves the sentinels above free.
+pub const SECOND_BUFFER: u64 = 1 << 62;
+
+/// ACK value for a frame the receiver couldn't take because its length
+/// doesn't fit the region, or because it failed to decrypt or unstamp. The
+/// sender fails with `EfdStreamError::Rejected`
+/// instead of treating it as delivered. A plain ACK is 1.
+pub const NACK: u64 = 3;
+
+/// ACK value for a frame that was delivered but that the child's handler
+/// failed on, with the code and message it gave in the C2P trailer. The
+/// sender fails with `EfdStreamError::HandlerFailed`. Only sent to a Rust
+/// parent, which reserves the trailer.
+pub const ERROR_ACK: u64 = 4;
+
+/// Fails with `EfdStreamError::ReservedLength` if a payload of `len` bytes
+/// would ring the doorbell with a reserved value. That includes 0: adding 0
+/// to an eventfd doesn't wake the reader, so the sender would wait forever,
+/// and any length with the `SECOND_BUFFER` bit set, which a double-buffered
+/// child would read from the wrong half.
+pub fn check_length(len: usize) -> Result<()> {
+    let len = len as u64;
+    if len == 0 || len & SECOND_BUFFER != 0 || RESERVED_DOORBELLS.contains(&len) {
+        return Err(EfdStreamError::ReservedLength { len });
+    }
+    Ok(())
+}
+
+/// Validates the doorbell `buf` against `shm_size` and returns the payload it
+/// announces within `shm`. Never panics or reads past `shm`, whatever the input.
+pub fn decode_frame<'a>(buf: &[u8], shm: &'a [u8], shm_size: usize) -> Result<&'a [u8]> {
+    let Ok(doorbell) = <[u8; DOORBELL_LEN]>::try_from(buf) else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Doorbell must be 8 bytes").into());
+    };
+    // Compare as u64 so a huge length can't wrap when narrowed to usize.
+    let length = u64::from_ne_bytes(doorbell);
+    if length > shm_size as u64 || shm_size > shm.len() {
+        return Err(EfdStreamError::LengthExceedsShm { len: length, cap: shm_size.min(shm.len()) });
+    }
+    Ok(&shm[..length as usize])
+}
+
+/// Byte order of the values rung on the doorbell and ACK eventfds (lengths,
+/// ACKs, sentinels) and of the header fields written to SHM (timestamps, the
+/// resize announcement). `Native` is the format Go and C peers use; the
+/// others must be agreed on in the handshake. The startup handshake itself
+/// and the control word are always native.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endianness {
+    #[default]
+    Native,
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// `Native` resolved to the host's order.
+    pub fn resolve(self) -> Self {
+        match self {
+            Endianness::Native if cfg!(target_endian = "big") => Endianness::Big,
+            Endianness::Native => Endianness::Little,
+            order => order,
+        }
+    }
+
+    /// The 8 bytes that carry `value` in this order.
+    pub fn encode(self, value: u64) -> [u8; DOORBELL_LEN] {
+        match self {
+            Endianness::Native => value.to_ne_bytes(),
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub fn decode(self, bytes: [u8; DOORBELL_LEN]) -> u64 {
+        match self {
+            Endianness::Native => u64::from_ne_bytes(bytes),
+            Endianness::Little => u64::from_le_bytes(bytes),
+            Endianness::Big => u64::from_be_bytes(bytes),
+        }
+    }
+
+    /// The counter value to write to an eventfd so that its 8 bytes are
+    /// `encode(value)`.
+    pub fn to_wire(self, value: u64) -> u64 {
+        u64::from_ne_bytes(self.encode(value))
+    }
+
+    /// The inverse of `to_wire`, for a value read from an eventfd.
+    pub fn from_wire(self, raw: u64) -> u64 {
+        self.decode(raw.to_ne_bytes())
+    }
+}
+
+/// Bytes of send time in front of each payload when timestamps were agreed
+/// on in the handshake (`ShmParentBuilder::timestamps`).
+pub const TIMESTAMP_LEN: usize = 8;
+
+/// `CLOCK_MONOTONIC` in nanoseconds, the clock frames are stamped with. It is
+/// shared by every process on the host, so a receiver can subtract a frame's
+/// send time from its own reading to get the one-way latency.
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// How far a frame's send time may be ahead of the receiver's reading before
+/// it is treated as clock skew rather than the two reads racing on
+/// different CPUs.
+pub const CLOCK_SKEW_TOLERANCE_NS: u64 = 1_000_000;
+
+/// Returns `sent` if it isn't in the future by the receiver's clock, and
+/// `EfdStreamError::ClockSkew` otherwise. Both ends stamp with
+/// `CLOCK_MONOTONIC`, so this only fails on a corrupt or foreign stamp.
+pub fn check_timestamp(sent: u64) -> Result<u64> {
+    let now = monotonic_ns();
+    if sent > now.saturating_add(CLOCK_SKEW_TOLERANCE_NS) {
+        return Err(EfdStreamError::ClockSkew { sent, now });
+    }
+    Ok(sent)
+}
+
+/// Splits a timestamped frame into the sender's `monotonic_ns` reading and
+/// the payload. Never panics, whatever the input.
+pub fn split_timestamp(frame: &[u8]) -> Result<(u64, &[u8])> {
+    let Some((stamp, payload)) = frame.split_first_chunk::<TIMESTAMP_LEN>() else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame too short for timestamp").into());
+    };
+    Ok((u64::from_ne_bytes(*stamp), payload))
+}
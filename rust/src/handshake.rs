@@ -0,0 +1,150 @@
+// Startup handshake: before the first data frame the parent writes an offer
+// into the P2C region and rings the P2C doorbell with the record length. The
+// child validates it and answers through the ACK value itself, so the answer
+// can't be overwritten by the child's first C2P frame, and a peer that just
+// ACKs with 1 (Go, C, or a child without the handshake enabled) reads as
+// "version 0" instead of hanging the parent. Offers are always little-endian
+// so both sides can decode them before any framing option has been agreed on.
+
+use crate::error::{EfdStreamError, Result};
+use crate::frame::Endianness;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub const CODEC_NONE: u32 = 0;
+
+/// Payloads are sealed with the shared key (`crypto` feature). The offer then
+/// carries a key check after the record; see `crypto.rs`.
+#[cfg(feature = "crypto")]
+pub(crate) const FLAG_ENCRYPTED: u32 = 1;
+/// Every frame starts with the sender's `frame::monotonic_ns` reading.
+pub(crate) const FLAG_TIMESTAMPS: u32 = 2;
+/// Lengths, ACKs and SHM header fields are little- or big-endian
+/// (`frame::Endianness`). Neither flag means the parent's native order.
+pub(crate) const FLAG_LITTLE_ENDIAN: u32 = 4;
+pub(crate) const FLAG_BIG_ENDIAN: u32 = 8;
+const SUPPORTED_CODECS: &[u32] = &[CODEC_NONE];
+
+pub(crate) fn order_flag(order: Endianness) -> u32 {
+    match order {
+        Endianness::Native => 0,
+        Endianness::Little => FLAG_LITTLE_ENDIAN,
+        Endianness::Big => FLAG_BIG_ENDIAN,
+    }
+}
+
+// The byte order `order_flag` produced `flags` for, with `Native` resolved:
+// both ends run on the same host.
+pub(crate) fn offered_order(flags: u32) -> Endianness {
+    if flags & FLAG_BIG_ENDIAN != 0 {
+        Endianness::Big
+    } else if flags & FLAG_LITTLE_ENDIAN != 0 {
+        Endianness::Little
+    } else {
+        Endianness::Native.resolve()
+    }
+}
+
+const MAGIC: [u8; 4] = *b"EFDS";
+pub(crate) const RECORD_LEN: usize = 20;
+
+// ACK value layout for an answer: flags in bits 0..32, version in 32..48,
+// status in 48..56, and bit 63 set so it can't be mistaken for a plain ACK.
+const ANSWER_MARKER: u64 = 1 << 63;
+
+const STATUS_OK: u32 = 0;
+const STATUS_VERSION_MISMATCH: u32 = 1;
+const STATUS_UNSUPPORTED: u32 = 2;
+const STATUS_KEY_MISMATCH: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Hello {
+    pub version: u32,
+    pub flags: u32,
+    pub codec: u32,
+    pub status: u32,
+}
+
+impl Hello {
+    pub(crate) fn offer(flags: u32, codec: u32) -> Self {
+        Self { version: PROTOCOL_VERSION, flags, codec, status: STATUS_OK }
+    }
+
+    pub(crate) fn encode(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4..8].copy_from_slice(&self.version.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.flags.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.codec.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.status.to_le_bytes());
+    }
+
+    // Returns None when the buffer doesn't hold a record at all, i.e. the
+    // peer never took part in the handshake.
+    pub(crate) fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < RECORD_LEN || buf[0..4] != MAGIC {
+            return None;
+        }
+        let word = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        Some(Self { version: word(4), flags: word(8), codec: word(12), status: word(16) })
+    }
+
+    pub(crate) fn encode_answer(&self) -> u64 {
+        ANSWER_MARKER
+            | (self.status as u64 & 0xff) << 48
+            | (self.version as u64 & 0xffff) << 32
+            | self.flags as u64
+    }
+
+    pub(crate) fn decode_answer(ack: u64) -> Option<Self> {
+        if ack & ANSWER_MARKER == 0 {
+            return None;
+        }
+        Some(Self {
+            version: (ack >> 32) as u32 & 0xffff,
+            flags: ack as u32,
+            codec: CODEC_NONE,
+            status: (ack >> 48) as u32 & 0xff,
+        })
+    }
+
+    // Child side: build the answer to a parent's offer, plus the outcome the
+    // child itself should report. `key_ok` says whether the offer's key check
+    // matched the child's key, or both sides left encryption off.
+    pub(crate) fn answer(offer: Option<Hello>, supported_flags: u32, key_ok: bool) -> (Hello, Result<Hello>) {
+        let mut reply = Hello::offer(supported_flags, CODEC_NONE);
+        let Some(offer) = offer else {
+            reply.status = STATUS_VERSION_MISMATCH;
+            return (reply, Err(EfdStreamError::VersionMismatch { local: PROTOCOL_VERSION, peer: 0 }));
+        };
+        if offer.version != PROTOCOL_VERSION {
+            reply.status = STATUS_VERSION_MISMATCH;
+            return (reply, Err(EfdStreamError::VersionMismatch { local: PROTOCOL_VERSION, peer: offer.version }));
+        }
+        let unsupported = offer.flags & !supported_flags;
+        if unsupported != 0 || !SUPPORTED_CODECS.contains(&offer.codec) {
+            reply.status = STATUS_UNSUPPORTED;
+            return (reply, Err(EfdStreamError::UnsupportedOption { flags: unsupported, codec: offer.codec }));
+        }
+        if !key_ok {
+            reply.status = STATUS_KEY_MISMATCH;
+            return (reply, Err(EfdStreamError::DecryptFailed));
+        }
+        (reply, Ok(offer))
+    }
+
+    // Parent side: interpret the child's answer to `self`.
+    pub(crate) fn check_answer(&self, reply: Option<Hello>) -> Result<()> {
+        let Some(reply) = reply else {
+            return Err(EfdStreamError::VersionMismatch { local: PROTOCOL_VERSION, peer: 0 });
+        };
+        match reply.status {
+            STATUS_OK if reply.version == PROTOCOL_VERSION => Ok(()),
+            STATUS_UNSUPPORTED => Err(EfdStreamError::UnsupportedOption {
+                flags: self.flags & !reply.flags,
+                codec: self.codec,
+            }),
+            STATUS_KEY_MISMATCH => Err(EfdStreamError::DecryptFailed),
+            _ => Err(EfdStreamError::VersionMismatch { local: PROTOCOL_VERSION, peer: reply.version }),
+        }
+    }
+}
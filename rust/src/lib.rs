@@ -1,3 +1,37 @@
+#[cfg(feature = "tokio")]
+pub mod async_parent;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod copy;
+mod crypto;
 pub mod efd;
-pub use efd::{ShmParent, ShmChild};
-
+pub mod error;
+mod fdpass;
+pub mod frame;
+mod handshake;
+pub mod metrics;
+mod region;
+mod retry;
+pub mod ring;
+pub mod socket;
+mod spawn;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod transport;
+pub mod writer;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+#[cfg(feature = "tokio")]
+pub use async_parent::AsyncShmParent;
+pub use efd::{Advice, Direction, FrameGuard, FrameHeader, OversizePolicy, PauseHandle, Responder, SessionInfo, ShmFdSet, ShmParent, ShmParentBuilder, ShmChild};
+pub use error::EfdStreamError;
+pub use frame::Endianness;
+pub use handshake::PROTOCOL_VERSION;
+pub use metrics::Metrics;
+pub use ring::{OverflowPolicy, RingShmChild, RingShmParent};
+pub use socket::{SocketChild, SocketParent};
+pub use spawn::{DropBehavior, FdLayout, SpawnMode};
+pub use transport::{DuplexChannel, InProcess, Message};
+pub use writer::{ShmWriter, ShmWriterBuilder};
+#[cfg(feature = "io-uring")]
+pub use uring::UringShmParent;
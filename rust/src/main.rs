@@ -2,7 +2,7 @@ use std::env;
 use std::thread;
 use std::time::Duration;
 
-use efdstream::{ShmParent, ShmChild};
+use efdstream::{Endianness, RingShmChild, RingShmParent, ShmParent, ShmChild, SocketChild, SocketParent};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -16,8 +16,16 @@ fn main() {
     let mut fd_c2p_send = 6;
     let mut fd_c2p_ack = 7;
     let mut fd_c2p_shm = 8;
+    let mut fd_socket = 3;
+    let mut fd_control = None;
 
     let mut shm_size = 1024 * 1024;
+    let mut c2p_size = None;
+    let mut endianness = Endianness::Native;
+    let mut handshake = false;
+    let mut ring = false;
+    let mut ack_batch = 1;
+    let mut socket = false;
 
     let mut i = 1;
     while i < args.len() {
@@ -37,27 +45,65 @@ fn main() {
             if i + 1 < args.len() { fd_c2p_ack = args[i+1].parse().unwrap_or(7); i += 1; }
         } else if args[i] == "-fd-c2p-shm" {
             if i + 1 < args.len() { fd_c2p_shm = args[i+1].parse().unwrap_or(8); i += 1; }
-        } else if args[i] == "-shm-size" || args[i] == "--shm-size" {
-            if i + 1 < args.len() { shm_size = args[i+1].parse().unwrap_or(1024 * 1024); i += 1; }
+        } else if args[i] == "-fd-control" {
+            if i + 1 < args.len() { fd_control = args[i+1].parse().ok(); i += 1; }
+        } else if args[i] == "-fd-socket" {
+            if i + 1 < args.len() { fd_socket = args[i+1].parse().unwrap_or(3); i += 1; }
+        } else if args[i] == "-handshake" || args[i] == "--handshake" {
+            handshake = true;
+        } else if args[i] == "-ring" || args[i] == "--ring" {
+            ring = true;
+        } else if (args[i] == "-ack-batch" || args[i] == "--ack-batch") && i + 1 < args.len() {
+            ack_batch = args[i+1].parse().unwrap_or(1); i += 1;
+        } else if args[i] == "-socket" || args[i] == "--socket" {
+            socket = true;
+        } else if (args[i] == "-shm-size" || args[i] == "--shm-size") && i + 1 < args.len() {
+            shm_size = args[i+1].parse().unwrap_or(1024 * 1024); i += 1;
+        } else if args[i] == "-endianness" && i + 1 < args.len() {
+            endianness = match args[i+1].as_str() {
+                "little" => Endianness::Little,
+                "big" => Endianness::Big,
+                _ => Endianness::Native,
+            };
+            i += 1;
+        } else if args[i] == "-c2p-size" && i + 1 < args.len() {
+            c2p_size = args[i+1].parse().ok(); i += 1;
         }
         i += 1;
     }
 
-    if mode == "parent" {
-        run_parent(&child_path, shm_size);
+    if mode == "echo" && ring {
+        run_ring_child(fd_p2c_send, fd_p2c_ack, fd_p2c_shm, fd_c2p_send, fd_c2p_ack, fd_c2p_shm, shm_size, ack_batch, false);
+    } else if mode == "echo" {
+        run_echo_child(ShmChild::from_env_args());
+    } else if mode == "env-echo" {
+        run_echo_child(ShmChild::from_env("EFDSTREAM"));
+    } else if mode == "parent" && ring {
+        run_ring_parent(&child_path);
+    } else if mode == "parent" && socket {
+        run_socket_parent(&child_path, shm_size);
+    } else if mode == "parent" {
+        run_parent(&child_path, shm_size, handshake);
+    } else if socket {
+        run_socket_child(fd_socket, shm_size);
+    } else if ring {
+        run_ring_child(fd_p2c_send, fd_p2c_ack, fd_p2c_shm, fd_c2p_send, fd_c2p_ack, fd_c2p_shm, shm_size, ack_batch, true);
     } else {
-        run_child(fd_p2c_send, fd_p2c_ack, fd_p2c_shm, fd_c2p_send, fd_c2p_ack, fd_c2p_shm, shm_size);
+        run_child(fd_p2c_send, fd_p2c_ack, fd_p2c_shm, fd_c2p_send, fd_c2p_ack, fd_c2p_shm, shm_size, c2p_size, handshake, endianness, fd_control);
     }
 }
 
-fn run_parent(child_path: &str, shm_size: usize) {
+fn run_parent(child_path: &str, shm_size: usize, handshake: bool) {
     if child_path.is_empty() {
         eprintln!("Child path is required in parent mode");
         std::process::exit(1);
     }
 
     // FDs are now auto-generated and mapped to 3, 4, 5, 6, 7, 8 in the child.
-    let mut parent = ShmParent::new(child_path, shm_size);
+    let mut parent = ShmParent::builder(child_path)
+        .shm_size(shm_size)
+        .handshake(handshake)
+        .build();
     parent.start().expect("Failed to start parent");
 
     println!("[Rust Parent] Child started");
@@ -66,11 +112,11 @@ fn run_parent(child_path: &str, shm_size: usize) {
         // Send
         let msg = format!("Hello from Rust Parent {}", i);
         println!("[Rust Parent] Sending: {}", msg);
-        parent.send_data(msg.as_bytes()).expect("Communication error");
+        parent.send_to_peer(msg.as_bytes()).expect("Communication error");
         println!("[Rust Parent] Received ACK");
 
         // Receive
-        match parent.read_data() {
+        match parent.recv_from_peer() {
             Ok(data) => {
                 let msg = String::from_utf8_lossy(&data);
                 println!("[Rust Parent] Received: {}", msg);
@@ -82,9 +128,11 @@ fn run_parent(child_path: &str, shm_size: usize) {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run_child(fd_p2c_send: i32, fd_p2c_ack: i32, fd_p2c_shm: i32,
              fd_c2p_send: i32, fd_c2p_ack: i32, fd_c2p_shm: i32,
-             shm_size: usize) {
+             shm_size: usize, c2p_size: Option<usize>, handshake: bool, endianness: Endianness,
+             fd_control: Option<i32>) {
     // Test: Open a file BEFORE initializing ShmChild to see if it takes FD 3-8
     if let Ok(f) = std::fs::File::open("/dev/null") {
         use std::os::unix::io::AsRawFd;
@@ -97,20 +145,26 @@ fn run_child(fd_p2c_send: i32, fd_p2c_ack: i32, fd_p2c_shm: i32,
     let mut child = ShmChild::new(
         fd_p2c_send, fd_p2c_ack, fd_p2c_shm,
         fd_c2p_send, fd_c2p_ack, fd_c2p_shm,
-        shm_size);
+        shm_size).handshake(handshake).endianness(endianness);
+    if let Some(fd) = fd_control {
+        child = child.control_channel(fd);
+    }
+    if let Some(size) = c2p_size {
+        child = child.c2p_size(size);
+    }
 
     // Spawn thread to send data back
     let mut child_sender = ShmChild::new(
         fd_p2c_send, fd_p2c_ack, fd_p2c_shm,
         fd_c2p_send, fd_c2p_ack, fd_c2p_shm,
-        shm_size);
+        shm_size).c2p_size(c2p_size.unwrap_or(shm_size)).endianness(endianness);
     
     thread::spawn(move || {
         for i in 0..5 {
             thread::sleep(Duration::from_millis(500));
             let msg = format!("Hello from Rust Child {}", i);
             println!("[Rust Child] Sending: {}", msg);
-            if let Err(e) = child_sender.send_data(msg.as_bytes()) {
+            if let Err(e) = child_sender.send_to_peer(msg.as_bytes()) {
                 println!("[Rust Child] Send error: {}", e);
                 break;
             }
@@ -127,3 +181,124 @@ fn run_child(fd_p2c_send: i32, fd_p2c_ack: i32, fd_p2c_shm: i32,
         println!("[Rust Child] Error: {}", e);
     }
 }
+
+// Sends every message straight back, for the integration tests. Selected
+// with `-mode echo` after the arguments `ShmParent::start` passes, or with
+// `-mode env-echo` to read them from the `EFDSTREAM_*` variables instead.
+fn run_echo_child(child: Result<ShmChild, efdstream::EfdStreamError>) {
+    let mut child = child.unwrap_or_else(|e| {
+        eprintln!("[Rust Echo] {}", e);
+        std::process::exit(2);
+    });
+    if let Err(e) = child.listen_request_response(|data| data.to_vec()) {
+        eprintln!("[Rust Echo] Error: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = child.shutdown_send() {
+        eprintln!("[Rust Echo] Shutdown error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run_ring_parent(child_path: &str) {
+    if child_path.is_empty() {
+        eprintln!("Child path is required in parent mode");
+        std::process::exit(1);
+    }
+
+    let mut parent = RingShmParent::new(child_path, 8, 4096);
+    parent.start().expect("Failed to start parent");
+
+    println!("[Rust Ring Parent] Child started");
+
+    // Queue every message up front; the ring only blocks once all slots are in flight.
+    for i in 0..5 {
+        let msg = format!("Hello from Rust Ring Parent {}", i);
+        println!("[Rust Ring Parent] Sending: {}", msg);
+        parent.send_data(msg.as_bytes()).expect("Communication error");
+    }
+
+    for _ in 0..5 {
+        match parent.read_data() {
+            Ok(data) => println!("[Rust Ring Parent] Received: {}", String::from_utf8_lossy(&data)),
+            Err(e) => println!("[Rust Ring Parent] Read error: {}", e),
+        }
+    }
+}
+
+// `verbose` is off for `-mode echo -ring`, which echoes silently for the
+// benchmarks.
+#[allow(clippy::too_many_arguments)]
+fn run_ring_child(fd_p2c_send: i32, fd_p2c_ack: i32, fd_p2c_shm: i32,
+                  fd_c2p_send: i32, fd_c2p_ack: i32, fd_c2p_shm: i32,
+                  shm_size: usize, ack_batch: u64, verbose: bool) {
+    let mut child = RingShmChild::new(
+        fd_p2c_send, fd_p2c_ack, fd_p2c_shm,
+        fd_c2p_send, fd_c2p_ack, fd_c2p_shm,
+        shm_size).ack_batch(ack_batch);
+
+    loop {
+        let data = match child.read_data() {
+            Ok(data) => data,
+            Err(e) => {
+                println!("[Rust Ring Child] Error: {}", e);
+                break;
+            }
+        };
+        if !verbose {
+            if child.send_data(&data).is_err() {
+                break;
+            }
+            continue;
+        }
+        let msg = String::from_utf8_lossy(&data);
+        println!("[Rust Ring Child] Received: {}", msg);
+        if let Err(e) = child.send_data(format!("Echo: {}", msg).as_bytes()) {
+            println!("[Rust Ring Child] Send error: {}", e);
+            break;
+        }
+    }
+}
+
+fn run_socket_parent(child_path: &str, max_size: usize) {
+    if child_path.is_empty() {
+        eprintln!("Child path is required in parent mode");
+        std::process::exit(1);
+    }
+
+    let mut parent = SocketParent::new(child_path, max_size);
+    parent.start().expect("Failed to start parent");
+
+    println!("[Rust Socket Parent] Child started");
+
+    for i in 0..5 {
+        let msg = format!("Hello from Rust Socket Parent {}", i);
+        println!("[Rust Socket Parent] Sending: {}", msg);
+        parent.send_data(msg.as_bytes()).expect("Communication error");
+
+        match parent.read_data() {
+            Ok(data) => println!("[Rust Socket Parent] Received: {}", String::from_utf8_lossy(&data)),
+            Err(e) => println!("[Rust Socket Parent] Read error: {}", e),
+        }
+    }
+}
+
+fn run_socket_child(fd_socket: i32, max_size: usize) {
+    let mut child = SocketChild::new(fd_socket, max_size);
+
+    loop {
+        let data = match child.read_data() {
+            Ok(data) => data,
+            Err(e) => {
+                println!("[Rust Socket Child] Error: {}", e);
+                break;
+            }
+        };
+        let msg = String::from_utf8_lossy(&data);
+        println!("[Rust Socket Child] Received: {}", msg);
+        if let Err(e) = child.send_data(format!("Echo: {}", msg).as_bytes()) {
+            println!("[Rust Socket Child] Send error: {}", e);
+            break;
+        }
+    }
+}
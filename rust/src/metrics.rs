@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+/// Running totals for one `ShmParent` or `RingShmParent`. Byte counts are payload bytes only,
+/// not the 8-byte length frames.
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Longest payload sent or received so far.
+    pub max_message_len: usize,
+    /// Time from ringing the doorbell to the ACK, for the last send.
+    pub last_rtt: Duration,
+    /// Sum of all send round trips; divide by `messages_sent` for the mean.
+    pub total_rtt: Duration,
+    /// Ring-mode frames discarded by a drop overflow policy.
+    pub dropped_frames: u64,
+    /// Ring mode: the most P2C slots found in use right after a send, out of
+    /// the ring's slot count.
+    pub max_occupancy: usize,
+
+    #[cfg(feature = "prometheus")]
+    prom: prom::PromMetrics,
+}
+
+impl Metrics {
+    pub(crate) fn record_send(&mut self, len: usize, rtt: Duration) {
+        self.messages_sent += 1;
+        self.bytes_sent += len as u64;
+        self.max_message_len = self.max_message_len.max(len);
+        self.last_rtt = rtt;
+        self.total_rtt += rtt;
+
+        #[cfg(feature = "prometheus")]
+        self.prom.record_send(len, rtt);
+    }
+
+    pub(crate) fn record_receive(&mut self, len: usize) {
+        self.messages_received += 1;
+        self.bytes_received += len as u64;
+        self.max_message_len = self.max_message_len.max(len);
+
+        #[cfg(feature = "prometheus")]
+        self.prom.record_receive(len);
+    }
+
+    pub(crate) fn record_drop(&mut self) {
+        self.dropped_frames += 1;
+
+        #[cfg(feature = "prometheus")]
+        self.prom.record_drop();
+    }
+
+    pub(crate) fn record_occupancy(&mut self, slots: usize) {
+        if slots > self.max_occupancy {
+            self.max_occupancy = slots;
+
+            #[cfg(feature = "prometheus")]
+            self.prom.max_occupancy.set(slots as i64);
+        }
+    }
+
+    #[cfg(feature = "prometheus")]
+    pub(crate) fn register(&self, registry: &prometheus::Registry) -> prometheus::Result<()> {
+        self.prom.register(registry)
+    }
+}
+
+#[cfg(feature = "prometheus")]
+mod prom {
+    use std::time::Duration;
+
+    use prometheus::{Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry};
+
+    // Collectors are created with the parent and updated on every message;
+    // registering only makes them visible to a registry.
+    #[derive(Clone)]
+    pub(super) struct PromMetrics {
+        messages: IntCounterVec,
+        bytes: IntCounterVec,
+        rtt: Histogram,
+        dropped: IntCounter,
+        pub(super) max_occupancy: IntGauge,
+    }
+
+    impl Default for PromMetrics {
+        fn default() -> Self {
+            let messages = IntCounterVec::new(
+                Opts::new("efdstream_messages_total", "Messages transferred over efdstream"),
+                &["direction"]).unwrap();
+            let bytes = IntCounterVec::new(
+                Opts::new("efdstream_bytes_total", "Payload bytes transferred over efdstream"),
+                &["direction"]).unwrap();
+            let rtt = Histogram::with_opts(HistogramOpts::new(
+                "efdstream_rtt_seconds", "Time from send doorbell to ACK")
+                .buckets(prometheus::exponential_buckets(1e-6, 4.0, 12).unwrap())).unwrap();
+            let dropped = IntCounter::new(
+                "efdstream_dropped_frames_total", "Ring frames discarded by the overflow policy").unwrap();
+            let max_occupancy = IntGauge::new(
+                "efdstream_ring_max_occupancy", "Most P2C ring slots in use after a send").unwrap();
+            Self { messages, bytes, rtt, dropped, max_occupancy }
+        }
+    }
+
+    impl std::fmt::Debug for PromMetrics {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("PromMetrics").finish_non_exhaustive()
+        }
+    }
+
+    impl PromMetrics {
+        pub(super) fn record_send(&self, len: usize, rtt: Duration) {
+            self.messages.with_label_values(&["sent"]).inc();
+            self.bytes.with_label_values(&["sent"]).inc_by(len as u64);
+            self.rtt.observe(rtt.as_secs_f64());
+        }
+
+        pub(super) fn record_receive(&self, len: usize) {
+            self.messages.with_label_values(&["received"]).inc();
+            self.bytes.with_label_values(&["received"]).inc_by(len as u64);
+        }
+
+        pub(super) fn record_drop(&self) {
+            self.dropped.inc();
+        }
+
+        pub(super) fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+            registry.register(Box::new(self.messages.clone()))?;
+            registry.register(Box::new(self.bytes.clone()))?;
+            registry.register(Box::new(self.rtt.clone()))?;
+            registry.register(Box::new(self.dropped.clone()))?;
+            registry.register(Box::new(self.max_occupancy.clone()))
+        }
+    }
+}
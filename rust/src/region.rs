@@ -0,0 +1,109 @@
+// Ownership of one MAP_SHARED mapping of a channel's memfd.
+//
+// Invariants: `ptr` is null (nothing mapped) or the start of a live mapping of
+// `len` bytes that this value alone unmaps, on `unmap` or drop. The peer
+// process writes to the mapping concurrently, so its contents are only ever
+// reached through raw pointers and atomics, never as long-lived references.
+
+use std::os::unix::io::AsFd;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{compiler_fence, Ordering};
+
+use nix::errno::Errno;
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+
+pub(crate) struct SharedRegion {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// Moving the only handle to a mapping to another thread is fine; the mapping
+// is not tied to the thread that created it. `Sync` is deliberately not
+// implemented: the owners write through `ptr` from `&mut self` methods and
+// have no locking of their own, so they are `Send` but not `Sync` as well.
+unsafe impl Send for SharedRegion {}
+
+impl SharedRegion {
+    pub(crate) const fn unmapped() -> Self {
+        Self { ptr: ptr::null_mut(), len: 0 }
+    }
+
+    pub(crate) fn map<F: AsFd>(fd: F, len: usize, prot: ProtFlags) -> std::io::Result<Self> {
+        Self::map_at(fd, len, prot, None)
+    }
+
+    // With `addr`, maps exactly there or fails with `AddrInUse`, never
+    // replacing a mapping already in the way. Kernels before 4.17 take
+    // MAP_FIXED_NOREPLACE as a mere hint, hence the check of the result.
+    pub(crate) fn map_at<F: AsFd>(fd: F, len: usize, prot: ProtFlags, addr: Option<usize>) -> std::io::Result<Self> {
+        let len_nz = std::num::NonZeroUsize::new(len)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Cannot map an empty region"))?;
+        let (hint, flags) = match addr.and_then(std::num::NonZeroUsize::new) {
+            Some(addr) => (Some(addr), MapFlags::MAP_SHARED | MapFlags::MAP_FIXED_NOREPLACE),
+            None => (None, MapFlags::MAP_SHARED),
+        };
+        let unavailable = || std::io::Error::new(std::io::ErrorKind::AddrInUse,
+            format!("Cannot map {} bytes at {:#x}", len, addr.unwrap_or(0)));
+        let ptr = match unsafe { mmap(hint, len_nz, prot, flags, fd, 0) } {
+            Ok(ptr) => ptr,
+            Err(Errno::EEXIST) => return Err(unavailable()),
+            Err(e) => return Err(e.into()),
+        };
+        let region = Self { ptr: ptr.as_ptr() as *mut u8, len };
+        if hint.is_some_and(|hint| hint.get() != region.ptr as usize) {
+            return Err(unavailable());
+        }
+        Ok(region)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    // Overwrites the whole mapping with zeros. The stores are volatile and
+    // fenced, so they are kept even though nothing reads them before
+    // `unmap`.
+    pub(crate) fn zero(&mut self) {
+        if self.ptr.is_null() {
+            return;
+        }
+        let words = self.len / 8;
+        for i in 0..words {
+            unsafe { ptr::write_volatile((self.ptr as *mut u64).add(i), 0) };
+        }
+        for i in words * 8..self.len {
+            unsafe { ptr::write_volatile(self.ptr.add(i), 0) };
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    // Nulls `ptr` whatever happens, so a second call (e.g. drop after an
+    // explicit unmap) never unmaps twice. A failure means `len` was wrong,
+    // which is a bug here, not something the caller can handle.
+    pub(crate) fn unmap(&mut self) {
+        if let Some(ptr) = NonNull::new(self.ptr as *mut std::ffi::c_void) {
+            let result = unsafe { munmap(ptr, self.len) };
+            debug_assert!(result.is_ok(), "munmap of {} bytes failed: {:?}", self.len, result);
+            #[cfg(feature = "log")]
+            if let Err(e) = result {
+                log::error!("munmap of {} bytes at {:p} failed: {}", self.len, self.ptr, e);
+            }
+        }
+        self.ptr = ptr::null_mut();
+        self.len = 0;
+    }
+}
+
+impl Drop for SharedRegion {
+    fn drop(&mut self) {
+        self.unmap();
+    }
+}
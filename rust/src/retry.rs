@@ -0,0 +1,40 @@
+// Retrying the syscalls that set up a session. Under memory pressure
+// memfd_create, ftruncate and mmap can fail with ENOMEM or EAGAIN and succeed
+// a moment later; anything else (EINVAL for a bad size, EMFILE) is a real
+// error and is returned straight away.
+
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Retry {
+    pub(crate) retries: u32,
+    // Pause before the first retry; doubled for each one after.
+    pub(crate) backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self { retries: 3, backoff: Duration::from_millis(10) }
+    }
+}
+
+impl Retry {
+    pub(crate) fn run<T>(&self, mut op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut backoff = self.backoff;
+        for _ in 0..self.retries {
+            match op() {
+                Err(e) if is_transient(&e) => {
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+        op()
+    }
+}
+
+fn is_transient(e: &std::io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::ENOMEM | libc::EAGAIN))
+}
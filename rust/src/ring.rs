@@ -0,0 +1,600 @@
+// Ring-buffer framing: each region is split into fixed-size slots so the
+// producer can queue several messages before it has to wait for the consumer.
+//
+// Region layout:
+//   [0, 64)     geometry written by the parent: magic, slot count, slot size
+//   [64, 128)   head: next sequence number the producer will write
+//   [128, 192)  tail: next sequence number the consumer will read
+//   [192, 256)  waiting flags, so the doorbells are only rung when needed
+//   [256, ..)   slots, each: generation u64, len u64, payload
+//
+// Every slot starts with a generation word used as a seqlock. On lap n over
+// the ring the producer stores 2n+1 before touching the payload and 2n+2 once
+// it is complete. The consumer expects 2n+2 both before and after copying, so
+// a slot that is still being written, or that was overwritten while being
+// read, is reported as stale instead of being delivered half-updated.
+//
+// The tail is only ever advanced with a compare-exchange, by the consumer
+// after reading a slot or by a drop-oldest producer discarding one, so each
+// sequence number is claimed by exactly one side.
+//
+// The send eventfd is the "data available" doorbell and the ack eventfd is the
+// "space available" doorbell. Both accumulate, so one read drains any number
+// of coalesced wakeups. With `ack_batch(n)` a consumer rings "space available"
+// only once it has freed n slots, so a producer kept at a full ring refills n
+// slots per wakeup instead of one. It rings early whenever it finds the ring
+// empty or is about to send, so neither side can wait on the other forever.
+
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::efd::{drain_with, eventfd_read, eventfd_write, wait_readable_or_exit, ShmChild, ShmParent};
+use crate::error::{EfdStreamError, Result};
+use crate::metrics::Metrics;
+
+const RING_MAGIC: u32 = u32::from_le_bytes(*b"EFDR");
+const HEADER_LEN: usize = 256;
+const SLOT_HEADER_LEN: usize = 16;
+const CACHE_LINE: usize = 64;
+
+#[repr(C)]
+struct RingHeader {
+    magic: u32,
+    slot_count: u32,
+    slot_size: u64,
+    _pad0: [u8; 48],
+    head: AtomicU64,
+    _pad1: [u8; 56],
+    tail: AtomicU64,
+    _pad2: [u8; 56],
+    producer_waiting: AtomicU32,
+    consumer_waiting: AtomicU32,
+    _pad3: [u8; 56],
+}
+
+#[repr(C)]
+struct SlotHeader {
+    generation: AtomicU64,
+    len: AtomicU64,
+}
+
+fn slot_stride(slot_size: usize) -> usize {
+    (SLOT_HEADER_LEN + slot_size).div_ceil(CACHE_LINE) * CACHE_LINE
+}
+
+/// Bytes of SHM needed per direction for `slot_count` slots of `slot_size`.
+pub fn region_size(slot_count: usize, slot_size: usize) -> usize {
+    HEADER_LEN + slot_count * slot_stride(slot_size)
+}
+
+struct Ring {
+    base: *mut u8,
+    slot_count: u64,
+    slot_size: usize,
+    stride: usize,
+}
+
+// `base` points into a `SharedRegion` owned by the same `RingShmParent` or
+// `RingShmChild`, so the ring moves between threads together with its mapping.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    // Parent side: lay out a freshly created (zeroed) region before the child
+    // is spawned.
+    unsafe fn create(base: *mut u8, slot_count: usize, slot_size: usize) -> Ring {
+        let header = base as *mut RingHeader;
+        unsafe {
+            (*header).magic = RING_MAGIC;
+            (*header).slot_count = slot_count as u32;
+            (*header).slot_size = slot_size as u64;
+        }
+        Ring { base, slot_count: slot_count as u64, slot_size, stride: slot_stride(slot_size) }
+    }
+
+    // Child side: pick up the geometry the parent wrote.
+    unsafe fn attach(base: *mut u8, len: usize) -> Result<Ring> {
+        if len < HEADER_LEN {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "SHM too small for ring header").into());
+        }
+        let header = unsafe { &*(base as *const RingHeader) };
+        let slot_count = header.slot_count as usize;
+        let slot_size = header.slot_size as usize;
+        if header.magic != RING_MAGIC || slot_count == 0 || region_size(slot_count, slot_size) > len {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "SHM does not hold a valid ring").into());
+        }
+        Ok(Ring { base, slot_count: slot_count as u64, slot_size, stride: slot_stride(slot_size) })
+    }
+
+    fn header(&self) -> &RingHeader {
+        unsafe { &*(self.base as *const RingHeader) }
+    }
+
+    fn slot(&self, seq: u64) -> (&SlotHeader, *mut u8) {
+        let index = (seq % self.slot_count) as usize;
+        unsafe {
+            let slot = self.base.add(HEADER_LEN + index * self.stride);
+            (&*(slot as *const SlotHeader), slot.add(SLOT_HEADER_LEN))
+        }
+    }
+
+    // Generation a slot carries once the message with sequence `seq` is
+    // completely written.
+    fn generation(&self, seq: u64) -> u64 {
+        2 * (seq / self.slot_count) + 2
+    }
+}
+
+/// What a ring producer does when every slot is still unread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the consumer to free a slot.
+    #[default]
+    Block,
+    /// Discard the message being sent.
+    DropNewest,
+    /// Discard the oldest unread message to make room.
+    DropOldest,
+}
+
+struct RingProducer {
+    ring: Ring,
+    doorbell: RawFd,
+    space: RawFd,
+    head: u64,
+    policy: OverflowPolicy,
+    max_in_flight: Option<u64>,
+    // How long `OverflowPolicy::Block` waits for a slot.
+    block_timeout: Option<Duration>,
+}
+
+impl RingProducer {
+    fn new(ring: Ring, doorbell: RawFd, space: RawFd, policy: OverflowPolicy, max_in_flight: Option<usize>,
+           block_timeout: Option<Duration>) -> Self {
+        let head = ring.header().head.load(Ordering::Acquire);
+        Self { ring, doorbell, space, head, policy, max_in_flight: max_in_flight.map(|max| max as u64), block_timeout }
+    }
+
+    // Returns whether a frame was dropped to honour the overflow policy.
+    fn send(&mut self, data: &[u8]) -> Result<bool> {
+        if data.len() > self.ring.slot_size {
+            return Err(EfdStreamError::DataTooLarge { len: data.len(), cap: self.ring.slot_size });
+        }
+        // Checked ahead of the overflow policy: hitting the limit is reported,
+        // never waited out or made room for.
+        if let Some(max) = self.max_in_flight {
+            let in_flight = self.in_flight();
+            if in_flight >= max {
+                return Err(EfdStreamError::TooManyInFlight { in_flight: in_flight as usize, max: max as usize });
+            }
+        }
+        let dropped = match self.policy {
+            OverflowPolicy::Block => {
+                self.wait_for_space()?;
+                false
+            }
+            OverflowPolicy::DropNewest => {
+                if self.is_full() {
+                    return Ok(true);
+                }
+                false
+            }
+            OverflowPolicy::DropOldest => self.drop_oldest(),
+        };
+
+        let (slot, payload) = self.ring.slot(self.head);
+        let generation = self.ring.generation(self.head);
+        slot.generation.store(generation - 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), payload, data.len());
+        }
+        slot.len.store(data.len() as u64, Ordering::Relaxed);
+        slot.generation.store(generation, Ordering::Release);
+
+        self.head += 1;
+        let header = self.ring.header();
+        header.head.store(self.head, Ordering::SeqCst);
+        if header.consumer_waiting.swap(0, Ordering::SeqCst) != 0 {
+            eventfd_write(unsafe { BorrowedFd::borrow_raw(self.doorbell) }, 1)?;
+        }
+        Ok(dropped)
+    }
+
+    // Sent messages the consumer hasn't read yet.
+    fn in_flight(&self) -> u64 {
+        self.head - self.ring.header().tail.load(Ordering::Acquire)
+    }
+
+    fn is_full(&self) -> bool {
+        self.in_flight() >= self.ring.slot_count
+    }
+
+    // Claims the oldest slot by moving the tail past it. The consumer claims
+    // slots with the same compare-exchange, so exactly one side wins; if the
+    // consumer got there first a slot is free and nothing is dropped.
+    fn drop_oldest(&mut self) -> bool {
+        let tail = self.ring.header().tail.load(Ordering::Acquire);
+        if self.head - tail < self.ring.slot_count {
+            return false;
+        }
+        self.ring.header().tail
+            .compare_exchange(tail, tail + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    fn wait_for_space(&mut self) -> Result<()> {
+        let deadline = self.block_timeout.map(|timeout| Instant::now() + timeout);
+        self.wait_in_flight(self.ring.slot_count - 1, deadline)
+    }
+
+    // Blocks until at most `limit` sent messages are still unread, or fails
+    // with `EfdStreamError::Timeout` once `deadline` passes. A flag left
+    // raised by a timeout only costs the consumer a spurious wakeup.
+    fn wait_in_flight(&mut self, limit: u64, deadline: Option<Instant>) -> Result<()> {
+        let header = self.ring.header();
+        loop {
+            if self.head - header.tail.load(Ordering::Acquire) <= limit {
+                return Ok(());
+            }
+            // Publish the flag, then look again so a consumer that freed a
+            // slot in between can't be missed.
+            header.producer_waiting.store(1, Ordering::SeqCst);
+            if self.head - header.tail.load(Ordering::SeqCst) <= limit {
+                return Ok(());
+            }
+            let space = unsafe { BorrowedFd::borrow_raw(self.space) };
+            if deadline.is_some() {
+                wait_readable_or_exit(space, None, deadline)?;
+            }
+            eventfd_read(space)?;
+        }
+    }
+}
+
+struct RingConsumer {
+    ring: Ring,
+    doorbell: RawFd,
+    space: RawFd,
+    ack_batch: u64,
+    // Slots freed since the producer was last woken.
+    unacked: u64,
+}
+
+impl RingConsumer {
+    fn new(ring: Ring, doorbell: RawFd, space: RawFd, ack_batch: u64) -> Self {
+        Self { ring, doorbell, space, ack_batch: ack_batch.max(1), unacked: 0 }
+    }
+
+    fn try_read(&mut self) -> Result<Option<Vec<u8>>> {
+        let header = self.ring.header();
+        loop {
+            let seq = header.tail.load(Ordering::Acquire);
+            if header.head.load(Ordering::Acquire) == seq {
+                self.flush_acks()?;
+                return Ok(None);
+            }
+
+            let expected = self.ring.generation(seq);
+            let (slot, payload) = self.ring.slot(seq);
+            let before = slot.generation.load(Ordering::Acquire);
+            let len = slot.len.load(Ordering::Relaxed) as usize;
+            let data = if before == expected && len <= self.ring.slot_size {
+                unsafe { std::slice::from_raw_parts(payload, len).to_vec() }
+            } else {
+                Vec::new()
+            };
+            fence(Ordering::Acquire);
+            let after = slot.generation.load(Ordering::Relaxed);
+
+            // A drop-oldest producer may have claimed this slot meanwhile;
+            // whatever was copied is then discarded and the next one tried.
+            if header.tail.compare_exchange(seq, seq + 1, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+                continue;
+            }
+            // The slot is given back either way; a stale frame is lost, not retried.
+            self.unacked += 1;
+            if self.unacked >= self.ack_batch {
+                self.flush_acks()?;
+            }
+            if before != expected || after != expected || len > self.ring.slot_size {
+                return Err(EfdStreamError::StaleRead { seq });
+            }
+            return Ok(Some(data));
+        }
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(data) = self.try_read()? {
+                return Ok(data);
+            }
+            let header = self.ring.header();
+            header.consumer_waiting.store(1, Ordering::SeqCst);
+            if header.head.load(Ordering::SeqCst) != header.tail.load(Ordering::SeqCst) {
+                continue;
+            }
+            eventfd_read(unsafe { BorrowedFd::borrow_raw(self.doorbell) })?;
+        }
+    }
+
+    // Wakes a producer waiting for space, if any slots were freed.
+    fn flush_acks(&mut self) -> Result<()> {
+        if self.unacked == 0 {
+            return Ok(());
+        }
+        self.unacked = 0;
+        if self.ring.header().producer_waiting.swap(0, Ordering::SeqCst) != 0 {
+            eventfd_write(unsafe { BorrowedFd::borrow_raw(self.space) }, 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parent side of the ring-buffer mode: `send_data` only blocks when all
+/// slots are in flight, instead of waiting for an ACK per message.
+pub struct RingShmParent {
+    inner: ShmParent,
+    slot_count: usize,
+    slot_size: usize,
+    policy: OverflowPolicy,
+    ack_batch: u64,
+    max_in_flight: Option<usize>,
+    block_timeout: Option<Duration>,
+    tx: Option<RingProducer>,
+    rx: Option<RingConsumer>,
+}
+
+impl RingShmParent {
+    pub fn new(child_path: &str, slot_count: usize, slot_size: usize) -> Self {
+        Self {
+            inner: ShmParent::new(child_path, region_size(slot_count, slot_size)),
+            slot_count,
+            slot_size,
+            policy: OverflowPolicy::Block,
+            ack_batch: 1,
+            max_in_flight: None,
+            block_timeout: None,
+            tx: None,
+            rx: None,
+        }
+    }
+
+    /// `ShmParentBuilder::child_args`: extra arguments after the crate's own,
+    /// `-ring` included.
+    pub fn child_args(mut self, args: &[&str]) -> Self {
+        self.inner.child_args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Sets what `send_data` does when the P2C ring is full. Defaults to
+    /// `OverflowPolicy::Block`.
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Wake the child, when it is blocked on a full C2P ring, only after
+    /// reading `n` of its messages rather than after each one. Fewer
+    /// syscalls on the ACK path, at the cost of the child waiting a little
+    /// longer for room. Defaults to 1.
+    pub fn ack_batch(mut self, n: u64) -> Self {
+        self.ack_batch = n;
+        self
+    }
+
+    /// Fail `send_data` with `EfdStreamError::TooManyInFlight` instead of
+    /// sending when `n` messages are already waiting for the child to read
+    /// them. Takes precedence over the overflow policy. By default only the
+    /// slot count bounds it.
+    pub fn max_in_flight(mut self, n: usize) -> Self {
+        self.max_in_flight = Some(n);
+        self
+    }
+
+    /// Under `OverflowPolicy::Block`, make a `send_data` that has waited
+    /// `timeout` for a free slot give up with `EfdStreamError::Timeout`,
+    /// so a stuck child can't hang the parent. Nothing is sent then.
+    /// `None`, the default, waits forever.
+    pub fn block_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.block_timeout = timeout;
+        self
+    }
+
+    /// Messages sent that the child hasn't read yet.
+    pub fn in_flight(&self) -> usize {
+        self.tx.as_ref().map_or(0, |tx| tx.in_flight() as usize)
+    }
+
+    /// Frames discarded by the overflow policy; also counted in `metrics()`.
+    pub fn dropped_frames(&self) -> u64 {
+        self.inner.metrics().dropped_frames
+    }
+
+    /// Slots of the P2C ring in use right now, out of the slot count: the
+    /// same count as `in_flight`, read from the ring's head and tail. The
+    /// peak after any send is `metrics().max_occupancy`, for sizing the
+    /// ring: one that never nears the slot count can shrink, and one that
+    /// sits at it is holding the parent back.
+    pub fn ring_occupancy(&self) -> usize {
+        self.in_flight()
+    }
+
+    /// See `ShmParent::control_atomic`.
+    pub fn control_atomic(&self) -> Option<&AtomicU64> {
+        self.inner.control_atomic()
+    }
+
+    /// Only `dropped_frames` and `max_occupancy` are tracked in ring mode.
+    pub fn metrics(&self) -> &Metrics {
+        self.inner.metrics()
+    }
+
+    /// Allocates both rings and spawns the child with `-ring` appended.
+    pub fn start(&mut self) -> Result<()> {
+        if self.slot_count == 0 || self.slot_count > u32::MAX as usize {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid ring slot count").into());
+        }
+        self.inner.allocate()?;
+        let (p2c, c2p) = unsafe {
+            (Ring::create(self.inner.shm_p2c.as_ptr(), self.slot_count, self.slot_size),
+             Ring::create(self.inner.shm_c2p.as_ptr(), self.slot_count, self.slot_size))
+        };
+        let fd = |file: &Option<std::fs::File>| file.as_ref().unwrap().as_raw_fd();
+        self.tx = Some(RingProducer::new(p2c, fd(&self.inner.file_p2c_send), fd(&self.inner.file_p2c_ack), self.policy,
+                                         self.max_in_flight, self.block_timeout));
+        self.rx = Some(RingConsumer::new(c2p, fd(&self.inner.file_c2p_send), fd(&self.inner.file_c2p_ack), self.ack_batch));
+        self.inner.spawn(&["-ring"])
+    }
+
+    pub fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        let (Some(tx), Some(rx)) = (&mut self.tx, &mut self.rx) else {
+            return Err(EfdStreamError::NotStarted);
+        };
+        // The child may be blocked on our ACKs while we block on its reads.
+        rx.flush_acks()?;
+        if tx.send(data)? {
+            self.inner.metrics.record_drop();
+        }
+        self.inner.metrics.record_occupancy(tx.in_flight() as usize);
+        Ok(())
+    }
+
+    /// Blocks until the child has read every message sent so far.
+    pub fn flush(&mut self) -> Result<()> {
+        match &mut self.tx {
+            Some(tx) => tx.wait_in_flight(0, None),
+            None => Err(EfdStreamError::NotStarted),
+        }
+    }
+
+    /// Largest message `send_data` accepts.
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        match &mut self.rx {
+            Some(rx) => rx.read(),
+            None => Err(EfdStreamError::NotStarted),
+        }
+    }
+
+    pub fn try_read_data(&mut self) -> Result<Option<Vec<u8>>> {
+        match &mut self.rx {
+            Some(rx) => rx.try_read(),
+            None => Err(EfdStreamError::NotStarted),
+        }
+    }
+
+    /// See `ShmParent::drain`. Takes every frame queued in the C2P ring,
+    /// up to `timeout`.
+    pub fn drain(&mut self, timeout: Duration) -> Result<Vec<Vec<u8>>> {
+        drain_with(timeout, || self.try_read_data())
+    }
+}
+
+/// Child side of the ring-buffer mode. Takes the same fds and `-shm-size`
+/// as `ShmChild`; the ring geometry is read from the regions themselves.
+pub struct RingShmChild {
+    inner: ShmChild,
+    policy: OverflowPolicy,
+    ack_batch: u64,
+    max_in_flight: Option<usize>,
+    block_timeout: Option<Duration>,
+    dropped_frames: u64,
+    tx: Option<RingProducer>,
+    rx: Option<RingConsumer>,
+}
+
+impl RingShmChild {
+    pub fn new(fd_p2c_send: RawFd, fd_p2c_ack: RawFd, fd_p2c_shm: RawFd,
+               fd_c2p_send: RawFd, fd_c2p_ack: RawFd, fd_c2p_shm: RawFd,
+               shm_size: usize) -> Self {
+        let mut inner = ShmChild::new(fd_p2c_send, fd_p2c_ack, fd_p2c_shm,
+                                      fd_c2p_send, fd_c2p_ack, fd_c2p_shm, shm_size);
+        // The consumer advances the tail stored in the P2C region.
+        inner.p2c_writable = true;
+        Self { inner, policy: OverflowPolicy::Block, ack_batch: 1, max_in_flight: None, block_timeout: None,
+               dropped_frames: 0, tx: None, rx: None }
+    }
+
+    /// Sets what `send_data` does when the C2P ring is full. Defaults to
+    /// `OverflowPolicy::Block`.
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The child half of `RingShmParent::ack_batch`: wake a parent blocked
+    /// on a full P2C ring only every `n` messages read.
+    pub fn ack_batch(mut self, n: u64) -> Self {
+        self.ack_batch = n;
+        self
+    }
+
+    /// See `RingShmParent::max_in_flight`; bounds this side's sends.
+    pub fn max_in_flight(mut self, n: usize) -> Self {
+        self.max_in_flight = Some(n);
+        self
+    }
+
+    /// See `RingShmParent::block_timeout`; bounds this side's sends.
+    pub fn block_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.block_timeout = timeout;
+        self
+    }
+
+    /// Messages sent that the parent hasn't read yet.
+    pub fn in_flight(&self) -> usize {
+        self.tx.as_ref().map_or(0, |tx| tx.in_flight() as usize)
+    }
+
+    /// Frames discarded by the overflow policy.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    /// See `ShmChild::control_atomic`.
+    pub fn control_atomic(&self) -> Option<&AtomicU64> {
+        self.inner.control_atomic()
+    }
+
+    pub fn init(&mut self) -> Result<()> {
+        self.inner.init()?;
+        let (p2c, c2p) = unsafe {
+            (Ring::attach(self.inner.shm_p2c.as_ptr(), self.inner.p2c_size)?,
+             Ring::attach(self.inner.shm_c2p.as_ptr(), self.inner.c2p_size)?)
+        };
+        self.rx = Some(RingConsumer::new(p2c, self.inner.fd_p2c_send, self.inner.fd_p2c_ack, self.ack_batch));
+        self.tx = Some(RingProducer::new(c2p, self.inner.fd_c2p_send, self.inner.fd_c2p_ack, self.policy, self.max_in_flight,
+                                           self.block_timeout));
+        Ok(())
+    }
+
+    pub fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        if self.tx.is_none() {
+            self.init()?;
+        }
+        self.rx.as_mut().unwrap().flush_acks()?;
+        if self.tx.as_mut().unwrap().send(data)? {
+            self.dropped_frames += 1;
+        }
+        Ok(())
+    }
+
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        if self.rx.is_none() {
+            self.init()?;
+        }
+        self.rx.as_mut().unwrap().read()
+    }
+
+    pub fn try_read_data(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.rx.is_none() {
+            self.init()?;
+        }
+        self.rx.as_mut().unwrap().try_read()
+    }
+}
@@ -0,0 +1,280 @@
+// Fallback transport over a Unix socketpair, for hosts where memfd or shared
+// mappings aren't available. The pair is SOCK_SEQPACKET, so each message is
+// one packet that the receiver gets whole, with no length prefix to
+// reassemble: the same one-message-per-doorbell semantics as the SHM path.
+// The socket buffer gives back-pressure, so there is no ACK.
+//
+// The child inherits its end as fd 3 and is passed `-socket -fd-socket 3`
+// plus `-shm-size`, which here is the largest message either side accepts.
+
+use std::io::ErrorKind;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+
+use nix::errno::Errno;
+use nix::sys::socket::{getsockopt, recv, send, setsockopt, shutdown, socketpair, sockopt, AddressFamily, MsgFlags, Shutdown, SockFlag, SockType};
+
+use crate::error::{EfdStreamError, Result};
+use crate::transport::DuplexChannel;
+
+// Room the kernel wants in the send buffer beyond the payload itself.
+const SNDBUF_SLACK: usize = 4096;
+
+struct PacketSocket {
+    fd: OwnedFd,
+    max_size: usize,
+    send_shut_down: bool,
+    peer_shut_down: bool,
+}
+
+impl PacketSocket {
+    fn new(fd: OwnedFd, max_size: usize) -> Self {
+        // A packet larger than the send buffer fails with EMSGSIZE, so grow
+        // it to fit `max_size` if the system allows; `send` reports the rest.
+        let wanted = max_size.saturating_add(SNDBUF_SLACK);
+        if getsockopt(&fd, sockopt::SndBuf).is_ok_and(|size| size < wanted) {
+            let _ = setsockopt(&fd, sockopt::SndBuf, &wanted);
+        }
+        Self { fd, max_size, send_shut_down: false, peer_shut_down: false }
+    }
+
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > self.max_size {
+            return Err(EfdStreamError::DataTooLarge { len: data.len(), cap: self.max_size });
+        }
+        // An empty packet reads as end of stream, just as the SHM doorbell
+        // can't carry 0.
+        if data.is_empty() {
+            return Err(EfdStreamError::ReservedLength { len: 0 });
+        }
+        if self.send_shut_down {
+            return Err(std::io::Error::new(ErrorKind::BrokenPipe, "Send side shut down").into());
+        }
+        loop {
+            match send(self.fd.as_raw_fd(), data, MsgFlags::MSG_NOSIGNAL) {
+                Ok(_) => return Ok(()),
+                Err(Errno::EINTR) => {}
+                Err(Errno::EMSGSIZE) => {
+                    let limit = getsockopt(&self.fd, sockopt::SndBuf).unwrap_or(0);
+                    let msg = format!("Message of {} bytes exceeds the socket send buffer ({} bytes); raise net.core.wmem_max", data.len(), limit);
+                    return Err(std::io::Error::new(ErrorKind::InvalidInput, msg).into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // Returns the next message. With `wait` false it never blocks and
+    // returns `None` if nothing has arrived yet.
+    fn recv(&mut self, wait: bool) -> Result<Option<Vec<u8>>> {
+        if self.peer_shut_down {
+            return Err(EfdStreamError::PeerClosed);
+        }
+        let wait = if wait { MsgFlags::empty() } else { MsgFlags::MSG_DONTWAIT };
+        // Peeked with an empty buffer first: MSG_TRUNC makes the kernel report
+        // the packet's full length, so the buffer is sized exactly and an
+        // oversize packet is caught rather than cut short.
+        let len = match recv_retrying(&self.fd, &mut [], wait | MsgFlags::MSG_PEEK | MsgFlags::MSG_TRUNC) {
+            Ok(0) => {
+                self.peer_shut_down = true;
+                return Err(EfdStreamError::PeerClosed);
+            }
+            Ok(len) => len,
+            Err(Errno::EAGAIN) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // An oversize packet is still taken off the queue, into an empty
+        // buffer, so the next receive sees the message after it.
+        let mut buf = vec![0; if len > self.max_size { 0 } else { len }];
+        recv_retrying(&self.fd, &mut buf, MsgFlags::MSG_DONTWAIT)?;
+        if len > self.max_size {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "Received message exceeds max message size").into());
+        }
+        Ok(Some(buf))
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if !self.send_shut_down {
+            shutdown(self.fd.as_raw_fd(), Shutdown::Write)?;
+            self.send_shut_down = true;
+        }
+        Ok(())
+    }
+}
+
+fn recv_retrying(fd: &OwnedFd, buf: &mut [u8], flags: MsgFlags) -> nix::Result<usize> {
+    loop {
+        match recv(fd.as_raw_fd(), buf, flags) {
+            Err(Errno::EINTR) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Parent side of the socket transport: spawns the child like `ShmParent`,
+/// but with one end of a socketpair instead of eventfds and SHM. Only Rust
+/// children understand `-socket`.
+pub struct SocketParent {
+    child_path: String,
+    max_size: usize,
+    socket: Option<PacketSocket>,
+    child: Option<Child>,
+}
+
+impl SocketParent {
+    /// `max_size` bounds the messages either side sends, like `shm_size`.
+    pub fn new(child_path: &str, max_size: usize) -> Self {
+        Self { child_path: child_path.to_string(), max_size, socket: None, child: None }
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        let (local, remote) = socketpair(AddressFamily::Unix, SockType::SeqPacket, None, SockFlag::SOCK_CLOEXEC)?;
+        let raw_remote = remote.as_raw_fd();
+
+        let mut cmd = Command::new(&self.child_path);
+        cmd.arg("-mode").arg("child");
+        cmd.arg("-socket");
+        cmd.arg("-fd-socket").arg("3");
+        cmd.arg("-shm-size").arg(self.max_size.to_string());
+        cmd.stdin(Stdio::inherit());
+        cmd.stdout(Stdio::inherit());
+        cmd.stderr(Stdio::inherit());
+
+        unsafe {
+            cmd.pre_exec(move || {
+                // dup2 onto itself would keep close-on-exec set.
+                if raw_remote == 3 {
+                    if libc::fcntl(3, libc::F_SETFD, 0) == -1 { return Err(std::io::Error::last_os_error()); }
+                } else if libc::dup2(raw_remote, 3) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        self.child = Some(cmd.spawn()?);
+        self.socket = Some(PacketSocket::new(local, self.max_size));
+        Ok(())
+    }
+
+    /// Largest message `send_data` accepts.
+    pub fn usable_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// The parent's end of the socketpair, readable when the child has sent
+    /// something. `None` before `start`.
+    pub fn socket_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.socket.as_ref().map(|socket| socket.fd.as_fd())
+    }
+
+    pub fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        self.socket()?.send(data)
+    }
+
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        Ok(self.socket()?.recv(true)?.expect("blocking recv returns a message"))
+    }
+
+    pub fn try_read_data(&mut self) -> Result<Option<Vec<u8>>> {
+        self.socket()?.recv(false)
+    }
+
+    /// Half-closes the socket: the child's reads fail with `PeerClosed`
+    /// once it has read everything sent before.
+    pub fn shutdown_send(&mut self) -> Result<()> {
+        self.socket()?.shutdown()
+    }
+
+    fn socket(&mut self) -> Result<&mut PacketSocket> {
+        self.socket.as_mut().ok_or(EfdStreamError::NotStarted)
+    }
+}
+
+impl Drop for SocketParent {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Child side of the socket transport.
+pub struct SocketChild {
+    socket: PacketSocket,
+}
+
+impl SocketChild {
+    /// Takes ownership of the inherited socket `fd` (3, as the parent passes
+    /// it with `-fd-socket`) and closes it on drop.
+    pub fn new(fd: RawFd, max_size: usize) -> Self {
+        Self::from_fd(unsafe { OwnedFd::from_raw_fd(fd) }, max_size)
+    }
+
+    /// Uses an already-connected `SOCK_SEQPACKET` socket, e.g. one half of a
+    /// `socketpair`. A stream socket would not keep message boundaries.
+    pub fn from_fd(fd: OwnedFd, max_size: usize) -> Self {
+        Self { socket: PacketSocket::new(fd, max_size) }
+    }
+
+    pub fn usable_size(&self) -> usize {
+        self.socket.max_size
+    }
+
+    pub fn socket_fd(&self) -> BorrowedFd<'_> {
+        self.socket.fd.as_fd()
+    }
+
+    pub fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        self.socket.send(data)
+    }
+
+    pub fn read_data(&mut self) -> Result<Vec<u8>> {
+        Ok(self.socket.recv(true)?.expect("blocking recv returns a message"))
+    }
+
+    pub fn try_read_data(&mut self) -> Result<Option<Vec<u8>>> {
+        self.socket.recv(false)
+    }
+
+    pub fn shutdown_send(&mut self) -> Result<()> {
+        self.socket.shutdown()
+    }
+}
+
+impl DuplexChannel for SocketParent {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.send_data(data)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>> {
+        self.read_data()
+    }
+
+    fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.try_read_data()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.shutdown_send()
+    }
+}
+
+impl DuplexChannel for SocketChild {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.send_data(data)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>> {
+        self.read_data()
+    }
+
+    fn try_recv(&mut self) -> Result<Option<Vec<u8>>> {
+        self.try_read_data()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.shutdown_send()
+    }
+}
@@ -0,0 +1,305 @@
+// Starting the child with its descriptors at the numbers it was told. The default
+// goes through `Command` with a `pre_exec` closure that dup2s them into place,
+// which makes std fork. `SpawnMode::PosixSpawn` expresses the same layout as
+// spawn file actions so libc can use vfork, whose cost doesn't grow with the
+// parent's address space.
+
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::ptr;
+
+use crate::error::{EfdStreamError, Result};
+
+/// How `ShmParent::start` creates the child process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpawnMode {
+    /// `std::process::Command`: fork, dup2 the fds, exec.
+    #[default]
+    ForkExec,
+    /// `posix_spawnp` with `POSIX_SPAWN_USEVFORK`, for parents with a large
+    /// address space where copying page tables on fork is slow.
+    PosixSpawn,
+}
+
+/// What dropping a `ShmParent` does with the child it spawned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DropBehavior {
+    /// Kill it with `SIGKILL`, with its whole group under `new_session`.
+    #[default]
+    Kill,
+    /// Send the EOF sentinel, as `shutdown_send` does, then block until it
+    /// exits. A child that ignores EOF blocks the drop indefinitely.
+    Wait,
+    /// Leave it running, e.g. a daemon the parent only launched. Nothing
+    /// reaps it, so once it exits it stays a zombie until this process does.
+    Detach,
+}
+
+/// Descriptor numbers the six channel fds get in the child. They are passed
+/// on as the `-fd-*` arguments, so any child that reads those follows along.
+/// Defaults to 3 through 8, which is what the Go and C children assume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdLayout {
+    pub p2c_send: RawFd,
+    pub p2c_ack: RawFd,
+    pub p2c_shm: RawFd,
+    pub c2p_send: RawFd,
+    pub c2p_ack: RawFd,
+    pub c2p_shm: RawFd,
+}
+
+impl Default for FdLayout {
+    fn default() -> Self {
+        Self { p2c_send: 3, p2c_ack: 4, p2c_shm: 5, c2p_send: 6, c2p_ack: 7, c2p_shm: 8 }
+    }
+}
+
+impl FdLayout {
+    /// The six numbers from `base` up, in the default's order.
+    pub fn from_base(base: RawFd) -> Self {
+        Self { p2c_send: base, p2c_ack: base + 1, p2c_shm: base + 2, c2p_send: base + 3, c2p_ack: base + 4, c2p_shm: base + 5 }
+    }
+
+    // Each target with the name of its `-fd-*` argument.
+    pub(crate) fn targets(&self) -> [(&'static str, RawFd); 6] {
+        [("p2c-send", self.p2c_send), ("p2c-ack", self.p2c_ack), ("p2c-shm", self.p2c_shm),
+         ("c2p-send", self.c2p_send), ("c2p-ack", self.c2p_ack), ("c2p-shm", self.c2p_shm)]
+    }
+
+    /// Fails with `EfdStreamError::DuplicateFd` if two targets are the same
+    /// number, which would have one `dup2` silently replace the other, or if
+    /// one is stdin, stdout or stderr.
+    pub fn validate(&self) -> Result<()> {
+        check_targets(&self.targets().map(|(_, fd)| fd))
+    }
+
+    // First number above the layout, for the optional control and priority fds.
+    pub(crate) fn next_free(&self) -> RawFd {
+        self.targets().iter().map(|&(_, fd)| fd).max().unwrap() + 1
+    }
+}
+
+// The same checks as `FdLayout::validate`, over every descriptor number the
+// child is given.
+pub(crate) fn check_targets(targets: &[RawFd]) -> Result<()> {
+    for (i, &fd) in targets.iter().enumerate() {
+        if fd < 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Negative fd in layout").into());
+        }
+        if fd <= 2 || targets[..i].contains(&fd) {
+            return Err(EfdStreamError::DuplicateFd { fd });
+        }
+    }
+    Ok(())
+}
+
+// A spawned child. posix_spawn only gives a pid, which std can't adopt.
+pub(crate) enum ChildProcess {
+    Command(Child),
+    Spawned { pid: libc::pid_t, status: Option<ExitStatus> },
+}
+
+impl ChildProcess {
+    pub(crate) fn id(&self) -> u32 {
+        match self {
+            ChildProcess::Command(child) => child.id(),
+            ChildProcess::Spawned { pid, .. } => *pid as u32,
+        }
+    }
+
+    pub(crate) fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        match self {
+            ChildProcess::Command(child) => child.wait(),
+            ChildProcess::Spawned { status: Some(status), .. } => Ok(*status),
+            ChildProcess::Spawned { pid, status } => loop {
+                let mut raw = 0;
+                if unsafe { libc::waitpid(*pid, &mut raw, 0) } == -1 {
+                    let e = std::io::Error::last_os_error();
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(e);
+                }
+                return Ok(*status.insert(ExitStatus::from_raw(raw)));
+            },
+        }
+    }
+
+    // `wait` without blocking: `None` while the child is still running.
+    pub(crate) fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        match self {
+            ChildProcess::Command(child) => child.try_wait(),
+            ChildProcess::Spawned { status: Some(status), .. } => Ok(Some(*status)),
+            ChildProcess::Spawned { pid, status } => {
+                let mut raw = 0;
+                match unsafe { libc::waitpid(*pid, &mut raw, libc::WNOHANG) } {
+                    -1 => Err(std::io::Error::last_os_error()),
+                    0 => Ok(None),
+                    _ => Ok(Some(*status.insert(ExitStatus::from_raw(raw)))),
+                }
+            }
+        }
+    }
+
+    // Once reaped the pid may belong to someone else, so it is left alone.
+    pub(crate) fn kill(&mut self) -> std::io::Result<()> {
+        match self {
+            ChildProcess::Command(child) => child.kill(),
+            ChildProcess::Spawned { pid, status: None } => {
+                if unsafe { libc::kill(*pid, libc::SIGKILL) } == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            }
+            ChildProcess::Spawned { .. } => Ok(()),
+        }
+    }
+}
+
+/// Runs `program` with `args`, inheriting stdio, with each `(fd, target)`
+/// in `fds` dup2'd to `target` in the child. With `new_session` the child
+/// calls `setsid` first, leading a new session and process group.
+pub(crate) fn spawn_child(mode: SpawnMode, new_session: bool, program: &str, args: &[String],
+                          env: &[(String, String)], fds: &[(RawFd, RawFd)]) -> std::io::Result<ChildProcess> {
+    // A source that is also an earlier pair's target would be replaced before
+    // it is duplicated, so every source is first copied above all targets.
+    // The copies are close-on-exec and closed here once the child is running.
+    let floor = fds.iter().map(|&(_, target)| target).max().map_or(0, |max| max + 1);
+    let staged = fds.iter()
+        .map(|&(fd, _)| match unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, floor) } {
+            -1 => Err(std::io::Error::last_os_error()),
+            copy => Ok(unsafe { OwnedFd::from_raw_fd(copy) }),
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let fds: Vec<(RawFd, RawFd)> = staged.iter().zip(fds).map(|(copy, &(_, target))| (copy.as_raw_fd(), target)).collect();
+    let fds = &fds[..];
+
+    match mode {
+        SpawnMode::ForkExec => {
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            cmd.envs(env.iter().map(|(key, value)| (key, value)));
+            cmd.stdin(Stdio::inherit());
+            cmd.stdout(Stdio::inherit());
+            cmd.stderr(Stdio::inherit());
+
+            let fds = fds.to_vec();
+            unsafe {
+                cmd.pre_exec(move || {
+                    if new_session && libc::setsid() == -1 { return Err(std::io::Error::last_os_error()); }
+                    for &(fd, target) in &fds {
+                        if libc::dup2(fd, target) == -1 { return Err(std::io::Error::last_os_error()); }
+                    }
+                    Ok(())
+                });
+            }
+            Ok(ChildProcess::Command(cmd.spawn()?))
+        }
+        SpawnMode::PosixSpawn => posix_spawn(program, args, env, fds, new_session).map(|pid| ChildProcess::Spawned { pid, status: None }),
+    }
+}
+
+// Whether exec would find `program` the way both spawn modes look it up:
+// as given if it contains a slash, and on `PATH` otherwise.
+pub(crate) fn check_executable(program: &str) -> std::io::Result<()> {
+    let path = std::env::var_os("PATH").unwrap_or_else(|| "/usr/local/bin:/usr/bin:/bin".into());
+    let candidates: Vec<std::path::PathBuf> = if program.contains('/') {
+        vec![program.into()]
+    } else {
+        std::env::split_paths(&path).map(|dir| dir.join(program)).collect()
+    };
+    let mut found = false;
+    for candidate in candidates {
+        if !candidate.metadata().is_ok_and(|meta| meta.is_file()) {
+            continue;
+        }
+        found = true;
+        let candidate = c_string(candidate.as_os_str())?;
+        if unsafe { libc::access(candidate.as_ptr(), libc::X_OK) } == 0 {
+            return Ok(());
+        }
+    }
+    if found {
+        Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{} is not executable", program)))
+    } else {
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found", program)))
+    }
+}
+
+fn c_string(s: &OsStr) -> std::io::Result<CString> {
+    CString::new(s.as_bytes())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Argument contains a NUL byte"))
+}
+
+// posix_spawn functions return the error number instead of setting errno.
+fn check(ret: libc::c_int) -> std::io::Result<()> {
+    if ret != 0 {
+        return Err(std::io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+struct FileActions(libc::posix_spawn_file_actions_t);
+
+impl Drop for FileActions {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawn_file_actions_destroy(&mut self.0) };
+    }
+}
+
+struct SpawnAttr(libc::posix_spawnattr_t);
+
+impl Drop for SpawnAttr {
+    fn drop(&mut self) {
+        unsafe { libc::posix_spawnattr_destroy(&mut self.0) };
+    }
+}
+
+fn posix_spawn(program: &str, args: &[String], env: &[(String, String)], fds: &[(RawFd, RawFd)],
+               new_session: bool) -> std::io::Result<libc::pid_t> {
+    let program = c_string(OsStr::new(program))?;
+    let mut argv_owned = vec![program.clone()];
+    for arg in args {
+        argv_owned.push(c_string(OsStr::new(arg))?);
+    }
+    // The environment as std would pass it, with `env` replacing any
+    // variable of the same name; entries can't contain NUL.
+    let envp_owned: Vec<CString> = std::env::vars_os()
+        .filter(|(key, _)| !env.iter().any(|(name, _)| key == name.as_str()))
+        .chain(env.iter().map(|(key, value)| (key.into(), value.into())))
+        .filter_map(|(key, value)| {
+            let mut entry = key.into_encoded_bytes();
+            entry.push(b'=');
+            entry.extend_from_slice(value.as_bytes());
+            CString::new(entry).ok()
+        })
+        .collect();
+
+    let mut argv: Vec<*mut libc::c_char> = argv_owned.iter().map(|arg| arg.as_ptr() as *mut _).collect();
+    argv.push(ptr::null_mut());
+    let mut envp: Vec<*mut libc::c_char> = envp_owned.iter().map(|entry| entry.as_ptr() as *mut _).collect();
+    envp.push(ptr::null_mut());
+
+    unsafe {
+        let mut actions = FileActions(std::mem::zeroed());
+        check(libc::posix_spawn_file_actions_init(&mut actions.0))?;
+        for &(fd, target) in fds {
+            check(libc::posix_spawn_file_actions_adddup2(&mut actions.0, fd, target))?;
+        }
+
+        let mut attr = SpawnAttr(std::mem::zeroed());
+        check(libc::posix_spawnattr_init(&mut attr.0))?;
+        let mut flags = libc::POSIX_SPAWN_USEVFORK as libc::c_short;
+        if new_session {
+            flags |= libc::POSIX_SPAWN_SETSID as libc::c_short;
+        }
+        check(libc::posix_spawnattr_setflags(&mut attr.0, flags))?;
+
+        let mut pid = 0;
+        check(libc::posix_spawnp(&mut pid, program.as_ptr(), &actions.0, &attr.0, argv.as_ptr(), envp.as_ptr()))?;
+        Ok(pid)
+    }
+}
@@ -0,0 +1,55 @@
+// Spans and events for the `tracing` feature. Each send and receive gets a
+// span; the fields borrow the OpenTelemetry messaging names, with the
+// direction as the destination, so an otel layer exports them unchanged.
+// The span is entered around the whole call, so events from a subscriber's
+// own layers nest under it.
+
+use tracing::field::Empty;
+use tracing::Span;
+
+use crate::error::{EfdStreamError, Result};
+
+pub(crate) fn send_span(direction: &'static str, size: usize) -> Span {
+    tracing::debug_span!("efdstream.send",
+        messaging.system = "efdstream",
+        messaging.operation = "publish",
+        messaging.destination.name = direction,
+        messaging.message.body.size = size,
+        efdstream.rtt_us = Empty,
+    )
+}
+
+// The size is recorded once a frame arrives.
+pub(crate) fn receive_span(direction: &'static str) -> Span {
+    tracing::debug_span!("efdstream.receive",
+        messaging.system = "efdstream",
+        messaging.operation = "receive",
+        messaging.destination.name = direction,
+        messaging.message.body.size = Empty,
+    )
+}
+
+pub(crate) fn record_size(size: usize) {
+    Span::current().record("messaging.message.body.size", size);
+}
+
+pub(crate) fn record_rtt(rtt: std::time::Duration) {
+    Span::current().record("efdstream.rtt_us", rtt.as_micros() as u64);
+}
+
+// An event for a failed call, in the current span. Ones a caller expects
+// in normal operation are kept at debug.
+pub(crate) fn finish<T>(result: &Result<T>) {
+    let Err(e) = result else { return };
+    match e {
+        EfdStreamError::ChildDied { .. } => tracing::error!(error = %e, "child died"),
+        EfdStreamError::Rejected => tracing::warn!(error = %e, "frame rejected as oversize"),
+        EfdStreamError::DecryptFailed => tracing::warn!(error = %e, "frame failed authentication"),
+        EfdStreamError::Timeout | EfdStreamError::Control { .. } | EfdStreamError::PeerClosed => tracing::debug!(error = %e),
+        _ => tracing::warn!(error = %e),
+    }
+}
+
+pub(crate) fn oversize_skipped(length: u64, size: usize) {
+    tracing::warn!(length, size, "oversize frame skipped");
+}
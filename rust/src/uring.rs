@@ -0,0 +1,244 @@
+// io_uring variant of the parent for thread-per-core runtimes (glommio and
+// similar). The doorbell and ACK eventfd reads and writes are submitted as
+// io_uring operations instead of blocking syscalls; the SHM copy stays
+// synchronous.
+//
+// Each `UringShmParent` owns a small ring. Its futures check the completion
+// queue whenever they are polled and yield back to the executor while the
+// operation is in flight, so they never block the thread and need no reactor
+// integration. The cost is that a waiting task keeps its core busy, which is
+// how thread-per-core designs usually run anyway.
+//
+// The 8-byte transfer buffer is owned by the parent, not the future, so
+// dropping a future mid-operation can't leave the kernel writing into freed
+// memory. The next call settles the stale operation first: a read is
+// cancelled, and whatever it took anyway is kept as state on the parent, as
+// `AsyncShmParent` does; a write is waited out, since an eventfd write
+// completes at once.
+
+use std::future::poll_fn;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::task::Poll;
+use std::time::Instant;
+
+use io_uring::{opcode, types, IoUring};
+
+use crate::efd::ShmParent;
+use crate::error::{EfdStreamError, Result};
+
+const OP: u64 = 1;
+const CANCEL: u64 = 2;
+
+// Which eventfd transfer an operation is, so a stale one can be finished
+// on behalf of the future that started it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    P2cDoorbell,
+    P2cAck,
+    C2pDoorbell,
+    C2pAck,
+}
+
+impl Op {
+    fn is_read(self) -> bool {
+        matches!(self, Op::P2cAck | Op::C2pDoorbell)
+    }
+}
+
+struct Doorbells {
+    ring: IoUring,
+    buf: Box<[u8; 8]>,
+    in_flight: Option<Op>,
+}
+
+impl Doorbells {
+    fn new() -> std::io::Result<Self> {
+        Ok(Self { ring: IoUring::new(4)?, buf: Box::new([0; 8]), in_flight: None })
+    }
+
+    async fn read(&mut self, fd: RawFd, op: Op) -> Result<u64> {
+        let entry = opcode::Read::new(types::Fd(fd), self.buf.as_mut_ptr(), 8).build().user_data(OP);
+        self.submit(entry, op)?;
+        self.complete().await?;
+        Ok(u64::from_ne_bytes(*self.buf))
+    }
+
+    async fn write(&mut self, fd: RawFd, value: u64, op: Op) -> Result<()> {
+        *self.buf = value.to_ne_bytes();
+        let entry = opcode::Write::new(types::Fd(fd), self.buf.as_ptr(), 8).build().user_data(OP);
+        self.submit(entry, op)?;
+        self.complete().await
+    }
+
+    // Finishes an operation whose future was dropped and reports how it
+    // ended, with the value a read took. `None` if nothing was in flight,
+    // or if a read was cancelled before it took anything.
+    async fn settle(&mut self) -> Option<(Op, Result<u64>)> {
+        let op = self.in_flight?;
+        if op.is_read() {
+            let cancel = opcode::AsyncCancel::new(OP).build().user_data(CANCEL);
+            // With the queue full the read is waited out instead.
+            if unsafe { self.ring.submission().push(&cancel) }.is_ok() {
+                let _ = self.ring.submit();
+            }
+        }
+        match self.complete().await {
+            Err(EfdStreamError::Io(e)) if e.raw_os_error() == Some(libc::ECANCELED) => None,
+            result => Some((op, result.map(|()| u64::from_ne_bytes(*self.buf)))),
+        }
+    }
+
+    fn submit(&mut self, entry: io_uring::squeue::Entry, op: Op) -> std::io::Result<()> {
+        unsafe { self.ring.submission().push(&entry) }
+            .map_err(|_| std::io::Error::other("io_uring submission queue full"))?;
+        self.ring.submit()?;
+        self.in_flight = Some(op);
+        Ok(())
+    }
+
+    // eventfd transfers all 8 bytes or fails, as in `eventfd_read`.
+    async fn complete(&mut self) -> Result<()> {
+        let result = poll_fn(|cx| match self.ring.completion().find(|cqe| cqe.user_data() == OP) {
+            Some(cqe) => Poll::Ready(cqe.result()),
+            None => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }).await;
+        self.in_flight = None;
+
+        match result {
+            8 => Ok(()),
+            0 => Err(EfdStreamError::PeerClosed),
+            n if n < 0 => Err(std::io::Error::from_raw_os_error(-n).into()),
+            n => Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                format!("eventfd transferred {} bytes, expected 8", n)).into()),
+        }
+    }
+}
+
+impl Drop for Doorbells {
+    // The kernel may still write into `buf`; cancel and wait before freeing it.
+    fn drop(&mut self) {
+        if self.in_flight.is_none() {
+            return;
+        }
+        let cancel = opcode::AsyncCancel::new(OP).build().user_data(CANCEL);
+        if unsafe { self.ring.submission().push(&cancel) }.is_err() {
+            return;
+        }
+        loop {
+            if self.ring.submit_and_wait(1).is_err() {
+                return;
+            }
+            if self.ring.completion().any(|cqe| cqe.user_data() == OP) {
+                return;
+            }
+        }
+    }
+}
+
+/// `ShmParent` whose `send_data` and `read_data` are futures driven by
+/// io_uring. Start the wrapped parent with `start` as usual.
+pub struct UringShmParent {
+    inner: ShmParent,
+    doorbells: Doorbells,
+    // A C2P doorbell a dropped `read_data` took off the eventfd; the next
+    // read delivers its message.
+    peeked: Option<u64>,
+    // The ACK a dropped `send_data` read but didn't check.
+    late_ack: Option<u64>,
+}
+
+impl UringShmParent {
+    pub fn new(parent: ShmParent) -> Result<Self> {
+        Ok(Self { inner: parent, doorbells: Doorbells::new()?, peeked: None, late_ack: None })
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        self.inner.start()
+    }
+
+    pub fn parent(&self) -> &ShmParent {
+        &self.inner
+    }
+
+    // Settles what a dropped future left in flight, keeping anything it
+    // consumed for the call it belongs to.
+    async fn recover(&mut self) -> Result<()> {
+        match self.doorbells.settle().await {
+            Some((Op::P2cDoorbell, written)) => {
+                written?;
+                self.inner.ack_pending = true;
+            }
+            Some((Op::P2cAck, acked)) => {
+                self.late_ack = Some(acked?);
+                self.inner.ack_pending = false;
+            }
+            Some((Op::C2pDoorbell, doorbell)) => self.peeked = Some(doorbell?),
+            Some((Op::C2pAck, written)) => {
+                written?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Cancellation-safe like `AsyncShmParent::send_data`: dropped before
+    /// the doorbell is rung, it sends nothing; dropped after, the message
+    /// has been sent and the next call waits for its ACK first, failing
+    /// with `Rejected` if the child NACKed it.
+    pub async fn send_data(&mut self, data: &[u8]) -> Result<()> {
+        let (send, ack) = match (&self.inner.file_p2c_send, &self.inner.file_p2c_ack) {
+            (Some(send), Some(ack)) => (send.as_raw_fd(), ack.as_raw_fd()),
+            _ => return Err(EfdStreamError::NotStarted),
+        };
+        self.recover().await?;
+        if let Some(acked) = self.late_ack.take() {
+            self.inner.check_ack(acked)?;
+        }
+        if self.inner.ack_pending {
+            let acked = self.doorbells.read(ack, Op::P2cAck).await?;
+            self.inner.ack_pending = false;
+            self.inner.check_ack(acked)?;
+        }
+        self.inner.check_send(data)?;
+        if self.inner.double_buffer {
+            return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Double buffering needs ShmParent's own sends").into());
+        }
+
+        let doorbell = self.inner.write_frame(data);
+        let sent_at = Instant::now();
+        self.doorbells.write(send, self.inner.endianness.to_wire(doorbell), Op::P2cDoorbell).await?;
+        self.inner.ack_pending = true;
+        let acked = self.doorbells.read(ack, Op::P2cAck).await?;
+        self.inner.ack_pending = false;
+        self.inner.check_ack(acked)?;
+        self.inner.metrics.record_send(data.len(), sent_at.elapsed());
+        Ok(())
+    }
+
+    /// Cancellation-safe: a doorbell a dropped future already took is kept,
+    /// and the next call returns its message.
+    pub async fn read_data(&mut self) -> Result<Vec<u8>> {
+        let (send, ack) = match (&self.inner.file_c2p_send, &self.inner.file_c2p_ack) {
+            (Some(send), Some(ack)) => (send.as_raw_fd(), ack.as_raw_fd()),
+            _ => return Err(EfdStreamError::NotStarted),
+        };
+        self.recover().await?;
+
+        let doorbell = match self.peeked.take() {
+            Some(doorbell) => doorbell,
+            None => self.doorbells.read(send, Op::C2pDoorbell).await?,
+        };
+        let result = match self.inner.take_frame(doorbell, |payload, _| Ok(payload.to_vec())) {
+            Ok(result) => result,
+            Err(e) => {
+                self.inner.nack_bad_frame()?;
+                return Err(e);
+            }
+        };
+        self.doorbells.write(ack, self.inner.endianness.to_wire(1), Op::C2pAck).await?;
+        result
+    }
+}
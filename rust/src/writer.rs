@@ -0,0 +1,122 @@
+// `std::io::Write` over the ring mode, for streaming byte data with
+// `io::copy` and friends.
+//
+// Writes are coalesced into frames of up to `frame_size` bytes, and each
+// frame takes one ring slot. Up to `window` frames can be unread at once, so
+// `write` only blocks when the child has fallen a whole window behind; the
+// ring's "space available" doorbell wakes it as frames are consumed. Memory
+// stays bounded at `window` slots plus one frame being filled.
+
+use std::io::{self, Write};
+
+use crate::error::Result;
+use crate::ring::RingShmParent;
+
+pub struct ShmWriterBuilder {
+    child_path: String,
+    child_args: Vec<String>,
+    window: usize,
+    frame_size: usize,
+}
+
+impl ShmWriterBuilder {
+    pub fn new(child_path: &str) -> Self {
+        Self { child_path: child_path.to_string(), child_args: Vec::new(), window: 8, frame_size: 64 * 1024 }
+    }
+
+    /// See `RingShmParent::child_args`.
+    pub fn child_args(mut self, args: &[&str]) -> Self {
+        self.child_args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Frames that may be sent but not yet read (the ring's slot count).
+    pub fn window(mut self, frames: usize) -> Self {
+        self.window = frames;
+        self
+    }
+
+    /// Largest frame, i.e. the size of each of the `window` SHM buffers.
+    pub fn frame_size(mut self, bytes: usize) -> Self {
+        self.frame_size = bytes;
+        self
+    }
+
+    pub fn build(self) -> ShmWriter {
+        ShmWriter {
+            parent: RingShmParent::new(&self.child_path, self.window, self.frame_size)
+                .child_args(&self.child_args.iter().map(String::as_str).collect::<Vec<_>>()),
+            pending: Vec::with_capacity(self.frame_size),
+            frame_size: self.frame_size,
+        }
+    }
+}
+
+/// A ring-mode parent driven through `Write`. The child reads the stream
+/// with `RingShmChild::read_data`, one frame at a time; frame boundaries
+/// carry no meaning. Like `BufWriter`, dropping the writer sends the partial
+/// frame and ignores any error, but doesn't wait for the child to read
+/// anything, so a dead child can't hang the drop. Call `flush` to wait and
+/// to see errors.
+pub struct ShmWriter {
+    parent: RingShmParent,
+    // Bytes written but not yet sent, less than one frame.
+    pending: Vec<u8>,
+    frame_size: usize,
+}
+
+impl ShmWriter {
+    pub fn builder(child_path: &str) -> ShmWriterBuilder {
+        ShmWriterBuilder::new(child_path)
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        if self.frame_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Frame size must not be zero").into());
+        }
+        self.parent.start()
+    }
+
+    /// The underlying parent, e.g. to `read_data` the child's replies.
+    pub fn parent(&mut self) -> &mut RingShmParent {
+        &mut self.parent
+    }
+
+    fn send_pending(&mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.parent.send_data(&self.pending)?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Write for ShmWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.pending.len() == self.frame_size {
+            self.send_pending()?;
+        }
+        // A whole frame's worth needs no staging copy.
+        if self.pending.is_empty() && buf.len() >= self.frame_size {
+            self.parent.send_data(&buf[..self.frame_size])?;
+            return Ok(self.frame_size);
+        }
+        let n = buf.len().min(self.frame_size - self.pending.len());
+        self.pending.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Sends the partial frame, then blocks until the child has read every
+    /// frame.
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_pending()?;
+        self.parent.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for ShmWriter {
+    fn drop(&mut self) {
+        let _ = self.send_pending();
+    }
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::ShmParent;
+
+use common::{echo_builder, payload, start_child_with};
+
+#[test]
+fn round_trips_with_a_spin_budget() {
+    let mut parent = echo_builder().shm_size(4096).ack_spin(10_000).build();
+    parent.start().unwrap();
+    for i in 0..200 {
+        parent.send_to_peer(&payload(i as u8, 100)).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), payload(i as u8, 100));
+    }
+}
+
+#[test]
+fn slow_ack_falls_back_to_blocking() {
+    let hold = Duration::from_millis(50);
+    // Far too few iterations to cover the hold.
+    let (mut parent, child) = start_child_with(ShmParent::builder("unused").shm_size(4096).ack_spin(10), move |mut child| {
+        child.listen_guarded(|frame| {
+            thread::sleep(hold);
+            frame.ack().unwrap();
+        }).unwrap();
+    });
+    for i in 0..3 {
+        let start = Instant::now();
+        parent.send_to_peer(&payload(i, 100)).unwrap();
+        assert!(start.elapsed() >= hold);
+    }
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use std::sync::Arc;
+use std::thread;
+
+use efdstream::ShmParent;
+
+use common::{payload, start_child_with, start_echo};
+
+// Each worker gets its own clone of the one copy.
+fn fan_out(data: Arc<[u8]>, expected: &[u8]) {
+    let workers: Vec<_> = (0..3).map(|_| {
+        let data = Arc::clone(&data);
+        thread::spawn(move || data.iter().map(|&b| b as u64).sum::<u64>())
+    }).collect();
+    let sum: u64 = expected.iter().map(|&b| b as u64).sum();
+    for worker in workers {
+        assert_eq!(worker.join().unwrap(), sum);
+    }
+    assert_eq!(&data[..], expected);
+}
+
+#[test]
+fn parent_shares_a_reply_across_threads() {
+    let mut parent = start_echo(64 * 1024);
+    let sent = payload(3, 10_000);
+    parent.send_to_peer(&sent).unwrap();
+    fan_out(parent.read_data_arc().unwrap(), &sent);
+}
+
+#[test]
+fn child_shares_a_request_across_threads() {
+    let sent = payload(4, 10_000);
+    let expected = sent.clone();
+    let (mut parent, child) = start_child_with(ShmParent::builder("unused").shm_size(64 * 1024), move |mut child| {
+        fan_out(child.read_data_arc().unwrap(), &expected);
+    });
+    parent.send_to_peer(&sent).unwrap();
+    child.join().unwrap();
+}
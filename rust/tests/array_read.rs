@@ -0,0 +1,37 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use common::{payload, start_child, start_echo};
+
+#[test]
+fn parent_reads_into_a_stack_array() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(&payload(1, 100)).unwrap();
+    let (buf, len) = parent.read_data_into_array::<128>().unwrap();
+    assert_eq!(&buf[..len], payload(1, 100));
+
+    parent.send_to_peer(&payload(2, 200)).unwrap();
+    let err = parent.read_data_into_array::<128>().unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::InvalidInput);
+    // Dropped, not left waiting.
+    parent.send_to_peer(&payload(3, 128)).unwrap();
+    let (buf, len) = parent.read_data_into_array::<128>().unwrap();
+    assert_eq!(&buf[..len], payload(3, 128));
+}
+
+#[test]
+fn child_answers_without_allocating() {
+    let (mut parent, child) = start_child(move |mut child| {
+        let (request, len) = child.read_data_into_array::<64>().unwrap();
+        child.send_to_peer(&request[..len]).unwrap();
+        let err = child.read_data_into_array::<64>().unwrap_err();
+        assert_eq!(std::io::Error::from(err).kind(), ErrorKind::InvalidInput);
+        assert!(child.read_data_into_array::<64>().is_err());
+    });
+    parent.send_to_peer(b"request").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"request");
+    parent.send_to_peer(&payload(1, 65)).unwrap();
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
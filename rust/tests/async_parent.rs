@@ -0,0 +1,62 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use std::future::{poll_fn, Future};
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+
+use efdstream::AsyncShmParent;
+
+use common::{echo_builder, payload, start_child};
+
+#[tokio::test(flavor = "current_thread")]
+async fn dropped_read_leaves_the_message_pending() {
+    let (parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        thread::sleep(Duration::from_millis(20));
+        child.send_to_peer(b"late").unwrap();
+    });
+    let mut parent = AsyncShmParent::new(parent);
+
+    let mut read = Box::pin(parent.read_data());
+    let first = poll_fn(|cx| Poll::Ready(read.as_mut().poll(cx))).await;
+    assert!(first.is_pending());
+    // The doorbell rings while the runtime is blocked, so the future is
+    // woken but never polled again before it is dropped.
+    thread::sleep(Duration::from_millis(100));
+    drop(read);
+
+    assert_eq!(parent.read_data().await.unwrap(), b"late");
+    child.join().unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn read_with_borrows_the_payload() {
+    let (parent, child) = start_child(move |mut child| {
+        for _ in 0..3 {
+            let request = child.recv_from_peer().unwrap();
+            child.send_to_peer(&request.iter().rev().copied().collect::<Vec<_>>()).unwrap();
+        }
+    });
+    let mut parent = AsyncShmParent::new(parent);
+
+    for request in [&b"abc"[..], b"hello", b"x"] {
+        parent.send_data(request).await.unwrap();
+        let (len, first) = parent.read_with(|reply| (reply.len(), reply[0])).await.unwrap();
+        assert_eq!((len, first), (request.len(), request[request.len() - 1]));
+    }
+    child.join().unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn echoes_through_a_spawned_child() {
+    let mut parent = AsyncShmParent::new(echo_builder().shm_size(64 * 1024).build());
+    parent.start().unwrap();
+    for i in 0..5 {
+        let sent = payload(i, 100 + 10_000 * i as usize);
+        parent.send_data(&sent).await.unwrap();
+        assert_eq!(parent.read_data().await.unwrap(), sent);
+    }
+}
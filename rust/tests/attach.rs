@@ -0,0 +1,66 @@
+mod common;
+
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+
+use efdstream::{ShmFdSet, ShmParent};
+
+use common::{payload, CHILD};
+
+// Starts the echo binary the way an outside process manager would: the fds
+// go to 3..8 in `pre_exec`, and the numbers are passed as arguments.
+fn spawn_echo(fds: ShmFdSet, handshake: bool) -> Child {
+    let mut cmd = Command::new(CHILD);
+    cmd.args(["-fd-p2c-send", "3", "-fd-p2c-ack", "4", "-fd-p2c-shm", "5",
+              "-fd-c2p-send", "6", "-fd-c2p-ack", "7", "-fd-c2p-shm", "8"]);
+    cmd.args(["-shm-size", &fds.p2c_size.to_string(), "-c2p-size", &fds.c2p_size.to_string(), "-mode", "echo"]);
+    if handshake {
+        cmd.arg("-handshake");
+    }
+    let sources = [&fds.p2c_send, &fds.p2c_ack, &fds.p2c_shm, &fds.c2p_send, &fds.c2p_ack, &fds.c2p_shm]
+        .map(|fd| fd.as_raw_fd());
+    unsafe {
+        cmd.pre_exec(move || {
+            // Staged above the targets first, so no source is overwritten.
+            let staged = sources.map(|fd| libc::fcntl(fd, libc::F_DUPFD, 100));
+            for (i, fd) in staged.into_iter().enumerate() {
+                if fd == -1 || libc::dup2(fd, 3 + i as i32) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let child = cmd.spawn().unwrap();
+    drop(fds);
+    child
+}
+
+#[test]
+fn drives_a_child_it_did_not_spawn() {
+    for handshake in [false, true] {
+        let mut parent = ShmParent::builder(CHILD).shm_size(4096).handshake(handshake).build();
+        let fds = parent.create_fds().unwrap();
+        let mut child = spawn_echo(fds, handshake);
+        parent.attach().unwrap();
+        assert_eq!(parent.child_id(), None);
+
+        for i in 0..3 {
+            let sent = payload(i, 1000 * (i as usize + 1));
+            parent.send_to_peer(&sent).unwrap();
+            assert_eq!(parent.recv_from_peer().unwrap(), sent);
+        }
+        // Dropping the parent leaves the child alone; the half-close ends it.
+        parent.shutdown_send().unwrap();
+        drop(parent);
+        assert!(child.wait().unwrap().success());
+    }
+}
+
+#[test]
+fn extra_channels_are_refused() {
+    let mut parent = ShmParent::builder(CHILD).control_channel(true).build();
+    let err = std::io::Error::from(parent.create_fds().unwrap_err());
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
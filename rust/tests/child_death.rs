@@ -0,0 +1,56 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::os::unix::process::ExitStatusExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::EfdStreamError;
+
+use common::start_echo;
+
+// Kills the child after `delay`, from another thread, while the caller blocks.
+fn kill_later(pid: u32, delay: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        thread::sleep(delay);
+        assert_eq!(unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) }, 0);
+    })
+}
+
+#[test]
+fn blocked_read_wakes_when_child_is_killed() {
+    let mut parent = start_echo(4096);
+    // The echo child only ever answers, so nothing will ring this doorbell.
+    let killer = kill_later(parent.child_id().unwrap(), Duration::from_millis(50));
+    let start = Instant::now();
+    let err = parent.recv_from_peer().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(5), "woke after {:?}", start.elapsed());
+    assert!(matches!(err, EfdStreamError::ChildDied { signal: Some(9), .. }), "{:?}", err);
+    killer.join().unwrap();
+}
+
+#[test]
+fn blocked_send_wakes_when_child_is_killed() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(b"unread reply").unwrap();
+    // The child is stuck sending its reply, so this send's ACK never comes.
+    let killer = kill_later(parent.child_id().unwrap(), Duration::from_millis(50));
+    let start = Instant::now();
+    let err = parent.send_to_peer(b"never acked").unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(5), "woke after {:?}", start.elapsed());
+    assert!(matches!(err, EfdStreamError::ChildDied { signal: Some(9), .. }), "{:?}", err);
+    killer.join().unwrap();
+}
+
+#[test]
+fn status_is_kept_for_diagnosis() {
+    let mut parent = start_echo(4096);
+    assert!(parent.child_status().unwrap().is_none());
+    parent.send_to_peer(b"unread reply").unwrap();
+    let killer = kill_later(parent.child_id().unwrap(), Duration::from_millis(50));
+    let err = parent.send_to_peer(b"never acked").unwrap_err();
+    killer.join().unwrap();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::BrokenPipe);
+    assert_eq!(parent.child_status().unwrap().and_then(|status| status.signal()), Some(9));
+}
+
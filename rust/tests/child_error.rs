@@ -0,0 +1,43 @@
+mod common;
+
+
+use efdstream::efd::ERROR_MESSAGE_LEN;
+use efdstream::EfdStreamError;
+
+use common::start_child;
+
+#[test]
+fn handler_error_reaches_the_parent() {
+    let (mut parent, child) = start_child(move |mut child| {
+        child.listen_with_responder(|request, responder| {
+            responder.send(b"partial").unwrap();
+            match request {
+                b"bad" => responder.set_error(22, "no such table").unwrap(),
+                b"long" => responder.set_error(7, &"é".repeat(ERROR_MESSAGE_LEN)).unwrap(),
+                _ => responder.send(request).unwrap(),
+            }
+        }).unwrap();
+    });
+    assert_eq!(parent.last_child_error(), None);
+
+    match parent.send_to_peer(b"bad") {
+        Err(EfdStreamError::HandlerFailed { code: 22, message }) => assert_eq!(message, "no such table"),
+        other => panic!("expected HandlerFailed, got {:?}", other),
+    }
+    assert_eq!(parent.last_child_error(), Some((22, "no such table".to_string())));
+
+    // The failed request's replies were dropped.
+    parent.send_to_peer(b"good").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"partial");
+    assert_eq!(parent.recv_from_peer().unwrap(), b"good");
+    assert_eq!(parent.last_child_error().unwrap().0, 22);
+
+    // Cut to whole two-byte characters.
+    assert!(parent.send_to_peer(b"long").is_err());
+    let (code, message) = parent.last_child_error().unwrap();
+    assert_eq!(code, 7);
+    assert_eq!(message, "é".repeat(ERROR_MESSAGE_LEN / 2));
+
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::sync::mpsc;
+
+use efdstream::{EfdStreamError, ShmChild, ShmParent};
+
+use common::start_child_with;
+
+fn is_open(fd: BorrowedFd<'_>) -> bool {
+    unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) != -1 }
+}
+
+fn assert_open(child: &ShmChild) {
+    for fd in [child.p2c_send_fd(), child.p2c_ack_fd(), child.c2p_send_fd(), child.c2p_ack_fd()] {
+        let fd = fd.unwrap();
+        assert!(is_open(fd), "fd {} closed", fd.as_raw_fd());
+    }
+}
+
+#[test]
+fn sends_then_listens_on_the_same_descriptors() {
+    let (tx, rx) = mpsc::channel();
+    let builder = ShmParent::builder("unused").shm_size(4096).control_channel(true);
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        for i in 0..5u8 {
+            child.send_to_peer(&[i; 100]).unwrap();
+        }
+        assert_open(&child);
+        // A listen that ends in an error leaves the descriptors for the next.
+        let err = child.listen(|data| tx.send(data.to_vec()).unwrap()).unwrap_err();
+        assert!(matches!(err, EfdStreamError::Control { bits: 1 }), "{:?}", err);
+        assert_open(&child);
+        tx.send(b"interrupted".to_vec()).unwrap();
+        child.listen(|data| tx.send(data.to_vec()).unwrap()).unwrap();
+        assert_open(&child);
+    });
+    for i in 0..5u8 {
+        assert_eq!(parent.recv_from_peer().unwrap(), [i; 100]);
+    }
+    parent.send_to_peer(b"first listen").unwrap();
+    assert_eq!(rx.recv().unwrap(), b"first listen");
+    parent.send_control(1).unwrap();
+    assert_eq!(rx.recv().unwrap(), b"interrupted");
+    parent.send_to_peer(b"second listen").unwrap();
+    assert_eq!(rx.recv().unwrap(), b"second listen");
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
@@ -0,0 +1,45 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, ShmParent};
+
+// A child that ignores the arguments `start` passes and never answers the
+// handshake. It leaves its pid in `pid_file` so the test can look for it.
+fn silent_child(pid_file: &Path) -> PathBuf {
+    let script = std::env::temp_dir().join(format!("efdstream-silent-{}.sh", std::process::id()));
+    std::fs::write(&script, format!("#!/bin/sh\necho $$ > {}\nexec sleep 30\n", pid_file.display())).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    script
+}
+
+#[test]
+fn child_that_exits_at_once_fails_setup() {
+    let mut parent = ShmParent::builder("/bin/true").shm_size(4096).handshake(true).build();
+    match parent.start() {
+        Err(EfdStreamError::ChildSetupFailed { status: Some(status) }) => assert!(status.success()),
+        other => panic!("expected ChildSetupFailed with a status, got {:?}", other),
+    }
+}
+
+#[test]
+fn silent_child_times_out_and_is_reaped() {
+    let pid_file = std::env::temp_dir().join(format!("efdstream-silent-{}.pid", std::process::id()));
+    let script = silent_child(&pid_file);
+    let mut parent = ShmParent::builder(script.to_str().unwrap())
+        .shm_size(4096)
+        .handshake(true)
+        .startup_timeout(Some(Duration::from_millis(200)))
+        .build();
+    let started = Instant::now();
+    match parent.start() {
+        Err(EfdStreamError::ChildSetupFailed { status: None }) => {}
+        other => panic!("expected ChildSetupFailed without a status, got {:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(5), "gave up after {:?}", started.elapsed());
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    // Killed and waited for: no process left, not even a zombie.
+    assert!(!PathBuf::from(format!("/proc/{}", pid.trim())).exists(), "child {} not reaped", pid.trim());
+    let _ = std::fs::remove_file(&pid_file);
+    let _ = std::fs::remove_file(&script);
+}
@@ -0,0 +1,51 @@
+// Shared setup for the integration tests: a parent whose child is this
+// crate's own binary in `-mode echo`, which sends every message back and
+// half-closes once the parent does, or one whose child runs on a thread of
+// the test. Each test crate uses only some of it.
+#![allow(dead_code)]
+
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+use efdstream::{ShmChild, ShmParent, ShmParentBuilder};
+
+pub const CHILD: &str = env!("CARGO_BIN_EXE_efdstream");
+
+/// Builder for an echo child; `-mode echo` comes last, so it overrides the
+/// `-mode child` that `start` passes.
+pub fn echo_builder() -> ShmParentBuilder {
+    ShmParent::builder(CHILD).child_args(&["-mode", "echo"])
+}
+
+pub fn start_echo(shm_size: usize) -> ShmParent {
+    let mut parent = echo_builder().shm_size(shm_size).build();
+    parent.start().expect("start echo child");
+    parent
+}
+
+/// Starts `builder`'s parent over a socket pair, with `child` running on a
+/// thread as the `ShmChild::from_socket` end. The builder's child path is
+/// never run.
+pub fn start_child_with<T: Send + 'static>(
+    builder: ShmParentBuilder,
+    child: impl FnOnce(ShmChild) -> T + Send + 'static,
+) -> (ShmParent, thread::JoinHandle<T>) {
+    let (parent_end, child_end) = UnixStream::pair().unwrap();
+    let child = thread::spawn(move || child(ShmChild::from_socket(&child_end).unwrap()));
+    let mut parent = builder.build();
+    parent.start_with_socket(&parent_end).unwrap();
+    (parent, child)
+}
+
+/// `start_child_with` for a parent with 4096-byte regions and nothing else set.
+pub fn start_child<T: Send + 'static>(
+    child: impl FnOnce(ShmChild) -> T + Send + 'static,
+) -> (ShmParent, thread::JoinHandle<T>) {
+    start_child_with(ShmParent::builder("unused").shm_size(4096), child)
+}
+
+/// `len` bytes that differ between messages, so a reply carrying the wrong
+/// message shows up even when the lengths match.
+pub fn payload(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed)).collect()
+}
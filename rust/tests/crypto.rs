@@ -0,0 +1,125 @@
+#![cfg(feature = "crypto")]
+
+mod common;
+
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{Direction, EfdStreamError, ShmParent};
+
+use common::{payload, start_child_with};
+
+const KEY: [u8; 32] = [7; 32];
+
+// Echo child on a thread, given its fds over a socket, since the spawned
+// echo binary has no key.
+fn start_encrypted(timestamps: bool) -> (ShmParent, thread::JoinHandle<()>) {
+    let builder = ShmParent::builder("unused").shm_size(64 * 1024).encrypt(KEY).timestamps(timestamps);
+    start_child_with(builder, move |child| {
+        let mut child = child.encrypt(KEY);
+        child.listen_request_response(|data| data.to_vec()).unwrap();
+    })
+}
+
+#[test]
+fn echoes_sealed_frames() {
+    for timestamps in [false, true] {
+        let (mut parent, child) = start_encrypted(timestamps);
+        assert!(parent.negotiated().encrypted);
+        for (i, len) in [0, 1, 4096, 60_000].into_iter().enumerate() {
+            let sent = payload(i as u8, len);
+            parent.send_to_peer(&sent).unwrap();
+            assert_eq!(parent.recv_from_peer().unwrap(), sent, "{} bytes, timestamps {}", len, timestamps);
+        }
+        parent.shutdown_send().unwrap();
+        child.join().unwrap();
+    }
+}
+
+#[test]
+fn seals_a_batch_in_place() {
+    for timestamps in [false, true] {
+        let (mut parent, child) = start_encrypted(timestamps);
+        let records = [payload(1, 1000), payload(2, 3000)];
+        assert_eq!(parent.send_from_iter(&records).unwrap(), (4000, 2));
+        assert_eq!(parent.recv_from_peer().unwrap(), records.concat(), "timestamps {}", timestamps);
+        parent.shutdown_send().unwrap();
+        child.join().unwrap();
+    }
+}
+
+#[test]
+fn child_seals_slices_in_place() {
+    for timestamps in [false, true] {
+        let builder = ShmParent::builder("unused").shm_size(64 * 1024).encrypt(KEY).timestamps(timestamps);
+        let (mut parent, child) = start_child_with(builder, move |child| {
+            let mut child = child.encrypt(KEY);
+            child.init().unwrap();
+            child.send_data_vectored(&[&payload(1, 1000), &[], &payload(2, 3000)]).unwrap();
+        });
+        assert_eq!(parent.recv_from_peer().unwrap(), [payload(1, 1000), payload(2, 3000)].concat(), "timestamps {}", timestamps);
+        child.join().unwrap();
+    }
+}
+
+#[test]
+fn double_buffering_is_refused() {
+    let mut parent = ShmParent::builder("unused").shm_size(4096).encrypt(KEY).double_buffer(true).build();
+    let (parent_end, _child_end) = UnixStream::pair().unwrap();
+    assert!(matches!(parent.start_with_socket(&parent_end),
+        Err(EfdStreamError::Io(e)) if e.kind() == std::io::ErrorKind::Unsupported));
+}
+
+// Flips the first byte of the memfd behind a region, so the frame in it no
+// longer authenticates.
+fn tamper(memfd: &std::os::fd::OwnedFd) {
+    use std::os::unix::fs::FileExt;
+    let region = std::fs::File::from(memfd.try_clone().unwrap());
+    let mut byte = [0u8];
+    region.read_exact_at(&mut byte, 0).unwrap();
+    region.write_all_at(&[byte[0] ^ 0xff], 0).unwrap();
+}
+
+#[test]
+fn child_nacks_a_frame_that_fails_to_open() {
+    let (fd_tx, fd_rx) = mpsc::channel();
+    let (go_tx, go_rx) = mpsc::channel();
+    let (mut parent, child) = start_child_with(ShmParent::builder("unused").shm_size(4096).encrypt(KEY), move |child| {
+        let mut child = child.encrypt(KEY);
+        child.init().unwrap();
+        fd_tx.send(child.shm_fd(Direction::ParentToChild).unwrap().try_clone_to_owned().unwrap()).unwrap();
+        go_rx.recv().unwrap();
+        child.recv_from_peer()
+    });
+    let memfd = fd_rx.recv().unwrap();
+
+    // The child isn't reading yet, so the send is left waiting for its ACK.
+    let sent = parent.send_data_by(b"sealed", Instant::now() + Duration::from_millis(20));
+    assert!(matches!(sent, Err(EfdStreamError::Timeout)));
+    tamper(&memfd);
+    go_tx.send(()).unwrap();
+    assert!(matches!(child.join().unwrap(), Err(EfdStreamError::DecryptFailed)));
+    assert!(matches!(parent.flush(), Err(EfdStreamError::Rejected)));
+}
+
+#[test]
+fn parent_nacks_a_frame_that_fails_to_open() {
+    let (fd_tx, fd_rx) = mpsc::channel();
+    let (mut parent, child) = start_child_with(ShmParent::builder("unused").shm_size(4096).encrypt(KEY), move |child| {
+        let mut child = child.encrypt(KEY);
+        child.init().unwrap();
+        fd_tx.send(child.shm_fd(Direction::ChildToParent).unwrap().try_clone_to_owned().unwrap()).unwrap();
+        child.send_to_peer(b"sealed")
+    });
+    let memfd = fd_rx.recv().unwrap();
+
+    // Tampered once the child has rung, before the parent reads it.
+    let mut doorbell = libc::pollfd { fd: parent.c2p_send_fd().unwrap().as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    assert_eq!(unsafe { libc::poll(&mut doorbell, 1, 5000) }, 1);
+    tamper(&memfd);
+    assert!(matches!(parent.recv_from_peer(), Err(EfdStreamError::DecryptFailed)));
+    assert!(matches!(child.join().unwrap(), Err(EfdStreamError::Rejected)));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, ShmParent};
+
+use common::{echo_builder, payload};
+
+// How long the child holds each message before it ACKs.
+const SLOW: Duration = Duration::from_millis(100);
+
+#[test]
+fn interleaves_two_outstanding_sends() {
+    let (tx, rx) = mpsc::channel();
+    let mut parent = ShmParent::builder("unused").shm_size(4096).double_buffer(true).build();
+    // The payload is copied out only after the sleep, so a send that wrote
+    // over the buffer being read would show up as the wrong message.
+    let child = parent.spawn_thread_child(move |data| {
+        thread::sleep(SLOW);
+        tx.send(data.to_vec()).unwrap();
+    }).unwrap();
+
+    let start = Instant::now();
+    parent.send_to_peer(&payload(1, 3000)).unwrap();
+    assert!(start.elapsed() < SLOW, "first send took {:?}", start.elapsed());
+    // Filled while the child holds the first; rung once that is ACKed.
+    parent.send_to_peer(&payload(2, 3000)).unwrap();
+    assert!(start.elapsed() >= SLOW, "second send took {:?}", start.elapsed());
+    // Back in the first buffer, while the child holds the second.
+    parent.send_to_peer(&payload(3, 3000)).unwrap();
+    assert_eq!(parent.metrics().messages_sent, 2);
+
+    parent.flush().unwrap();
+    assert_eq!(parent.metrics().messages_sent, 3);
+    for seed in 1..=3 {
+        assert_eq!(rx.recv().unwrap(), payload(seed, 3000));
+    }
+    parent.shutdown_send().unwrap();
+    child.join().unwrap().unwrap();
+}
+
+#[test]
+fn echoes_from_both_buffers() {
+    let mut parent = echo_builder().shm_size(4096).double_buffer(true).build();
+    parent.start().unwrap();
+    assert!(parent.negotiated().double_buffer);
+    for seed in 0..6 {
+        let message = payload(seed, 100 + seed as usize * 500);
+        parent.send_to_peer(&message).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), message);
+    }
+    parent.send_data_vectored(&[b"head", b"body"]).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"headbody");
+    parent.flush().unwrap();
+    assert_eq!(parent.metrics().messages_sent, 7);
+}
+
+#[test]
+fn refuses_what_needs_each_ack() {
+    let mut parent = echo_builder().shm_size(4096).handshake(true).double_buffer(true).build();
+    parent.start().unwrap();
+    let unsupported = |result: Result<_, EfdStreamError>| {
+        matches!(result, Err(EfdStreamError::Io(e)) if e.kind() == ErrorKind::Unsupported)
+    };
+    assert!(unsupported(parent.send_data_with_status(b"status").map(|_| ())));
+    assert!(unsupported(parent.resize_shm(64 * 1024)));
+    parent.send_to_peer(b"still works").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"still works");
+}
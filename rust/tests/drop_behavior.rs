@@ -0,0 +1,72 @@
+mod common;
+
+use efdstream::DropBehavior;
+
+use common::echo_builder;
+
+fn alive(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+fn start(behavior: DropBehavior) -> (efdstream::ShmParent, u32) {
+    start_with(behavior, false)
+}
+
+fn start_with(behavior: DropBehavior, zero_on_drop: bool) -> (efdstream::ShmParent, u32) {
+    let mut parent = echo_builder().shm_size(4096).drop_behavior(behavior).zero_on_drop(zero_on_drop).build();
+    parent.start().unwrap();
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+    let pid = parent.child_id().unwrap();
+    (parent, pid)
+}
+
+#[test]
+fn wait_lets_the_child_exit_and_reaps_it() {
+    let (parent, pid) = start(DropBehavior::Wait);
+    drop(parent);
+    // Reaped, so not even a zombie is left to signal.
+    assert!(!alive(pid));
+}
+
+#[test]
+fn detach_leaves_the_child_running() {
+    let (parent, pid) = start(DropBehavior::Detach);
+    drop(parent);
+    assert!(alive(pid));
+    assert!(libc::WIFSIGNALED(kill_and_reap(pid)));
+}
+
+// Returns the wait status.
+fn kill_and_reap(pid: u32) -> i32 {
+    let mut status = 0;
+    unsafe {
+        assert_eq!(libc::kill(pid as libc::pid_t, libc::SIGKILL), 0);
+        assert_eq!(libc::waitpid(pid as libc::pid_t, &mut status, 0), pid as libc::pid_t);
+    }
+    status
+}
+
+#[test]
+fn detach_keeps_the_childs_regions_under_zero_on_drop() {
+    let (parent, pid) = start_with(DropBehavior::Detach, true);
+    drop(parent);
+    // The child still maps the P2C memfd, with the last message in it.
+    let fds = std::fs::read_dir(format!("/proc/{}/fd", pid)).unwrap();
+    let p2c = fds.map(|fd| fd.unwrap().path())
+        .find(|fd| std::fs::read_link(fd).is_ok_and(|target| target.to_string_lossy().contains("efdstream_shm_p2c")))
+        .expect("child holds the P2C memfd");
+    let contents = std::fs::read(p2c).unwrap();
+    // Killed first, so a failure doesn't leave it holding the test's output.
+    kill_and_reap(pid);
+    assert_eq!(&contents[..4], b"ping");
+}
+
+#[test]
+fn kill_is_the_default() {
+    let (parent, pid) = start(DropBehavior::default());
+    drop(parent);
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid as libc::pid_t, &mut status, 0) }, pid as libc::pid_t);
+    assert!(libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGKILL);
+}
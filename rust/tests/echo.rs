@@ -0,0 +1,86 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::frame::monotonic_ns;
+use efdstream::EfdStreamError;
+
+use common::{echo_builder, payload, start_echo};
+
+const SHM_SIZE: usize = 64 * 1024;
+
+fn kind(e: EfdStreamError) -> ErrorKind {
+    std::io::Error::from(e).kind()
+}
+
+#[test]
+fn echoes_varying_sizes() {
+    let mut parent = start_echo(SHM_SIZE);
+    let sizes = [1, 7, 64, 1000, 4096, 4097, 30_000, SHM_SIZE / 2, SHM_SIZE - 1];
+    for (i, &len) in sizes.iter().cycle().take(100).enumerate() {
+        let sent = payload(i as u8, len);
+        parent.send_to_peer(&sent).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), sent, "message {} of {} bytes", i, len);
+    }
+}
+
+#[test]
+fn rejects_empty_message() {
+    // A zero doorbell wouldn't wake the child, so it is refused up front.
+    let mut parent = start_echo(SHM_SIZE);
+    assert!(matches!(parent.send_to_peer(&[]), Err(EfdStreamError::ReservedLength { len: 0 })));
+    parent.send_to_peer(b"after").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"after");
+}
+
+#[test]
+fn echoes_empty_message_with_timestamps() {
+    let mut parent = echo_builder().shm_size(SHM_SIZE).timestamps(true).build();
+    parent.start().unwrap();
+    parent.send_to_peer(&[]).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn echoes_message_filling_the_region() {
+    let mut parent = start_echo(SHM_SIZE);
+    let len = parent.usable_size();
+    assert!(len >= SHM_SIZE);
+    let sent = payload(1, len);
+    parent.send_to_peer(&sent).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), sent);
+}
+
+#[test]
+fn rejects_oversize_message() {
+    let mut parent = start_echo(SHM_SIZE);
+    let err = parent.send_to_peer(&payload(2, parent.usable_size() + 1)).unwrap_err();
+    assert_eq!(kind(err), ErrorKind::InvalidInput);
+
+    // Nothing was sent, so the session carries on.
+    parent.send_to_peer(b"still here").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"still here");
+}
+
+#[test]
+fn shuts_down_cleanly() {
+    let mut parent = start_echo(SHM_SIZE);
+    parent.send_to_peer(b"last").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"last");
+
+    parent.shutdown_send().unwrap();
+    assert_eq!(kind(parent.send_to_peer(b"late").unwrap_err()), ErrorKind::BrokenPipe);
+    // The child's listen returns and it half-closes its side in turn.
+    assert_eq!(kind(parent.recv_from_peer().unwrap_err()), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn stamps_replies_with_the_shared_monotonic_clock() {
+    let mut parent = echo_builder().shm_size(SHM_SIZE).timestamps(true).build();
+    parent.start().unwrap();
+    let before = monotonic_ns();
+    parent.send_to_peer(b"ping").unwrap();
+    let (data, sent) = parent.read_timed().unwrap();
+    assert_eq!(data, b"ping");
+    assert!((before..=monotonic_ns()).contains(&sent));
+}
@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+
+use efdstream::Endianness;
+
+const VALUE: u64 = 0x0102_0304_0506_0708;
+const LITTLE: [u8; 8] = [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01];
+const BIG: [u8; 8] = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
+
+fn native() -> [u8; 8] {
+    if cfg!(target_endian = "big") { BIG } else { LITTLE }
+}
+
+// The 8 bytes a peer reading the eventfd sees after `to_wire(value)` is rung.
+fn rung(order: Endianness, value: u64) -> [u8; 8] {
+    let mut eventfd = unsafe { File::from_raw_fd(libc::eventfd(0, libc::EFD_CLOEXEC)) };
+    eventfd.write_all(&order.to_wire(value).to_ne_bytes()).unwrap();
+    let mut bytes = [0u8; 8];
+    eventfd.read_exact(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn encode_lays_out_the_bytes_of_each_order() {
+    assert_eq!(Endianness::Little.encode(VALUE), LITTLE);
+    assert_eq!(Endianness::Big.encode(VALUE), BIG);
+    assert_eq!(Endianness::Native.encode(VALUE), native());
+    for order in [Endianness::Native, Endianness::Little, Endianness::Big] {
+        assert_eq!(order.decode(order.encode(VALUE)), VALUE);
+    }
+}
+
+#[test]
+fn to_wire_puts_the_encoded_bytes_on_the_eventfd() {
+    assert_eq!(rung(Endianness::Little, VALUE), LITTLE);
+    assert_eq!(rung(Endianness::Big, VALUE), BIG);
+    assert_eq!(rung(Endianness::Native, VALUE), native());
+    // An ACK of 1 in big-endian is a 1 in the last byte.
+    assert_eq!(rung(Endianness::Big, 1), [0, 0, 0, 0, 0, 0, 0, 1]);
+}
+
+#[test]
+fn from_wire_undoes_to_wire() {
+    for order in [Endianness::Native, Endianness::Little, Endianness::Big] {
+        for value in [1, 3, VALUE, u64::MAX - 1] {
+            assert_eq!(order.from_wire(order.to_wire(value)), value);
+        }
+    }
+    assert_eq!(Endianness::Native.to_wire(VALUE), VALUE);
+    assert_eq!(Endianness::Big.from_wire(u64::from_ne_bytes(BIG)), VALUE);
+    assert_eq!(Endianness::Little.from_wire(u64::from_ne_bytes(LITTLE)), VALUE);
+}
@@ -0,0 +1,11 @@
+use efdstream::{EfdStreamError, ShmChild};
+
+#[test]
+fn syscall_errors_keep_their_errno() {
+    // Nothing is open at these numbers, so the first syscall on them fails.
+    let mut child = ShmChild::new(900, 901, 902, 903, 904, 905, 4096);
+    match child.init() {
+        Err(EfdStreamError::Io(e)) => assert_eq!(e.raw_os_error(), Some(libc::EBADF), "{}", e),
+        other => panic!("expected EBADF, got {:?}", other.err()),
+    }
+}
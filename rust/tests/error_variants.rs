@@ -0,0 +1,44 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::frame::decode_frame;
+use efdstream::{EfdStreamError, ShmParent};
+
+use common::{payload, start_echo};
+
+#[test]
+fn not_started_before_start() {
+    let mut parent = ShmParent::builder("unused").shm_size(4096).build();
+    assert!(matches!(parent.send_to_peer(b"early"), Err(EfdStreamError::NotStarted)));
+    let err = std::io::Error::from(parent.send_to_peer(b"early").unwrap_err());
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+}
+
+#[test]
+fn data_too_large_names_both_sizes() {
+    let mut parent = start_echo(4096);
+    let cap = parent.usable_size();
+    match parent.send_to_peer(&payload(1, cap + 1)) {
+        Err(EfdStreamError::DataTooLarge { len, cap: reported }) => assert_eq!((len, reported), (cap + 1, cap)),
+        other => panic!("expected DataTooLarge, got {:?}", other),
+    }
+}
+
+#[test]
+fn peer_closed_after_the_child_half_closes() {
+    let mut parent = start_echo(4096);
+    parent.shutdown_send().unwrap();
+    assert!(matches!(parent.recv_from_peer(), Err(EfdStreamError::PeerClosed)));
+    // Every time, not just the first.
+    assert!(matches!(parent.recv_from_peer(), Err(EfdStreamError::PeerClosed)));
+}
+
+#[test]
+fn length_exceeds_shm_from_the_doorbell() {
+    let shm = [0u8; 64];
+    match decode_frame(&65u64.to_ne_bytes(), &shm, shm.len()) {
+        Err(EfdStreamError::LengthExceedsShm { len, cap }) => assert_eq!((len, cap), (65, 64)),
+        other => panic!("expected LengthExceedsShm, got {:?}", other),
+    }
+}
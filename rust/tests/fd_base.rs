@@ -0,0 +1,70 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::{EfdStreamError, FdLayout, ShmParent, SpawnMode};
+
+use common::{payload, CHILD};
+
+// The targets of the memfds this process holds, e.g. `/memfd:name (deleted)`.
+fn memfd_names() -> Vec<String> {
+    std::fs::read_dir("/proc/self/fd").unwrap().flatten()
+        .filter_map(|entry| std::fs::read_link(entry.path()).ok())
+        .map(|target| target.to_string_lossy().into_owned())
+        .filter(|target| target.starts_with("/memfd:"))
+        .collect()
+}
+
+#[test]
+fn from_base_counts_up() {
+    assert_eq!(FdLayout::from_base(3), FdLayout::default());
+    assert_eq!(FdLayout::from_base(20), FdLayout {
+        p2c_send: 20, p2c_ack: 21, p2c_shm: 22, c2p_send: 23, c2p_ack: 24, c2p_shm: 25,
+    });
+}
+
+#[test]
+fn child_reads_the_moved_fds_from_the_environment() {
+    for mode in [SpawnMode::ForkExec, SpawnMode::PosixSpawn] {
+        let mut parent = ShmParent::builder(CHILD)
+            .child_args(&["-mode", "env-echo"])
+            .shm_size(4096)
+            .handshake(true)
+            .fd_base(40)
+            .env_prefix("EFDSTREAM")
+            .spawn_mode(mode)
+            .build();
+        parent.start().unwrap();
+        for i in 0..3 {
+            let sent = payload(i, 1000 * (i as usize + 1));
+            parent.send_to_peer(&sent).unwrap();
+            assert_eq!(parent.recv_from_peer().unwrap(), sent);
+        }
+    }
+}
+
+#[test]
+fn memfds_carry_the_prefix() {
+    let mut parent = ShmParent::builder(CHILD)
+        .child_args(&["-mode", "echo"])
+        .shm_size(4096)
+        .memfd_prefix("fd_base_test")
+        .build();
+    parent.start().unwrap();
+    let names = memfd_names();
+    for suffix in ["p2c", "c2p"] {
+        let name = format!("/memfd:fd_base_test_{} ", suffix);
+        assert!(names.iter().any(|n| n.starts_with(&name)), "{:?}", names);
+    }
+    parent.send_to_peer(b"named").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"named");
+}
+
+#[test]
+fn nul_in_the_prefix_is_rejected() {
+    let mut parent = ShmParent::builder(CHILD).memfd_prefix("bad\0prefix").build();
+    match parent.start() {
+        Err(EfdStreamError::Io(e)) => assert_eq!(e.kind(), ErrorKind::InvalidInput),
+        other => panic!("expected InvalidInput, got {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+
+use efdstream::{EfdStreamError, FdLayout, SpawnMode};
+
+use common::{echo_builder, payload};
+
+// The numbers the next six descriptors this process opens will get.
+fn next_fds() -> [i32; 6] {
+    let files: Vec<File> = (0..6).map(|_| File::open("/dev/null").unwrap()).collect();
+    std::array::from_fn(|i| files[i].as_raw_fd())
+}
+
+#[test]
+fn sources_that_are_targets_survive_the_remap() {
+    for mode in [SpawnMode::ForkExec, SpawnMode::PosixSpawn] {
+        // Each channel is created at the number another one is moved to.
+        let fds = next_fds();
+        let layout = FdLayout {
+            p2c_send: fds[5], p2c_ack: fds[4], p2c_shm: fds[3],
+            c2p_send: fds[2], c2p_ack: fds[1], c2p_shm: fds[0],
+        };
+        let mut parent = echo_builder().shm_size(4096).fd_layout(layout).spawn_mode(mode).build();
+        parent.start().unwrap();
+        let created = [parent.p2c_send_fd(), parent.p2c_ack_fd(), parent.c2p_send_fd(), parent.c2p_ack_fd()]
+            .map(|fd| fd.unwrap().as_raw_fd());
+        assert_eq!(created, [fds[0], fds[1], fds[3], fds[4]], "the collision wasn't set up");
+
+        for i in 0..3 {
+            let sent = payload(i, 1000 * (i as usize + 1));
+            parent.send_to_peer(&sent).unwrap();
+            assert_eq!(parent.recv_from_peer().unwrap(), sent);
+        }
+    }
+}
+
+fn duplicate_fd(layout: FdLayout, expected: i32) {
+    match layout.validate() {
+        Err(EfdStreamError::DuplicateFd { fd }) => assert_eq!(fd, expected),
+        other => panic!("expected DuplicateFd for {}, got {:?}", expected, other),
+    }
+}
+
+#[test]
+fn validate_rejects_two_equal_targets() {
+    FdLayout::default().validate().unwrap();
+    duplicate_fd(FdLayout { c2p_ack: 4, ..FdLayout::default() }, 4);
+    duplicate_fd(FdLayout { c2p_shm: 20, ..FdLayout::from_base(20) }, 20);
+}
+
+#[test]
+fn validate_rejects_the_standard_streams() {
+    for fd in 0..=2 {
+        duplicate_fd(FdLayout { p2c_send: fd, ..FdLayout::from_base(10) }, fd);
+        duplicate_fd(FdLayout { c2p_shm: fd, ..FdLayout::from_base(10) }, fd);
+    }
+}
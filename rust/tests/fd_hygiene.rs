@@ -0,0 +1,45 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::start_echo;
+
+// The child's eventfds and memfds, by fd number.
+fn channel_fds(pid: u32) -> BTreeMap<i32, String> {
+    std::fs::read_dir(format!("/proc/{}/fd", pid)).unwrap().flatten()
+        .filter_map(|entry| {
+            let target = std::fs::read_link(entry.path()).ok()?.to_string_lossy().into_owned();
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            (target.contains("[eventfd]") || target.starts_with("/memfd:")).then_some((fd, target))
+        })
+        .collect()
+}
+
+#[test]
+fn child_gets_only_its_own_six() {
+    // Another session's descriptors are open in this process throughout.
+    let mut other = start_echo(4096);
+    let mut parent = start_echo(4096);
+    for session in [&mut other, &mut parent] {
+        session.send_to_peer(b"ping").unwrap();
+        assert_eq!(session.recv_from_peer().unwrap(), b"ping");
+    }
+
+    for session in [&other, &parent] {
+        let fds = channel_fds(session.child_id().unwrap());
+        assert_eq!(fds.keys().copied().collect::<Vec<_>>(), [3, 4, 5, 6, 7, 8], "{:?}", fds);
+    }
+}
+
+#[test]
+fn unrelated_children_inherit_none() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+
+    let mut unrelated = std::process::Command::new("sleep").arg("10").spawn().unwrap();
+    let fds = channel_fds(unrelated.id());
+    unrelated.kill().unwrap();
+    unrelated.wait().unwrap();
+    assert!(fds.is_empty(), "{:?}", fds);
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::EfdStreamError;
+
+use common::{start_child, start_echo};
+
+// How long the child takes to start reading.
+const SLOW: Duration = Duration::from_millis(50);
+
+#[test]
+fn flush_waits_for_an_outstanding_ack() {
+    let (got_tx, got_rx) = mpsc::channel();
+    let (mut parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        thread::sleep(SLOW);
+        got_tx.send(child.recv_from_peer().unwrap()).unwrap();
+    });
+    let start = Instant::now();
+    let sent = parent.send_data_by(b"late", start + Duration::from_millis(10));
+    assert!(matches!(sent, Err(EfdStreamError::Timeout)));
+    parent.flush().unwrap();
+    // The child started its sleep a little before `start`.
+    assert!(start.elapsed() >= SLOW / 2, "flushed after {:?}", start.elapsed());
+    assert_eq!(got_rx.recv().unwrap(), b"late");
+    assert_eq!(parent.poll_acks().unwrap(), 0);
+    child.join().unwrap();
+}
+
+#[test]
+fn flush_after_acked_sends_returns_at_once() {
+    let mut parent = start_echo(4096);
+    parent.flush().unwrap();
+    parent.send_to_peer(b"ping").unwrap();
+    parent.flush().unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use std::process::Command;
+
+use common::{echo_builder, CHILD};
+
+const FDS: [&str; 12] = ["-fd-p2c-send", "3", "-fd-p2c-ack", "4", "-fd-p2c-shm", "5",
+                         "-fd-c2p-send", "6", "-fd-c2p-ack", "7", "-fd-c2p-shm", "8"];
+
+// Runs the echo child, which builds itself with `from_env_args`, straight
+// from `args` and returns the error it exits with.
+fn echo_error(args: &[&str]) -> String {
+    let output = Command::new(CHILD).args(["-mode", "echo"]).args(args).output().unwrap();
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn accepts_what_start_passes_and_ignores_the_rest() {
+    let mut parent = echo_builder().shm_size(4096).handshake(true).child_args(&["-verbose", "-name", "echo"]).build();
+    parent.start().unwrap();
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+}
+
+#[test]
+fn malformed_fd_is_named() {
+    let mut args = FDS.to_vec();
+    args[1] = "three";
+    args.extend(["-shm-size", "4096"]);
+    let err = echo_error(&args);
+    assert!(err.contains("Invalid value for -fd-p2c-send: \"three\""), "{}", err);
+}
+
+#[test]
+fn missing_flag_is_named() {
+    let err = echo_error(&FDS);
+    assert!(err.contains("Missing -shm-size"), "{}", err);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{FrameGuard, ShmParent};
+
+use common::{payload, start_child_with};
+
+const HOLD: Duration = Duration::from_millis(50);
+
+// Guarded listener on a thread; each message goes to `on_frame` and then
+// down the returned channel.
+fn start_guarded(on_frame: fn(FrameGuard<'_>) -> Vec<u8>) -> (ShmParent, mpsc::Receiver<Vec<u8>>, thread::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let (parent, child) = start_child_with(ShmParent::builder("unused").shm_size(64 * 1024), move |mut child| {
+        child.listen_guarded(|frame| tx.send(on_frame(frame)).unwrap()).unwrap();
+    });
+    (parent, rx, child)
+}
+
+#[test]
+fn held_guard_blocks_the_sender() {
+    // The borrow crosses to another thread, which holds it before copying.
+    let (mut parent, rx, child) = start_guarded(|frame| {
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(HOLD);
+                frame.to_vec()
+            }).join().unwrap()
+        })
+    });
+    let sent = payload(1, 4096);
+    let start = Instant::now();
+    parent.send_to_peer(&sent).unwrap();
+    assert!(start.elapsed() >= HOLD);
+    assert_eq!(rx.recv().unwrap(), sent);
+
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn early_ack_releases_the_sender() {
+    let (mut parent, rx, child) = start_guarded(|frame| {
+        let data = frame.to_vec();
+        frame.ack().unwrap();
+        thread::sleep(HOLD);
+        data
+    });
+    for i in 0..3 {
+        let sent = payload(i, 100);
+        let start = Instant::now();
+        parent.send_to_peer(&sent).unwrap();
+        assert!(start.elapsed() < HOLD);
+        assert_eq!(rx.recv().unwrap(), sent);
+    }
+
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn parent_guard_blocks_the_child() {
+    let (tx, rx) = mpsc::channel();
+    let (mut parent, child) = start_child_with(ShmParent::builder("unused").shm_size(64 * 1024), move |mut child| {
+        child.init().unwrap();
+        for i in 0..3 {
+            child.send_to_peer(&payload(i, 4096)).unwrap();
+            tx.send(i).unwrap();
+        }
+    });
+
+    for i in 0..3 {
+        let frame = parent.read_data_ref().unwrap();
+        assert_eq!(&*frame, &payload(i, 4096)[..]);
+        thread::sleep(HOLD);
+        assert!(rx.try_recv().is_err(), "the child's send returned before the ACK");
+        drop(frame);
+        assert_eq!(rx.recv().unwrap(), i);
+    }
+    child.join().unwrap();
+}
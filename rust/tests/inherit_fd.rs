@@ -0,0 +1,50 @@
+mod common;
+
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+use efdstream::EfdStreamError;
+
+use common::echo_builder;
+
+// Both ends close-on-exec, as std opens everything.
+fn pipe() -> (OwnedFd, OwnedFd) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+    unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+}
+
+// Whether every copy of the pipe's write end is closed, waiting up to
+// `timeout_ms` for it.
+fn hung_up(read: &OwnedFd, timeout_ms: i32) -> bool {
+    let mut pollfd = libc::pollfd { fd: read.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    assert!(unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } >= 0);
+    pollfd.revents & libc::POLLHUP != 0
+}
+
+#[test]
+fn child_holds_the_inherited_fd() {
+    let (read, write) = pipe();
+    let mut parent = echo_builder().shm_size(4096).inherit_fd(write.as_raw_fd(), 20).build();
+    parent.start().unwrap();
+    drop(write);
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+    // Only the child's copy keeps the pipe open, until it is killed.
+    assert!(!hung_up(&read, 0));
+    drop(parent);
+    assert!(hung_up(&read, 5000));
+}
+
+#[test]
+fn rejects_a_number_the_channels_use() {
+    let (_read, write) = pipe();
+    let mut parent = echo_builder().inherit_fd(write.as_raw_fd(), 5).build();
+    assert!(matches!(parent.start(), Err(EfdStreamError::DuplicateFd { fd: 5 })));
+
+    // The control fd goes just above the layout, at 9.
+    let mut parent = echo_builder().control_channel(true).inherit_fd(write.as_raw_fd(), 9).build();
+    assert!(matches!(parent.start(), Err(EfdStreamError::DuplicateFd { fd: 9 })));
+
+    let mut parent = echo_builder().inherit_fd(write.as_raw_fd(), 20).inherit_fd(write.as_raw_fd(), 20).build();
+    assert!(matches!(parent.start(), Err(EfdStreamError::DuplicateFd { fd: 20 })));
+}
@@ -0,0 +1,28 @@
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::start_child;
+
+#[test]
+fn full_channel_holds_back_the_ack() {
+    let (tx, rx) = mpsc::sync_channel(1);
+    let (mut parent, child) = start_child(move |mut child| child.listen_bounded(tx));
+    // Fills the channel; nothing is holding the ACK back yet.
+    parent.send_to_peer(b"one").unwrap();
+
+    let delay = Duration::from_millis(200);
+    let consumer = thread::spawn(move || {
+        thread::sleep(delay);
+        rx.into_iter().collect::<Vec<_>>()
+    });
+    let start = Instant::now();
+    parent.send_to_peer(b"two").unwrap();
+    assert!(start.elapsed() >= delay, "ACKed after {:?} with the channel full", start.elapsed());
+
+    parent.shutdown_send().unwrap();
+    child.join().unwrap().unwrap();
+    assert_eq!(consumer.join().unwrap(), [b"one".to_vec(), b"two".to_vec()]);
+}
@@ -0,0 +1,38 @@
+use std::env;
+use std::process::Command;
+
+use efdstream::{EfdStreamError, ShmParent};
+
+const IN_CHILD: &str = "EFDSTREAM_LOCK_MEMORY_CHILD";
+const LIMIT: u64 = 4096;
+
+// Runs in its own process, so the lowered limit can't reach other tests.
+fn start_under_a_low_limit() {
+    let limit = libc::rlimit { rlim_cur: LIMIT, rlim_max: LIMIT };
+    assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) }, 0);
+    // Root's CAP_IPC_LOCK would ignore the limit.
+    if unsafe { libc::geteuid() } == 0 {
+        assert_eq!(unsafe { libc::setuid(65534) }, 0);
+    }
+    let mut parent = ShmParent::builder("unused").shm_size(64 * 1024).lock_memory(true).build();
+    match parent.start() {
+        Err(EfdStreamError::MemlockLimit { requested, limit }) => {
+            assert!(requested >= 2 * 64 * 1024, "{}", requested);
+            assert_eq!(limit, LIMIT);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn low_memlock_limit_fails_start() {
+    if env::var_os(IN_CHILD).is_some() {
+        return start_under_a_low_limit();
+    }
+    let status = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "low_memlock_limit_fails_start", "--test-threads=1"])
+        .env(IN_CHILD, "1")
+        .status()
+        .unwrap();
+    assert!(status.success(), "{}", status);
+}
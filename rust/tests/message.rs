@@ -0,0 +1,66 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::thread;
+
+use efdstream::{DuplexChannel, EfdStreamError, InProcess, Message};
+
+use common::start_echo;
+
+#[derive(Debug, PartialEq)]
+struct Reading {
+    sensor: u32,
+    value: f64,
+    label: String,
+}
+
+impl Message for Reading {
+    fn encode(&self, payload: &mut Vec<u8>) {
+        payload.extend_from_slice(&self.sensor.to_le_bytes());
+        payload.extend_from_slice(&self.value.to_le_bytes());
+        payload.extend_from_slice(self.label.as_bytes());
+    }
+
+    fn decode(payload: &[u8]) -> efdstream::error::Result<Self> {
+        if payload.len() < 12 {
+            return Err(std::io::Error::new(ErrorKind::InvalidData, "short reading").into());
+        }
+        Ok(Reading {
+            sensor: u32::from_le_bytes(payload[..4].try_into().unwrap()),
+            value: f64::from_le_bytes(payload[4..12].try_into().unwrap()),
+            label: String::decode(&payload[12..])?,
+        })
+    }
+}
+
+fn kind(e: EfdStreamError) -> ErrorKind {
+    std::io::Error::from(e).kind()
+}
+
+#[test]
+fn typed_values_round_trip() {
+    let mut parent = start_echo(4096);
+    for sensor in 0..3 {
+        let reading = Reading { sensor, value: sensor as f64 * 1.5, label: format!("sensor {}", sensor) };
+        parent.send_message(&reading).unwrap();
+        assert_eq!(parent.recv_message::<Reading>().unwrap(), reading);
+    }
+    parent.send_message(&"text".to_string()).unwrap();
+    assert_eq!(parent.recv_message::<String>().unwrap(), "text");
+}
+
+#[test]
+fn oversized_and_malformed_values_fail_cleanly() {
+    let (mut a, mut b) = InProcess::pair(64);
+    let big = Reading { sensor: 1, value: 0.0, label: "x".repeat(64) };
+    assert_eq!(kind(a.send_message(&big).unwrap_err()), ErrorKind::InvalidInput);
+    assert!(matches!(a.send_message(&String::new()), Err(EfdStreamError::ReservedLength { len: 0 })));
+
+    let peer = thread::spawn(move || {
+        b.send(&[1, 2, 3]).unwrap();
+        b.send(&[0xff, 0xfe]).unwrap();
+    });
+    assert_eq!(kind(a.recv_message::<Reading>().unwrap_err()), ErrorKind::InvalidData);
+    assert_eq!(kind(a.recv_message::<String>().unwrap_err()), ErrorKind::InvalidData);
+    peer.join().unwrap();
+}
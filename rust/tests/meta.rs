@@ -0,0 +1,20 @@
+mod common;
+
+use efdstream::efd::META_LEN;
+
+use common::{payload, start_child};
+
+#[test]
+fn metadata_travels_beside_the_payload_both_ways() {
+    let (mut parent, child) = start_child(move |mut child| {
+        let (data, meta) = child.read_data_with_meta::<8>().unwrap();
+        assert_eq!(data, payload(1, 1000));
+        assert_eq!(&meta, b"req-0001");
+        child.send_data_with_meta(&payload(2, 2000), &[7u8; META_LEN]).unwrap();
+    });
+    parent.send_data_with_meta(&payload(1, 1000), b"req-0001").unwrap();
+    let (data, meta) = parent.read_data_with_meta::<META_LEN>().unwrap();
+    assert_eq!(data, payload(2, 2000));
+    assert_eq!(meta, [7u8; META_LEN]);
+    child.join().unwrap();
+}
@@ -0,0 +1,17 @@
+mod common;
+
+use common::{payload, start_echo};
+
+#[test]
+fn counts_payload_bytes_both_ways() {
+    let mut parent = start_echo(4096);
+    for (i, len) in [10, 3000, 100].into_iter().enumerate() {
+        let sent = payload(i as u8, len);
+        parent.send_to_peer(&sent).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), sent);
+    }
+    let metrics = parent.metrics();
+    assert_eq!((metrics.messages_sent, metrics.messages_received), (3, 3));
+    assert_eq!((metrics.bytes_sent, metrics.bytes_received), (3110, 3110));
+    assert_eq!(metrics.max_message_len, 3000);
+}
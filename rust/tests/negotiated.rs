@@ -0,0 +1,41 @@
+mod common;
+
+use efdstream::{Advice, Direction, Endianness, SessionInfo};
+
+use common::echo_builder;
+
+#[test]
+fn reports_what_the_session_runs_with() {
+    let mut parent = echo_builder().shm_size(1000).handshake(true).timestamps(true).control_channel(true).build();
+    parent.start().unwrap();
+    let info = parent.negotiated();
+    assert_eq!(info, SessionInfo {
+        // Rounded up to fill the pages of each mapping.
+        p2c_size: parent.usable_size(),
+        c2p_size: parent.c2p_usable_size(),
+        direction: Direction::Bidirectional,
+        handshake: true,
+        endianness: Endianness::Native,
+        timestamps: true,
+        encrypted: false,
+        advice: None,
+        huge_pages: false,
+        memory_locked: false,
+        control_channel: true,
+        priority_channel: false,
+        double_buffer: false,
+        shared_state_size: 0,
+        ack_spin: 0,
+    });
+    assert!(info.p2c_size > 1000);
+}
+
+#[test]
+fn huge_pages_depend_on_the_kernel_setting() {
+    let mut parent = echo_builder().shm_size(4096).advise(Advice::HugePage).build();
+    parent.start().unwrap();
+    let info = parent.negotiated();
+    assert_eq!(info.advice, Some(Advice::HugePage));
+    let setting = std::fs::read_to_string("/sys/kernel/mm/transparent_hugepage/shmem_enabled").unwrap_or_default();
+    assert_eq!(info.huge_pages, !setting.is_empty() && !setting.contains("[never]") && !setting.contains("[deny]"));
+}
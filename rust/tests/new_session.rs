@@ -0,0 +1,48 @@
+mod common;
+
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
+
+use efdstream::ShmParent;
+
+use common::CHILD;
+
+#[test]
+fn child_gets_its_own_session_and_its_group_dies_with_the_parent() {
+    // The echo child, behind a script that first starts a grandchild in the
+    // same group and leaves its pid in `pid_file`.
+    let pid_file = std::env::temp_dir().join(format!("efdstream-session-{}.pid", std::process::id()));
+    let script = std::env::temp_dir().join(format!("efdstream-session-{}.sh", std::process::id()));
+    std::fs::write(&script, format!("#!/bin/sh\nsleep 30 &\necho $! > {}\nexec {} \"$@\"\n", pid_file.display(), CHILD)).unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    // The orphaned grandchild is reparented to us, so we can reap it.
+    assert_eq!(unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1) }, 0);
+
+    let mut parent = ShmParent::builder(script.to_str().unwrap())
+        .shm_size(4096)
+        .child_args(&["-mode", "echo"])
+        .new_session(true)
+        .build();
+    parent.start().unwrap();
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+    let child = parent.child_id().unwrap() as libc::pid_t;
+    let grandchild: libc::pid_t = std::fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap();
+    let _ = std::fs::remove_file(&pid_file);
+    let _ = std::fs::remove_file(&script);
+    unsafe {
+        assert_ne!(libc::getsid(child), libc::getsid(0));
+        assert_eq!(libc::getsid(child), child);
+        assert_eq!(libc::getpgid(grandchild), child);
+    }
+
+    drop(parent);
+    // Until the child itself is gone the grandchild isn't ours to wait for.
+    let start = Instant::now();
+    let mut status = 0;
+    while unsafe { libc::waitpid(grandchild, &mut status, libc::WNOHANG) } != grandchild {
+        assert!(start.elapsed() < Duration::from_secs(5), "grandchild {} outlived the parent", grandchild);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(libc::WIFSIGNALED(status) && libc::WTERMSIG(status) == libc::SIGKILL, "{:#x}", status);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc;
+
+use common::start_child;
+
+#[test]
+fn full_nonblocking_doorbell_fails_with_would_block() {
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    // Holds the fds without ever reading the doorbell.
+    let (mut parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        done_rx.recv().unwrap();
+    });
+
+    let doorbell = parent.p2c_send_fd().unwrap().as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(doorbell, libc::F_GETFL);
+        assert_ne!(libc::fcntl(doorbell, libc::F_SETFL, flags | libc::O_NONBLOCK), -1);
+        // The largest count an eventfd holds; any further write overflows.
+        let full = (u64::MAX - 1).to_ne_bytes();
+        assert_eq!(libc::write(doorbell, full.as_ptr().cast(), full.len()), 8);
+    }
+
+    let err = parent.send_to_peer(b"stuck").unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::WouldBlock);
+    done_tx.send(()).unwrap();
+    child.join().unwrap();
+}
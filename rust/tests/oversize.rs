@@ -0,0 +1,57 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, OversizePolicy, ShmChild, ShmParent};
+
+use common::payload;
+
+// A thread child given half the parent's P2C size. It takes the region for a
+// double-buffered one and reads frames from the first half only, so a send
+// longer than that announces more than it can read.
+fn start_narrow_child(policy: OversizePolicy) -> (ShmParent, thread::JoinHandle<Vec<u8>>, usize) {
+    let mut parent = ShmParent::builder("unused").shm_size(4096).build();
+    let fds = parent.create_fds().unwrap();
+    let half = fds.p2c_size / 2;
+    let child = thread::spawn(move || {
+        let raw = [&fds.p2c_send, &fds.p2c_ack, &fds.p2c_shm, &fds.c2p_send, &fds.c2p_ack, &fds.c2p_shm]
+            .map(|fd| fd.as_raw_fd());
+        let mut child = ShmChild::new(raw[0], raw[1], raw[2], raw[3], raw[4], raw[5], half)
+            .c2p_size(fds.c2p_size)
+            .oversize_policy(policy);
+        match child.recv_from_peer() {
+            Ok(data) => data,
+            Err(e) => {
+                assert_eq!(std::io::Error::from(e).kind(), ErrorKind::InvalidData);
+                b"failed".to_vec()
+            }
+        }
+    });
+    parent.attach().unwrap();
+    (parent, child, half)
+}
+
+fn send_oversize(parent: &mut ShmParent, half: usize) {
+    let started = Instant::now();
+    let err = parent.send_to_peer(&payload(1, half + 1)).unwrap_err();
+    assert!(matches!(err, EfdStreamError::Rejected), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(5), "rejected after {:?}", started.elapsed());
+}
+
+#[test]
+fn skip_rejects_the_frame_and_keeps_listening() {
+    let (mut parent, child, half) = start_narrow_child(OversizePolicy::Skip);
+    send_oversize(&mut parent, half);
+    parent.send_to_peer(&payload(2, 500)).unwrap();
+    assert_eq!(child.join().unwrap(), payload(2, 500));
+}
+
+#[test]
+fn fail_rejects_the_frame_and_ends_the_receive() {
+    let (mut parent, child, half) = start_narrow_child(OversizePolicy::Fail);
+    send_oversize(&mut parent, half);
+    assert_eq!(child.join().unwrap(), b"failed");
+}
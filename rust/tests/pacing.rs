@@ -0,0 +1,35 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use efdstream::EfdStreamError;
+
+use common::echo_builder;
+
+const INTERVAL: Duration = Duration::from_millis(20);
+
+#[test]
+fn sends_are_spaced_by_the_interval() {
+    let mut parent = echo_builder().shm_size(4096).min_send_interval(INTERVAL).build();
+    parent.start().unwrap();
+    let start = Instant::now();
+    for i in 0..5u8 {
+        parent.send_to_peer(&[i]).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), [i]);
+    }
+    // The first send goes straight out; each later one waits its turn.
+    assert!(start.elapsed() >= INTERVAL * 4, "5 sends took {:?}", start.elapsed());
+}
+
+#[test]
+fn deadline_inside_the_pacing_wait_times_out() {
+    let mut parent = echo_builder().shm_size(4096).min_send_interval(Duration::from_secs(60)).build();
+    parent.start().unwrap();
+    parent.send_to_peer(b"first").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"first");
+
+    let start = Instant::now();
+    let paced = parent.send_data_by(b"second", Instant::now() + INTERVAL);
+    assert!(matches!(paced, Err(EfdStreamError::Timeout)), "{:?}", paced);
+    assert!(start.elapsed() < INTERVAL, "waited {:?} before failing", start.elapsed());
+}
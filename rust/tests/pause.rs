@@ -0,0 +1,69 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::thread;
+
+use efdstream::{EfdStreamError, PauseHandle, ShmChild, ShmParent};
+
+use common::start_child;
+
+// A socket child that hands its pause handle back, then runs `listen`.
+fn start(listen: fn(&mut ShmChild)) -> (ShmParent, PauseHandle, thread::JoinHandle<()>) {
+    let (handle_tx, handle_rx) = mpsc::channel();
+    let (parent, child) = start_child(move |mut child| {
+        handle_tx.send(child.pause_handle().unwrap()).unwrap();
+        listen(&mut child);
+    });
+    (parent, handle_rx.recv().unwrap(), child)
+}
+
+fn would_block(result: Result<(), EfdStreamError>) {
+    assert_eq!(std::io::Error::from(result.unwrap_err()).kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn worker_pauses_and_resumes_the_parent() {
+    let (mut parent, handle, child) = start(|child| {
+        let (tx, rx) = mpsc::channel();
+        child.listen_async(tx).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [b"after".to_vec()]);
+    });
+    let worker = thread::spawn(move || handle.request_pause().map(|_| handle));
+    let handle = worker.join().unwrap().unwrap();
+    assert!(parent.is_paused());
+    would_block(parent.send_to_peer(b"during"));
+
+    handle.request_resume().unwrap();
+    assert!(!parent.is_paused());
+    parent.send_to_peer(b"after").unwrap();
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn responder_pauses_from_the_handler() {
+    let (mut parent, handle, child) = start(|child| {
+        child.listen_with_responder(|request, responder| {
+            if request == b"full" {
+                responder.request_pause().unwrap();
+            }
+        }).unwrap();
+    });
+    parent.send_to_peer(b"full").unwrap();
+    assert!(parent.is_paused());
+    would_block(parent.send_to_peer(b"more"));
+    handle.request_resume().unwrap();
+    parent.send_to_peer(b"more").unwrap();
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn handle_outliving_the_child_fails() {
+    let (parent, handle, child) = start(|_| {});
+    child.join().unwrap();
+    let err = handle.request_pause().unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::BrokenPipe);
+    assert!(!parent.is_paused());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use efdstream::FrameHeader;
+
+use common::{echo_builder, payload, start_echo};
+
+#[test]
+fn peek_leaves_the_message_for_recv() {
+    let mut parent = start_echo(4096);
+    let sent = payload(1, 1000);
+    parent.send_to_peer(&sent).unwrap();
+    let header = parent.peek_frame().unwrap();
+    assert_eq!((header.len, header.sent), (1000, None));
+    assert_eq!(header.head(), &sent[..FrameHeader::HEAD_LEN]);
+    // Still the same message until it is taken.
+    assert_eq!(parent.peek_frame().unwrap(), header);
+    assert_eq!(parent.try_read_data().unwrap(), Some(sent));
+
+    parent.send_to_peer(b"short").unwrap();
+    let header = parent.peek_frame().unwrap();
+    assert_eq!((header.len, header.head()), (5, &b"short"[..]));
+    assert_eq!(parent.recv_from_peer().unwrap(), b"short");
+}
+
+#[test]
+fn skip_drops_the_peeked_message() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(b"unwanted").unwrap();
+    assert_eq!(parent.peek_frame().unwrap().head(), b"unwanted");
+    parent.skip_frame().unwrap();
+    // Skipping ACKed it, so the child is free to take the next one.
+    parent.send_to_peer(b"wanted").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"wanted");
+
+    // Without a peek it drops the next message.
+    parent.send_to_peer(b"unwanted").unwrap();
+    parent.skip_frame().unwrap();
+    parent.send_to_peer(b"wanted").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"wanted");
+}
+
+#[test]
+fn peek_reads_the_timestamp() {
+    let mut parent = echo_builder().shm_size(4096).timestamps(true).build();
+    parent.start().unwrap();
+    parent.send_to_peer(b"ping").unwrap();
+    let header = parent.peek_frame().unwrap();
+    assert_eq!((header.len, header.head()), (4, &b"ping"[..]));
+    let (data, sent) = parent.read_timed().unwrap();
+    assert_eq!((data, Some(sent)), (b"ping".to_vec(), header.sent));
+}
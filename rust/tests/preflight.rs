@@ -0,0 +1,28 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::{EfdStreamError, ShmParent};
+
+use common::echo_builder;
+
+fn child_error(child_path: &str) -> ErrorKind {
+    match ShmParent::builder(child_path).build().preflight() {
+        Err(EfdStreamError::Unavailable { capability: "the child binary", error }) => error.kind(),
+        other => panic!("expected the child binary to be unavailable, got {:?}", other),
+    }
+}
+
+#[test]
+fn passes_where_start_works() {
+    echo_builder().seal_memfds(true).build().preflight().unwrap();
+    // Looked up on PATH, like the spawn does.
+    ShmParent::builder("sh").build().preflight().unwrap();
+}
+
+#[test]
+fn names_an_unusable_child() {
+    assert_eq!(child_error("/nonexistent/efdstream"), ErrorKind::NotFound);
+    assert_eq!(child_error("efdstream-not-on-path"), ErrorKind::NotFound);
+    assert_eq!(child_error(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")), ErrorKind::PermissionDenied);
+}
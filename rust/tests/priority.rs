@@ -0,0 +1,66 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, ShmParent};
+
+use common::{echo_builder, start_child_with, start_echo};
+
+fn kind(e: EfdStreamError) -> ErrorKind {
+    std::io::Error::from(e).kind()
+}
+
+#[test]
+fn echoes_priority_messages() {
+    // The control fd comes first above the layout, then the priority fds.
+    let mut parent = echo_builder().shm_size(4096).control_channel(true).priority_channel(true).build();
+    parent.start().unwrap();
+    parent.send_priority(b"urgent").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"urgent");
+    parent.send_to_peer(b"bulk").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"bulk");
+}
+
+#[test]
+fn priority_overtakes_a_waiting_bulk_message() {
+    let (order_tx, order_rx) = mpsc::channel();
+    let (go_tx, go_rx) = mpsc::channel::<()>();
+    let builder = ShmParent::builder("unused").shm_size(4096).priority_channel(true);
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        child.init().unwrap();
+        // Both doorbells are ringing by the time this starts reading.
+        go_rx.recv().unwrap();
+        for _ in 0..2 {
+            order_tx.send(child.recv_from_peer().unwrap()).unwrap();
+        }
+    });
+    let bulk = parent.send_data_by(b"bulk", Instant::now() + Duration::from_millis(20));
+    assert!(matches!(bulk, Err(EfdStreamError::Timeout)));
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        go_tx.send(()).unwrap();
+    });
+    parent.send_priority(b"urgent").unwrap();
+
+    assert_eq!(order_rx.recv().unwrap(), b"urgent");
+    assert_eq!(order_rx.recv().unwrap(), b"bulk");
+    child.join().unwrap();
+    releaser.join().unwrap();
+}
+
+#[test]
+fn needs_the_priority_channel() {
+    let mut parent = start_echo(4096);
+    assert_eq!(kind(parent.send_priority(b"urgent").unwrap_err()), ErrorKind::Unsupported);
+}
+
+#[test]
+fn rejects_oversize_priority_message() {
+    let mut parent = echo_builder().shm_size(4096).priority_channel(true).build();
+    parent.start().unwrap();
+    let err = parent.send_priority(&vec![0; parent.usable_size() + 1]).unwrap_err();
+    assert_eq!(kind(err), ErrorKind::InvalidInput);
+}
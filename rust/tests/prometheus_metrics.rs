@@ -0,0 +1,29 @@
+#![cfg(feature = "prometheus")]
+
+mod common;
+
+use prometheus::Registry;
+
+use common::start_echo;
+
+// The value of `name{direction="..."}` in the registry, if it is there.
+fn counter(registry: &Registry, name: &str, direction: &str) -> Option<f64> {
+    registry.gather().iter()
+        .find(|family| family.name() == name)?
+        .get_metric().iter()
+        .find(|metric| metric.get_label().iter().any(|label| label.name() == "direction" && label.value() == direction))
+        .map(|metric| metric.get_counter().get_value())
+}
+
+#[test]
+fn registered_metrics_count_a_round_trip() {
+    let mut parent = start_echo(4096);
+    let registry = Registry::new();
+    parent.register_metrics(&registry).unwrap();
+
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+    assert_eq!(counter(&registry, "efdstream_messages_total", "sent"), Some(1.0));
+    assert_eq!(counter(&registry, "efdstream_messages_total", "received"), Some(1.0));
+    assert_eq!(counter(&registry, "efdstream_bytes_total", "sent"), Some(4.0));
+}
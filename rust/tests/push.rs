@@ -0,0 +1,60 @@
+mod common;
+
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+use mio::unix::SourceFd;
+use mio::{Events, Interest, Poll, Token};
+
+use efdstream::{Direction, EfdStreamError, ShmParent};
+
+use common::{payload, start_child_with};
+
+const EVENTS: u8 = 50;
+
+fn push_session(direction: Direction) {
+    // Unprompted events, some in bursts and some after a pause, so both a
+    // waiting parent and one that is busy draining get woken.
+    let builder = ShmParent::builder("unused").shm_size(4096).direction(direction);
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        for i in 0..EVENTS {
+            if i % 10 == 0 {
+                thread::sleep(Duration::from_millis(5));
+            }
+            child.send_to_peer(&payload(i, 100)).unwrap();
+        }
+        child.shutdown_send().unwrap();
+    });
+
+    // mio registrations are edge-triggered.
+    let mut poll = Poll::new().unwrap();
+    let doorbell = parent.c2p_send_fd().unwrap().as_raw_fd();
+    poll.registry().register(&mut SourceFd(&doorbell), Token(0), Interest::READABLE).unwrap();
+    let mut events = Events::with_capacity(4);
+    let mut received = Vec::new();
+    'reactor: loop {
+        poll.poll(&mut events, Some(Duration::from_secs(5))).unwrap();
+        assert!(!events.is_empty(), "no wakeup after {} events", received.len());
+        loop {
+            match parent.try_read_data() {
+                Ok(Some(event)) => received.push(event),
+                Ok(None) => break,
+                Err(EfdStreamError::PeerClosed) => break 'reactor,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+    }
+    assert_eq!(received, (0..EVENTS).map(|i| payload(i, 100)).collect::<Vec<_>>());
+    child.join().unwrap();
+}
+
+#[test]
+fn child_pushes_to_an_edge_triggered_parent() {
+    push_session(Direction::Bidirectional);
+}
+
+#[test]
+fn push_only_session() {
+    push_session(Direction::ChildToParent);
+}
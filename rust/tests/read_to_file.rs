@@ -0,0 +1,22 @@
+mod common;
+
+use std::fs;
+
+use common::{payload, start_child};
+
+#[test]
+fn messages_are_appended_to_the_file() {
+    let path = std::env::temp_dir().join(format!("efdstream-read-to-file-{}.bin", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let (mut parent, child) = start_child(move |mut child| {
+        child.send_to_peer(&payload(1, 3000)).unwrap();
+        child.send_to_peer(&payload(2, 500)).unwrap();
+    });
+    assert_eq!(parent.read_data_to_file(&path).unwrap(), 3000);
+    assert_eq!(parent.read_data_to_file(&path).unwrap(), 500);
+    child.join().unwrap();
+
+    let contents = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(contents, [payload(1, 3000), payload(2, 500)].concat());
+}
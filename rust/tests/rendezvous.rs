@@ -0,0 +1,125 @@
+use std::io::ErrorKind;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use efdstream::{EfdStreamError, ShmChild, ShmParent};
+
+fn socket_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("efdstream-{}-{}.sock", name, std::process::id()))
+}
+
+// A child started on its own, retrying until the parent is listening.
+fn connect_echo(path: PathBuf) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut child = loop {
+            match ShmChild::connect(&path) {
+                Ok(child) => break child,
+                Err(EfdStreamError::Io(e)) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                Err(e) => panic!("{:?}", e),
+            }
+        };
+        child.listen_request_response(|request| request.to_vec()).unwrap();
+    })
+}
+
+fn bind_and_echo(path: &PathBuf) {
+    let child = connect_echo(path.clone());
+    let mut parent = ShmParent::builder("unused").shm_size(4096).build();
+    parent.bind(path).unwrap();
+    assert!(!path.exists());
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn child_connects_by_path() {
+    bind_and_echo(&socket_path("connect"));
+}
+
+#[test]
+fn stale_socket_is_replaced() {
+    let path = socket_path("stale");
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    bind_and_echo(&path);
+}
+
+#[test]
+fn live_socket_or_other_file_is_left_alone() {
+    let path = socket_path("live");
+    let _listener = UnixListener::bind(&path).unwrap();
+    let err = ShmParent::builder("unused").build().bind(&path).unwrap_err();
+    assert!(matches!(&err, EfdStreamError::Io(e) if e.kind() == ErrorKind::AddrInUse), "{:?}", err);
+    std::fs::remove_file(&path).unwrap();
+
+    let path = socket_path("file");
+    std::fs::write(&path, b"not a socket").unwrap();
+    let err = ShmParent::builder("unused").build().bind(&path).unwrap_err();
+    assert!(matches!(&err, EfdStreamError::Io(e) if e.kind() == ErrorKind::AddrInUse), "{:?}", err);
+    assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn child_notices_the_parent_going_away() {
+    let path = socket_path("gone");
+    let child = thread::spawn({
+        let path = path.clone();
+        move || {
+            let mut child = loop {
+                match ShmChild::connect(&path) {
+                    Ok(child) => break child,
+                    Err(_) => thread::sleep(Duration::from_millis(5)),
+                }
+            };
+            child.listen_request_response(|request| request.to_vec()).unwrap_err()
+        }
+    });
+    let mut parent = ShmParent::builder("unused").shm_size(4096).build();
+    parent.bind(&path).unwrap();
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+    // Gone without a shutdown_send, as when the parent process dies.
+    drop(parent);
+    let err = child.join().unwrap();
+    assert!(matches!(&err, EfdStreamError::Io(e) if e.kind() == ErrorKind::ConnectionReset), "{:?}", err);
+}
+
+#[test]
+fn child_reconnects_to_a_restarted_parent() {
+    let path = socket_path("reconnect");
+    let (reconnected, reconnects) = mpsc::channel();
+    let child = thread::spawn({
+        let path = path.clone();
+        move || {
+            let child = loop {
+                match ShmChild::connect(&path) {
+                    Ok(child) => break child,
+                    Err(_) => thread::sleep(Duration::from_millis(5)),
+                }
+            };
+            let mut child = child.reconnect(10, Duration::from_millis(1), move || reconnected.send(()).unwrap());
+            child.listen_request_response(|request| request.to_vec()).unwrap();
+        }
+    });
+    for i in 0..3 {
+        let mut parent = ShmParent::builder("unused").shm_size(4096).build();
+        parent.bind(&path).unwrap();
+        let request = format!("session {}", i);
+        parent.send_to_peer(request.as_bytes()).unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), request.as_bytes());
+        if i == 2 {
+            parent.shutdown_send().unwrap();
+        }
+    }
+    child.join().unwrap();
+    assert_eq!(reconnects.try_iter().count(), 2);
+}
+
@@ -0,0 +1,27 @@
+use efdstream::frame::{check_length, EOF_DOORBELL, RESERVED_DOORBELLS, RESET_DOORBELL, RESIZE_DOORBELL, SECOND_BUFFER};
+use efdstream::EfdStreamError;
+
+fn reserved(len: u64) {
+    match check_length(len as usize) {
+        Err(EfdStreamError::ReservedLength { len: rejected }) => assert_eq!(rejected, len),
+        other => panic!("expected ReservedLength for {:#x}, got {:?}", len, other),
+    }
+}
+
+#[test]
+fn rejects_zero_and_every_sentinel() {
+    reserved(0);
+    for len in [EOF_DOORBELL, RESIZE_DOORBELL, RESET_DOORBELL] {
+        assert!(RESERVED_DOORBELLS.contains(&len));
+        reserved(len);
+    }
+    reserved(SECOND_BUFFER);
+    reserved(SECOND_BUFFER | 100);
+}
+
+#[test]
+fn accepts_ordinary_lengths() {
+    for len in [1, 4096, 1 << 40, SECOND_BUFFER as usize - 1] {
+        check_length(len).unwrap();
+    }
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use std::os::unix::io::AsRawFd;
+
+use common::echo_builder;
+
+#[test]
+fn reset_recovers_a_desynced_session() {
+    let mut parent = echo_builder().shm_size(4096).handshake(true).build();
+    parent.start().unwrap();
+    parent.send_to_peer(b"never read").unwrap();
+    // Once the reply's doorbell rings, the echo child is stuck in
+    // `send_to_peer` waiting for an ACK that won't come.
+    let mut doorbell = libc::pollfd { fd: parent.c2p_send_fd().unwrap().as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    assert_eq!(unsafe { libc::poll(&mut doorbell, 1, 5000) }, 1);
+
+    parent.reset().unwrap();
+    parent.send_to_peer(b"after reset").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"after reset");
+}
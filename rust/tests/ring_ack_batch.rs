@@ -0,0 +1,41 @@
+mod common;
+
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use efdstream::RingShmParent;
+
+use common::CHILD;
+
+const SLOTS: usize = 4;
+const MESSAGES: u64 = 500;
+
+#[test]
+fn batched_acks_on_full_rings_keep_flowing() {
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let mut parent = RingShmParent::new(CHILD, SLOTS, 64)
+            .child_args(&["-mode", "echo", "-ack-batch", "4"])
+            .ack_batch(4);
+        parent.start().unwrap();
+        // Both rings full, plus the reply the child holds: any more and the
+        // two sides would wait on each other.
+        let window = 2 * SLOTS + 1;
+        let mut unanswered = VecDeque::new();
+        for i in 0..MESSAGES {
+            if unanswered.len() == window {
+                let expected: u64 = unanswered.pop_front().unwrap();
+                assert_eq!(parent.read_data().unwrap(), expected.to_ne_bytes());
+            }
+            parent.send_data(&i.to_ne_bytes()).unwrap();
+            unanswered.push_back(i);
+        }
+        for expected in unanswered {
+            assert_eq!(parent.read_data().unwrap(), expected.to_ne_bytes());
+        }
+        done.send(()).unwrap();
+    });
+    finished.recv_timeout(Duration::from_secs(10)).expect("rings stalled with ack_batch(4)");
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, RingShmParent};
+
+use common::CHILD;
+
+#[test]
+fn full_ring_send_gives_up() {
+    // The ring child echoes every message; unread, its replies fill the C2P
+    // ring, it stops reading, and then the P2C ring fills too.
+    let timeout = Duration::from_millis(100);
+    let mut parent = RingShmParent::new(CHILD, 2, 64).block_timeout(Some(timeout));
+    parent.start().unwrap();
+    let start = Instant::now();
+    let err = loop {
+        if let Err(e) = parent.send_data(b"unread") {
+            break e;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "never blocked");
+    };
+    assert!(matches!(err, EfdStreamError::Timeout), "{:?}", err);
+    assert!(start.elapsed() >= timeout);
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use efdstream::{OverflowPolicy, RingShmParent};
+
+use common::CHILD;
+
+const SLOTS: usize = 4;
+const SENT: u64 = 32;
+
+#[test]
+fn full_ring_drops_the_oldest_frames() {
+    let mut parent = RingShmParent::new(CHILD, SLOTS, 64)
+        .child_args(&["-mode", "echo"])
+        .overflow_policy(OverflowPolicy::DropOldest);
+    parent.start().unwrap();
+    // With its replies unread the echo child stalls after a few messages,
+    // and from then on every send overwrites the oldest frame in the ring.
+    for i in 0..SENT {
+        parent.send_data(&i.to_ne_bytes()).unwrap();
+    }
+    let dropped = parent.dropped_frames();
+    assert!(dropped > 0, "the ring never filled");
+    assert_eq!(parent.metrics().dropped_frames, dropped);
+
+    let received: Vec<u64> = (0..SENT - dropped)
+        .map(|_| u64::from_ne_bytes(parent.read_data().unwrap().try_into().unwrap()))
+        .collect();
+    // Whatever was skipped, the order holds and the newest frames all arrive.
+    assert!(received.windows(2).all(|w| w[0] < w[1]), "{:?}", received);
+    assert_eq!(received[received.len() - SLOTS..], (SENT - SLOTS as u64..SENT).collect::<Vec<_>>()[..]);
+    assert!(parent.try_read_data().unwrap().is_none());
+}
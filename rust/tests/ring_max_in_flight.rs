@@ -0,0 +1,22 @@
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, RingShmParent};
+
+const MAX: usize = 3;
+
+#[test]
+fn send_past_the_limit_fails_at_once() {
+    // `true` never reads a frame, so every send stays in flight.
+    let mut parent = RingShmParent::new("/bin/true", 8, 64).max_in_flight(MAX);
+    parent.start().unwrap();
+    for _ in 0..MAX {
+        parent.send_data(b"unread").unwrap();
+    }
+    assert_eq!(parent.in_flight(), MAX);
+
+    let start = Instant::now();
+    let err = parent.send_data(b"one too many").unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(1), "blocked for {:?}", start.elapsed());
+    assert!(matches!(err, EfdStreamError::TooManyInFlight { in_flight: MAX, max: MAX }), "{:?}", err);
+    assert_eq!(parent.in_flight(), MAX);
+}
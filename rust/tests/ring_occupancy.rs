@@ -0,0 +1,49 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{OverflowPolicy, RingShmParent};
+
+use common::CHILD;
+
+const SLOTS: usize = 4;
+
+// Polls `f` until it holds, for up to five seconds.
+fn eventually(mut f: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if f() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    false
+}
+
+#[test]
+fn high_water_mark_tracks_a_full_ring() {
+    let mut parent = RingShmParent::new(CHILD, SLOTS, 64).overflow_policy(OverflowPolicy::DropNewest);
+    assert_eq!(parent.ring_occupancy(), 0);
+    parent.start().unwrap();
+    parent.send_data(b"one").unwrap();
+    assert!(parent.metrics().max_occupancy >= 1);
+
+    // The ring child echoes; with its replies unread it stalls once it holds
+    // a reply for every C2P slot and one more, and the P2C ring fills
+    // behind it.
+    let mut sent = 1;
+    while sent - parent.dropped_frames() < (2 * SLOTS + 1) as u64 {
+        parent.send_data(b"unread").unwrap();
+        sent += 1;
+    }
+    assert!(eventually(|| parent.ring_occupancy() == SLOTS));
+    assert_eq!(parent.metrics().max_occupancy, SLOTS);
+
+    // Reading the replies lets the child catch up; the peak stays.
+    assert!(eventually(|| {
+        parent.drain(Duration::from_millis(10)).unwrap();
+        parent.ring_occupancy() == 0
+    }));
+    assert_eq!(parent.metrics().max_occupancy, SLOTS);
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use efdstream::{EfdStreamError, RingShmParent};
+
+use common::CHILD;
+
+// Offsets from the ring layout in src/ring.rs.
+const HEAD: usize = 64;
+const FIRST_SLOT: usize = 256;
+
+// Maps the parent's C2P memfd, found by its name among our own fds.
+fn map_c2p() -> *mut u8 {
+    let fd = fs::read_dir("/proc/self/fd").unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| fs::read_link(path).is_ok_and(|target| target.to_string_lossy().starts_with("/memfd:efdstream_shm_c2p")))
+        .expect("no C2P memfd");
+    let file = fs::OpenOptions::new().read(true).write(true).open(fd).unwrap();
+    let addr = unsafe {
+        libc::mmap(std::ptr::null_mut(), 4096, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, file.as_raw_fd(), 0)
+    };
+    assert_ne!(addr, libc::MAP_FAILED);
+    addr as *mut u8
+}
+
+fn word(base: *mut u8, offset: usize) -> &'static AtomicU64 {
+    unsafe { &*(base.add(offset) as *const AtomicU64) }
+}
+
+#[test]
+fn rewritten_generation_is_a_stale_read() {
+    let mut parent = RingShmParent::new(CHILD, 4, 64).child_args(&["-mode", "echo"]);
+    parent.start().unwrap();
+    let c2p = map_c2p();
+
+    parent.send_data(b"first").unwrap();
+    // Once the echo is published, make its slot look half-written.
+    let start = Instant::now();
+    while word(c2p, HEAD).load(Ordering::Acquire) == 0 {
+        assert!(start.elapsed() < Duration::from_secs(5), "no echo");
+        thread::sleep(Duration::from_millis(1));
+    }
+    word(c2p, FIRST_SLOT).store(1, Ordering::Release);
+
+    let err = parent.read_data().unwrap_err();
+    assert!(matches!(err, EfdStreamError::StaleRead { seq: 0 }), "{:?}", err);
+    // The slot was given back, so the stream carries on.
+    parent.send_data(b"second").unwrap();
+    assert_eq!(parent.read_data().unwrap(), b"second");
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use common::{payload, start_echo};
+
+#[test]
+fn payload_is_split_across_the_buffers_in_order() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(&payload(1, 250)).unwrap();
+    let (mut a, mut b, mut c) = ([0u8; 100], [0u8; 100], [0u8; 100]);
+    assert_eq!(parent.read_data_scattered(&mut [&mut a, &mut b, &mut c]).unwrap(), 250);
+    assert_eq!([&a[..], &b[..], &c[..50]].concat(), payload(1, 250));
+    // Past the payload, the last buffer is left alone.
+    assert_eq!(c[50..], [0; 50]);
+}
+
+#[test]
+fn oversize_payload_is_acked_and_discarded() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(&payload(2, 301)).unwrap();
+    let (mut a, mut b, mut c) = ([0u8; 100], [0u8; 100], [0u8; 100]);
+    let err = parent.read_data_scattered(&mut [&mut a, &mut b, &mut c]).unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::InvalidInput);
+    // The echo child got its ACK and is free to answer the next one.
+    parent.send_to_peer(&payload(3, 300)).unwrap();
+    assert_eq!(parent.read_data_scattered(&mut [&mut a, &mut b, &mut c]).unwrap(), 300);
+    assert_eq!([a, b, c].concat(), payload(3, 300));
+}
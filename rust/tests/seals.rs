@@ -0,0 +1,57 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+use efdstream::ShmChild;
+
+use common::{echo_builder, payload};
+
+// The seals on every memfd this process holds under `prefix`.
+fn seals_of_memfds(prefix: &str) -> Vec<i32> {
+    let mut seals = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/fd").unwrap().flatten() {
+        let Ok(target) = std::fs::read_link(entry.path()) else { continue };
+        if !target.to_string_lossy().starts_with(&format!("/memfd:{}", prefix)) {
+            continue;
+        }
+        let fd: i32 = entry.file_name().to_str().unwrap().parse().unwrap();
+        seals.push(unsafe { libc::fcntl(fd, libc::F_GET_SEALS) });
+    }
+    seals
+}
+
+#[test]
+fn sealed_regions_still_grow() {
+    let mut parent = echo_builder().shm_size(4096).handshake(true).seal_memfds(true).build();
+    parent.start().unwrap();
+    let seals = seals_of_memfds("efdstream_shm_");
+    assert!(seals.len() >= 2);
+    assert!(seals.iter().all(|&s| s == libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL), "{:?}", seals);
+
+    parent.resize_shm(64 * 1024).unwrap();
+    let sent = payload(1, 40 * 1024);
+    parent.send_to_peer(&sent).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), sent);
+}
+
+#[test]
+fn child_refuses_a_write_sealed_region() {
+    let eventfd = || unsafe { OwnedFd::from_raw_fd(libc::eventfd(0, libc::EFD_CLOEXEC)) };
+    let name = c"test_write_sealed";
+    let memfd = |seals: i32| unsafe {
+        let fd = libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING);
+        assert!(fd >= 0);
+        assert_eq!(libc::ftruncate(fd, 4096), 0);
+        assert_eq!(libc::fcntl(fd, libc::F_ADD_SEALS, seals), 0);
+        OwnedFd::from_raw_fd(fd)
+    };
+    let (p2c_send, p2c_ack, c2p_send, c2p_ack) = (eventfd(), eventfd(), eventfd(), eventfd());
+    // The child only reads P2C, so a write seal there is fine.
+    let (p2c_shm, c2p_shm) = (memfd(libc::F_SEAL_WRITE), memfd(libc::F_SEAL_WRITE));
+
+    let mut child = ShmChild::new(p2c_send.as_raw_fd(), p2c_ack.as_raw_fd(), p2c_shm.as_raw_fd(),
+        c2p_send.as_raw_fd(), c2p_ack.as_raw_fd(), c2p_shm.as_raw_fd(), 4096);
+    let err = child.init().unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::EfdStreamError;
+
+use common::{echo_builder, payload, start_echo};
+
+#[test]
+fn child_gets_the_records_concatenated() {
+    let mut parent = start_echo(4096);
+    let records: Vec<Vec<u8>> = (0..10).map(|i| payload(i, 100 + i as usize)).collect();
+    let (len, count) = parent.send_from_iter(&records).unwrap();
+    assert_eq!((len, count), (records.iter().map(Vec::len).sum(), 10));
+    assert_eq!(parent.recv_from_peer().unwrap(), records.concat());
+
+    // Anything that derefs to bytes will do.
+    assert_eq!(parent.send_from_iter(["one ", "two"]).unwrap(), (7, 2));
+    assert_eq!(parent.recv_from_peer().unwrap(), b"one two");
+}
+
+#[test]
+fn stamps_the_batch() {
+    let mut parent = echo_builder().shm_size(4096).timestamps(true).build();
+    parent.start().unwrap();
+    parent.send_from_iter([&b"log line 1\n"[..], b"log line 2\n"]).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"log line 1\nlog line 2\n");
+}
+
+#[test]
+fn rejects_a_batch_larger_than_the_region() {
+    let mut parent = start_echo(4096);
+    let size = parent.usable_size();
+    let err = parent.send_from_iter([payload(1, size / 2), payload(2, size / 2 + 1)]).unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::InvalidInput);
+    let err = parent.send_from_iter(Vec::<Vec<u8>>::new()).unwrap_err();
+    assert!(matches!(err, EfdStreamError::ReservedLength { len: 0 }), "{:?}", err);
+
+    // The child never saw either, and a batch that fits exactly still goes.
+    assert_eq!(parent.send_from_iter([payload(3, size / 2), payload(4, size - size / 2)]).unwrap(), (size, 2));
+    assert_eq!(parent.recv_from_peer().unwrap(), [payload(3, size / 2), payload(4, size - size / 2)].concat());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use efdstream::ShmParent;
+
+use common::{echo_builder, start_child_with, start_echo};
+
+#[test]
+fn both_sides_see_the_same_bytes() {
+    assert!(ShmParent::builder("unused").shared_state_size(100).build().shared_state().is_none());
+    let builder = ShmParent::builder("unused").shm_size(4096).shared_state_size(100);
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        child.init().unwrap();
+        // The messages order the accesses; the region itself carries no sync.
+        assert_eq!(child.recv_from_peer().unwrap(), b"written");
+        let state = child.shared_state_mut().unwrap();
+        assert_eq!(state.len(), 100);
+        assert_eq!(&state[..5], b"hello");
+        state[50..55].copy_from_slice(b"world");
+        child.send_to_peer(b"written").unwrap();
+    });
+    parent.shared_state_mut().unwrap()[..5].copy_from_slice(b"hello");
+    parent.send_to_peer(b"written").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"written");
+    assert_eq!(&parent.shared_state().unwrap()[50..55], b"world");
+    child.join().unwrap();
+}
+
+#[test]
+fn spawned_child_accepts_the_state_fd() {
+    let mut parent = echo_builder().shm_size(4096).control_channel(true).priority_channel(true).shared_state_size(4096).build();
+    parent.start().unwrap();
+    assert_eq!(parent.shared_state().unwrap().len(), 4096);
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+}
+
+#[test]
+fn absent_unless_requested() {
+    let parent = start_echo(4096);
+    assert!(parent.shared_state().is_none());
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::slice;
+
+use efdstream::{Direction, ShmParent};
+
+use common::{payload, start_child_with};
+
+// The start address of each mapping of a memfd named with `prefix`.
+fn mapped_at(prefix: &str) -> Vec<usize> {
+    std::fs::read_to_string("/proc/self/maps").unwrap().lines()
+        .filter(|line| line.contains(&format!("/memfd:{}", prefix)))
+        .map(|line| usize::from_str_radix(line.split('-').next().unwrap(), 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn both_sides_report_where_the_regions_are() {
+    assert_eq!(ShmParent::builder("unused").build().shm_base(Direction::ParentToChild), None);
+    let builder = ShmParent::builder("unused").shm_size(4096).memfd_prefix("shm_base_test");
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        assert_eq!(child.shm_base(Direction::ParentToChild), None);
+        let request = child.recv_from_peer().unwrap();
+        let p2c = child.shm_base(Direction::ParentToChild).unwrap();
+        assert_eq!(unsafe { slice::from_raw_parts(p2c, request.len()) }, &request[..]);
+        child.send_to_peer(&payload(2, 1000)).unwrap();
+        let c2p = child.shm_base(Direction::ChildToParent).unwrap();
+        assert_eq!(unsafe { slice::from_raw_parts(c2p, 1000) }, &payload(2, 1000)[..]);
+        assert_eq!(child.shm_base(Direction::Bidirectional), None);
+        let mapped = mapped_at("shm_base_test");
+        for base in [p2c as usize, c2p as usize] {
+            assert!(mapped.contains(&base), "{:#x} not in {:x?}", base, mapped);
+        }
+    });
+    parent.send_to_peer(&payload(1, 1000)).unwrap();
+    let p2c = parent.shm_base(Direction::ParentToChild).unwrap();
+    assert_eq!(unsafe { slice::from_raw_parts(p2c, 1000) }, &payload(1, 1000)[..]);
+    assert_eq!(parent.recv_from_peer().unwrap(), payload(2, 1000));
+    let c2p = parent.shm_base(Direction::ChildToParent).unwrap();
+    assert_eq!(parent.shm_base(Direction::Bidirectional), None);
+    let mapped = mapped_at("shm_base_test");
+    for base in [p2c as usize, c2p as usize] {
+        assert!(mapped.contains(&base), "{:#x} not in {:x?}", base, mapped);
+    }
+    child.join().unwrap();
+}
+
+#[test]
+fn simplex_has_no_base_for_the_missing_direction() {
+    let builder = ShmParent::builder("unused").shm_size(4096).direction(Direction::ParentToChild);
+    let (parent, child) = start_child_with(builder, move |mut child| {
+        child.init().unwrap();
+        assert!(child.shm_base(Direction::ParentToChild).is_some());
+        assert_eq!(child.shm_base(Direction::ChildToParent), None);
+    });
+    assert!(parent.shm_base(Direction::ParentToChild).is_some());
+    assert_eq!(parent.shm_base(Direction::ChildToParent), None);
+    child.join().unwrap();
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+use efdstream::{Direction, EfdStreamError, ShmChild, ShmParent};
+
+use common::{payload, start_child, start_child_with};
+
+fn memfd_len(fd: BorrowedFd<'_>) -> usize {
+    let target = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap();
+    assert!(target.to_string_lossy().starts_with("/memfd:"), "{:?}", target);
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) }, 0);
+    stat.st_size as usize
+}
+
+// What a grandchild given the fd would see: its own mapping of the region.
+fn map_and_read(fd: BorrowedFd<'_>, len: usize) -> Vec<u8> {
+    unsafe {
+        let addr = libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, fd.as_raw_fd(), 0);
+        assert_ne!(addr, libc::MAP_FAILED);
+        let bytes = std::slice::from_raw_parts(addr as *const u8, len).to_vec();
+        libc::munmap(addr, len);
+        bytes
+    }
+}
+
+#[test]
+fn child_hands_on_the_memfds() {
+    let (mut parent, child) = start_child(move |mut child| {
+        let received = child.recv_from_peer().unwrap();
+        let p2c = child.shm_fd(Direction::ParentToChild).unwrap();
+        assert_eq!(memfd_len(p2c), child.usable_size());
+        assert_eq!(map_and_read(p2c, received.len()), received);
+        // The C2P memfd also holds the trailer after the payload area.
+        assert!(memfd_len(child.shm_fd(Direction::ChildToParent).unwrap()) > child.usable_size());
+    });
+    parent.send_to_peer(&payload(1, 3000)).unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn both_directions_is_not_a_region() {
+    let child = ShmChild::new(3, 4, 5, 6, 7, 8, 4096);
+    let err = child.shm_fd(Direction::Bidirectional).unwrap_err();
+    assert_eq!(std::io::Error::from(err).kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn simplex_child_has_no_memfd_for_the_missing_direction() {
+    let builder = ShmParent::builder("unused").shm_size(4096).direction(Direction::ParentToChild);
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        assert!(child.shm_fd(Direction::ParentToChild).is_ok());
+        match child.shm_fd(Direction::ChildToParent) {
+            Err(EfdStreamError::WrongDirection { direction }) => assert_eq!(direction, Direction::ParentToChild),
+            other => panic!("expected WrongDirection, got {:?}", other),
+        }
+        assert_eq!(child.recv_from_peer().unwrap(), b"simplex");
+    });
+    parent.send_to_peer(b"simplex").unwrap();
+    child.join().unwrap();
+}
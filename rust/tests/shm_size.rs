@@ -0,0 +1,42 @@
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+use efdstream::{EfdStreamError, ShmChild};
+
+fn eventfd() -> OwnedFd {
+    unsafe { OwnedFd::from_raw_fd(libc::eventfd(0, libc::EFD_CLOEXEC)) }
+}
+
+fn memfd(len: i64) -> OwnedFd {
+    unsafe {
+        let fd = libc::memfd_create(c"test_shm_size".as_ptr(), libc::MFD_CLOEXEC);
+        assert!(fd >= 0);
+        assert_eq!(libc::ftruncate(fd, len), 0);
+        OwnedFd::from_raw_fd(fd)
+    }
+}
+
+fn init_with(p2c_len: i64, c2p_len: i64, shm_size: usize) -> Result<(), EfdStreamError> {
+    let (p2c_send, p2c_ack, c2p_send, c2p_ack) = (eventfd(), eventfd(), eventfd(), eventfd());
+    let (p2c_shm, c2p_shm) = (memfd(p2c_len), memfd(c2p_len));
+    let mut child = ShmChild::new(p2c_send.as_raw_fd(), p2c_ack.as_raw_fd(), p2c_shm.as_raw_fd(),
+        c2p_send.as_raw_fd(), c2p_ack.as_raw_fd(), c2p_shm.as_raw_fd(), shm_size);
+    child.init()
+}
+
+#[test]
+fn child_rejects_a_larger_configured_size() {
+    let err = init_with(4096, 4096, 8192).unwrap_err();
+    assert!(matches!(err, EfdStreamError::ShmSizeMismatch { parent: 4096, child: 8192 }), "{:?}", err);
+}
+
+#[test]
+fn child_rejects_a_smaller_configured_size() {
+    let err = init_with(8192, 8192, 4096).unwrap_err();
+    assert!(matches!(err, EfdStreamError::ShmSizeMismatch { parent: 8192, child: 4096 }), "{:?}", err);
+}
+
+#[test]
+fn child_accepts_matching_regions() {
+    // A parent without the C2P trailer, as Go and C parents create them.
+    init_with(4096, 4096, 4096).unwrap();
+}
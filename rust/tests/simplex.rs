@@ -0,0 +1,80 @@
+mod common;
+
+use std::io::ErrorKind;
+use std::thread;
+
+use efdstream::{Direction, EfdStreamError, ShmChild, ShmParent};
+
+use common::{echo_builder, payload, start_child_with};
+
+fn wrong_direction<T: std::fmt::Debug>(result: Result<T, EfdStreamError>, expected: Direction) {
+    match result {
+        Err(EfdStreamError::WrongDirection { direction }) => assert_eq!(direction, expected),
+        other => panic!("expected WrongDirection, got {:?}", other),
+    }
+}
+
+fn start_simplex(direction: Direction, child: impl FnOnce(ShmChild) + Send + 'static) -> (ShmParent, thread::JoinHandle<()>) {
+    start_child_with(ShmParent::builder("unused").shm_size(4096).direction(direction), child)
+}
+
+#[test]
+fn parent_streams_to_child() {
+    let (mut parent, child) = start_simplex(Direction::ParentToChild, |mut child| {
+        assert!(child.c2p_send_fd().is_none() && child.c2p_ack_fd().is_none());
+        assert!(child.p2c_send_fd().is_some() && child.p2c_ack_fd().is_some());
+        for i in 0..3 {
+            assert_eq!(child.recv_from_peer().unwrap(), payload(i, 1000));
+        }
+        wrong_direction(child.send_to_peer(b"reply"), Direction::ParentToChild);
+        let eof = child.recv_from_peer().unwrap_err();
+        assert_eq!(std::io::Error::from(eof).kind(), ErrorKind::UnexpectedEof);
+    });
+    assert!(parent.c2p_send_fd().is_none() && parent.c2p_ack_fd().is_none());
+    assert!(parent.control_atomic().is_none());
+    for i in 0..3 {
+        parent.send_to_peer(&payload(i, 1000)).unwrap();
+    }
+    wrong_direction(parent.recv_from_peer(), Direction::ParentToChild);
+    wrong_direction(parent.try_read_data(), Direction::ParentToChild);
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn child_streams_to_parent() {
+    let (mut parent, child) = start_simplex(Direction::ChildToParent, |mut child| {
+        assert!(child.p2c_send_fd().is_none() && child.p2c_ack_fd().is_none());
+        assert!(child.c2p_send_fd().is_some() && child.c2p_ack_fd().is_some());
+        for i in 0..3 {
+            child.send_to_peer(&payload(i, 1000)).unwrap();
+        }
+        wrong_direction(child.recv_from_peer(), Direction::ChildToParent);
+        child.shutdown_send().unwrap();
+    });
+    assert!(parent.p2c_send_fd().is_none() && parent.p2c_ack_fd().is_none());
+    wrong_direction(parent.send_to_peer(b"request"), Direction::ChildToParent);
+    for i in 0..3 {
+        assert_eq!(parent.recv_from_peer().unwrap(), payload(i, 1000));
+    }
+    let eof = parent.recv_from_peer().unwrap_err();
+    assert_eq!(std::io::Error::from(eof).kind(), ErrorKind::UnexpectedEof);
+    child.join().unwrap();
+}
+
+#[test]
+fn spawned_child_gets_only_its_direction() {
+    let mut parent = echo_builder().shm_size(4096).direction(Direction::ParentToChild).build();
+    parent.start().unwrap();
+    // Delivered and ACKed; the echo child then fails to reply.
+    parent.send_to_peer(b"one way").unwrap();
+    wrong_direction(parent.recv_from_peer(), Direction::ParentToChild);
+}
+
+#[test]
+fn rejects_options_needing_the_missing_direction() {
+    let mut parent = echo_builder().direction(Direction::ChildToParent).timestamps(true).build();
+    wrong_direction(parent.start(), Direction::ChildToParent);
+    let mut parent = echo_builder().direction(Direction::ParentToChild).control_channel(true).build();
+    wrong_direction(parent.start(), Direction::ParentToChild);
+}
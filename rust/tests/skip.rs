@@ -0,0 +1,38 @@
+mod common;
+
+use common::{payload, start_child, start_echo};
+
+#[test]
+fn parent_skip_consumes_the_doorbell() {
+    let mut parent = start_echo(4096);
+    for i in 0..3 {
+        parent.send_to_peer(&payload(i, 1000)).unwrap();
+        parent.skip_frame().unwrap();
+        // Nothing left counted on the eventfd for a later read to trip on.
+        assert_eq!(parent.try_read_data().unwrap(), None);
+    }
+    parent.send_to_peer(b"kept").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"kept");
+}
+
+#[test]
+fn child_skips_unwanted_frames() {
+    let (mut parent, child) = start_child(move |mut child| {
+        // Keeps every third message.
+        let mut kept = Vec::new();
+        for i in 0..9 {
+            if i % 3 == 0 {
+                kept.push(child.recv_from_peer().unwrap());
+            } else {
+                child.skip_frame().unwrap();
+            }
+        }
+        assert!(child.skip_frame().is_err());
+        kept
+    });
+    for i in 0..9 {
+        parent.send_to_peer(&payload(i, 500)).unwrap();
+    }
+    parent.shutdown_send().unwrap();
+    assert_eq!(child.join().unwrap(), [payload(0, 500), payload(3, 500), payload(6, 500)]);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::{EfdStreamError, SocketChild};
+use nix::sys::socket::{socketpair, AddressFamily, SockFlag, SockType};
+
+use common::payload;
+
+fn pair(max_size: usize) -> (SocketChild, SocketChild) {
+    let (a, b) = socketpair(AddressFamily::Unix, SockType::SeqPacket, None, SockFlag::SOCK_CLOEXEC).unwrap();
+    (SocketChild::from_fd(a, max_size), SocketChild::from_fd(b, max_size))
+}
+
+fn kind(e: EfdStreamError) -> ErrorKind {
+    std::io::Error::from(e).kind()
+}
+
+#[test]
+fn keeps_message_boundaries() {
+    let (mut a, mut b) = pair(64 * 1024);
+    let sent: Vec<Vec<u8>> = [1, 300, 64 * 1024, 2, 4096].iter().enumerate()
+        .map(|(i, &len)| payload(i as u8, len))
+        .collect();
+    // Queued back to back before anything is read.
+    for message in &sent {
+        a.send_data(message).unwrap();
+    }
+    for message in &sent {
+        assert_eq!(&b.read_data().unwrap(), message);
+    }
+    assert_eq!(b.try_read_data().unwrap(), None);
+}
+
+#[test]
+fn refuses_empty_and_oversize_messages() {
+    let (mut a, mut b) = pair(1024);
+    assert!(matches!(a.send_data(&[]), Err(EfdStreamError::ReservedLength { len: 0 })));
+    assert_eq!(kind(a.send_data(&payload(0, 1025)).unwrap_err()), ErrorKind::InvalidInput);
+
+    a.send_data(b"after").unwrap();
+    assert_eq!(b.read_data().unwrap(), b"after");
+}
+
+#[test]
+fn drops_packet_larger_than_receiver_accepts() {
+    let (a, b) = socketpair(AddressFamily::Unix, SockType::SeqPacket, None, SockFlag::SOCK_CLOEXEC).unwrap();
+    let mut big = SocketChild::from_fd(a, 4096);
+    let mut small = SocketChild::from_fd(b, 1024);
+
+    big.send_data(&payload(0, 2048)).unwrap();
+    big.send_data(b"next").unwrap();
+    assert_eq!(kind(small.read_data().unwrap_err()), ErrorKind::InvalidData);
+    assert_eq!(small.read_data().unwrap(), b"next");
+}
+
+#[test]
+fn reports_message_beyond_send_buffer() {
+    // Larger than net.core.wmem_max allows the send buffer to grow.
+    let (mut a, _b) = pair(256 * 1024 * 1024);
+    let err = a.send_data(&vec![0; 128 * 1024 * 1024]).unwrap_err();
+    assert_eq!(kind(err), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn half_close_ends_the_peer_stream() {
+    let (mut a, mut b) = pair(1024);
+    a.send_data(b"last").unwrap();
+    a.shutdown_send().unwrap();
+    assert_eq!(b.read_data().unwrap(), b"last");
+    assert_eq!(kind(b.read_data().unwrap_err()), ErrorKind::UnexpectedEof);
+    b.send_data(b"reply").unwrap();
+    assert_eq!(a.read_data().unwrap(), b"reply");
+}
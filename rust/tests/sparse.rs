@@ -0,0 +1,32 @@
+mod common;
+
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+use efdstream::{Direction, ShmParent};
+
+use common::start_child_with;
+
+const GIB: usize = 1 << 30;
+
+// Bytes of the memfd actually backed by pages.
+fn committed(fd: BorrowedFd<'_>) -> usize {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) }, 0);
+    stat.st_blocks as usize * 512
+}
+
+#[test]
+fn large_regions_commit_only_touched_pages() {
+    let (mut parent, child) = start_child_with(ShmParent::builder("unused").shm_size(GIB), move |mut child| {
+        let request = child.recv_from_peer().unwrap();
+        child.send_to_peer(&request).unwrap();
+        let p2c = committed(child.shm_fd(Direction::ParentToChild).unwrap());
+        let c2p = committed(child.shm_fd(Direction::ChildToParent).unwrap());
+        (p2c, c2p)
+    });
+    parent.send_to_peer(&[7; 10_000]).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), [7; 10_000]);
+    let (p2c, c2p) = child.join().unwrap();
+    // The message and, for C2P, the trailer at the far end: a few pages.
+    assert!(p2c < 1 << 20 && c2p < 1 << 20, "P2C {} bytes, C2P {} bytes committed", p2c, c2p);
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{payload, start_child};
+
+extern "C" fn ignore(_: libc::c_int) {}
+
+// SIGUSR1 with a handler that does nothing and no SA_RESTART, so every
+// signal a blocked thread takes ends its poll or read early with EINTR.
+fn install_handler() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as usize;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert_eq!(libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()), 0);
+    });
+}
+
+// Interrupts `thread` every millisecond until dropped.
+struct Pounder {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Pounder {
+    fn start(target: libc::pthread_t) -> Self {
+        install_handler();
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+        let handle = thread::spawn(move || {
+            while !flag.load(Ordering::Relaxed) {
+                unsafe { libc::pthread_kill(target, libc::SIGUSR1) };
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        Self { stop, handle: Some(handle) }
+    }
+}
+
+impl Drop for Pounder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.take().unwrap().join().unwrap();
+    }
+}
+
+#[test]
+fn child_waits_survive_signals() {
+    let (tid_tx, tid_rx) = mpsc::channel();
+    let (data_tx, data_rx) = mpsc::channel();
+    let (mut parent, child) = start_child(move |mut child| {
+        tid_tx.send(unsafe { libc::pthread_self() }).unwrap();
+        data_tx.send(child.recv_from_peer().unwrap()).unwrap();
+        child.listen(|data| data_tx.send(data.to_vec()).unwrap()).unwrap();
+    });
+    let pounder = Pounder::start(tid_rx.recv().unwrap());
+    // Each wait is interrupted many times before anything is rung.
+    for i in 0..3 {
+        thread::sleep(Duration::from_millis(30));
+        assert!(data_rx.try_recv().is_err(), "a wakeup was taken for a message");
+        parent.send_to_peer(&payload(i, 1000)).unwrap();
+        assert_eq!(data_rx.recv().unwrap(), payload(i, 1000));
+    }
+    thread::sleep(Duration::from_millis(30));
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+    drop(pounder);
+    assert!(data_rx.try_recv().is_err());
+}
+
+#[test]
+fn parent_timeouts_survive_signals() {
+    let (go_tx, go_rx) = mpsc::channel::<()>();
+    let (mut parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        go_rx.recv().unwrap();
+        child.send_to_peer(&payload(1, 1000)).unwrap();
+        go_rx.recv().unwrap();
+        assert_eq!(child.recv_from_peer().unwrap(), payload(2, 1000));
+    });
+    let _pounder = Pounder::start(unsafe { libc::pthread_self() });
+
+    // Nothing arrives: the signals neither end the wait early nor produce data.
+    let started = Instant::now();
+    assert_eq!(parent.read_data_timeout(Duration::from_millis(100)).unwrap(), None);
+    assert!(started.elapsed() >= Duration::from_millis(100), "gave up after {:?}", started.elapsed());
+
+    go_tx.send(()).unwrap();
+    assert_eq!(parent.read_data_timeout(Duration::from_secs(5)).unwrap(), Some(payload(1, 1000)));
+
+    // The ACK comes only once the child is let go, well after the first signals.
+    let release = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        go_tx.send(()).unwrap();
+    });
+    assert!(parent.send_data_timeout(&payload(2, 1000), Duration::from_secs(5)).unwrap());
+    release.join().unwrap();
+    child.join().unwrap();
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::{EfdStreamError, ShmParent};
+
+use common::{echo_builder, start_child_with};
+
+const LEN: usize = 64 * 1024;
+
+// An anonymous mapping of `LEN` bytes somewhere the kernel picked.
+fn anonymous() -> *mut libc::c_void {
+    let addr = unsafe {
+        libc::mmap(std::ptr::null_mut(), LEN, libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+    };
+    assert_ne!(addr, libc::MAP_FAILED);
+    addr
+}
+
+fn kind<T: std::fmt::Debug>(result: Result<T, EfdStreamError>) -> ErrorKind {
+    std::io::Error::from(result.unwrap_err()).kind()
+}
+
+#[test]
+fn state_lands_at_the_requested_address() {
+    // Far from where the kernel places mappings itself, so free in both
+    // processes; one just unmapped could be reused by a concurrent test.
+    let addr = 0x5a5a_0000_0000usize as *mut libc::c_void;
+
+    let mut parent = echo_builder().shm_size(4096).shared_state_size(LEN).shared_state_at(addr).build();
+    parent.start().unwrap();
+    assert_eq!(parent.shared_state().unwrap().as_ptr(), addr as *const u8);
+    // The child mapped it at the same address, or it would have exited.
+    parent.send_to_peer(b"ping").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+}
+
+#[test]
+fn taken_address_is_refused_not_replaced() {
+    let addr = anonymous();
+    unsafe { *(addr as *mut u8) = 42 };
+
+    let mut parent = echo_builder().shm_size(4096).shared_state_size(LEN).shared_state_at(addr).build();
+    assert_eq!(kind(parent.start()), ErrorKind::AddrInUse);
+    assert_eq!(unsafe { *(addr as *const u8) }, 42);
+    assert_eq!(unsafe { libc::munmap(addr, LEN) }, 0);
+}
+
+#[test]
+fn child_refuses_a_taken_address() {
+    let addr = anonymous();
+    // Raw pointers aren't Send.
+    let raw = addr as usize;
+    let (_parent, child) = start_child_with(ShmParent::builder("unused").shm_size(4096).shared_state_size(LEN), move |child| {
+        let mut child = child.shared_state_at(raw as *mut _);
+        kind(child.init())
+    });
+    assert_eq!(child.join().unwrap(), ErrorKind::AddrInUse);
+    assert_eq!(unsafe { libc::munmap(addr, LEN) }, 0);
+}
+
+#[test]
+fn needs_a_shared_state() {
+    let addr = anonymous();
+    let mut parent = echo_builder().shared_state_at(addr).build();
+    assert_eq!(kind(parent.start()), ErrorKind::InvalidInput);
+    assert_eq!(unsafe { libc::munmap(addr, LEN) }, 0);
+}
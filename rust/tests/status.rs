@@ -0,0 +1,33 @@
+mod common;
+
+use efdstream::{Direction, EfdStreamError, ShmParent};
+
+use common::{start_child, start_echo};
+
+#[test]
+fn child_status_reaches_the_sender() {
+    let (mut parent, child) = start_child(move |mut child| {
+        child.listen_with_status(|data| if data == b"ok" { 0 } else { data.len() as u64 }).unwrap();
+    });
+
+    assert_eq!(parent.send_data_with_status(b"ok").unwrap(), 0);
+    assert_eq!(parent.send_data_with_status(b"unknown request").unwrap(), 15);
+    // The status word is cleared for each send.
+    assert_eq!(parent.send_data_with_status(b"ok").unwrap(), 0);
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn children_without_a_status_report_zero() {
+    let mut parent = start_echo(4096);
+    assert_eq!(parent.send_data_with_status(b"ping").unwrap(), 0);
+    assert_eq!(parent.recv_from_peer().unwrap(), b"ping");
+}
+
+#[test]
+fn needs_the_c2p_region() {
+    let mut parent = ShmParent::builder("unused").direction(Direction::ParentToChild).build();
+    let err = parent.send_data_with_status(b"ping").unwrap_err();
+    assert!(matches!(err, EfdStreamError::WrongDirection { direction: Direction::ParentToChild }), "{:?}", err);
+}
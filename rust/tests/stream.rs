@@ -0,0 +1,57 @@
+mod common;
+
+use std::io::ErrorKind;
+
+use efdstream::EfdStreamError;
+
+use common::{payload, start_child};
+
+#[test]
+fn responder_streams_rows_per_request() {
+    let (mut parent, child) = start_child(|mut child| {
+        child.listen_with_responder(|request, responder| {
+            let rows = request[0];
+            responder.send_stream((0..rows).map(|i| payload(i, 100))).unwrap();
+        }).unwrap();
+    });
+    for rows in [3u8, 0, 1] {
+        parent.send_to_peer(&[rows]).unwrap();
+        let items: Vec<_> = parent.read_stream().collect::<Result<_, _>>().unwrap();
+        assert_eq!(items, (0..rows).map(|i| payload(i, 100)).collect::<Vec<_>>());
+    }
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
+
+#[test]
+fn child_streams_outside_a_handler() {
+    let (mut parent, child) = start_child(|mut child| {
+        child.send_stream(vec![b"a".to_vec(), Vec::new(), b"c".to_vec()]).unwrap();
+        child.send_to_peer(b"plain").unwrap();
+    });
+    let items: Vec<_> = parent.read_stream().collect::<Result<_, _>>().unwrap();
+    assert_eq!(items, [b"a".to_vec(), Vec::new(), b"c".to_vec()]);
+
+    let mut stream = parent.read_stream();
+    match stream.next() {
+        Some(Err(EfdStreamError::Io(e))) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+        other => panic!("expected InvalidData, got {:?}", other),
+    }
+    assert!(stream.next().is_none());
+    child.join().unwrap();
+}
+
+#[test]
+fn oversized_item_queues_nothing() {
+    let (mut parent, child) = start_child(|mut child| {
+        child.listen_with_responder(|_, responder| {
+            let too_big = vec![0; 64 * 1024];
+            assert!(responder.send_stream([b"row".to_vec(), too_big]).is_err());
+            responder.send(b"fallback").unwrap();
+        }).unwrap();
+    });
+    parent.send_to_peer(b"query").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"fallback");
+    parent.shutdown_send().unwrap();
+    child.join().unwrap();
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use std::sync::mpsc;
+
+use efdstream::ShmParent;
+
+use common::payload;
+
+#[test]
+fn thread_child_receives_over_real_eventfds() {
+    for handshake in [false, true] {
+        let (tx, rx) = mpsc::channel();
+        let mut parent = ShmParent::builder("unused").shm_size(4096).handshake(handshake).build();
+        let child = parent.spawn_thread_child(move |data| tx.send(data.to_vec()).unwrap()).unwrap();
+        assert!(parent.child_id().is_none());
+        for i in 0..3 {
+            parent.send_to_peer(&payload(i, 1000)).unwrap();
+            assert_eq!(rx.recv().unwrap(), payload(i, 1000));
+        }
+        parent.shutdown_send().unwrap();
+        child.join().unwrap().unwrap();
+    }
+}
+
+#[test]
+fn dropping_the_parent_ends_the_thread() {
+    let mut parent = ShmParent::builder("unused").shm_size(4096).build();
+    let child = parent.spawn_thread_child(|_| {}).unwrap();
+    parent.send_to_peer(b"one").unwrap();
+    drop(parent);
+    child.join().unwrap().unwrap();
+}
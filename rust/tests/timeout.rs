@@ -0,0 +1,34 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{payload, start_echo};
+
+const WAIT: Duration = Duration::from_millis(50);
+
+#[test]
+fn read_gives_up_and_the_next_one_still_works() {
+    let mut parent = start_echo(4096);
+    let start = Instant::now();
+    assert_eq!(parent.read_data_timeout(WAIT).unwrap(), None);
+    assert!(start.elapsed() >= WAIT);
+
+    parent.send_to_peer(b"late").unwrap();
+    assert_eq!(parent.read_data_timeout(Duration::from_secs(5)).unwrap(), Some(b"late".to_vec()));
+}
+
+#[test]
+fn send_gives_up_on_a_stuck_child() {
+    let mut parent = start_echo(4096);
+    parent.send_to_peer(&payload(1, 100)).unwrap();
+    // The child is blocked handing back the first reply, so nothing ACKs
+    // this one until that reply is read.
+    let start = Instant::now();
+    assert!(!parent.send_data_timeout(&payload(2, 100), WAIT).unwrap());
+    assert!(start.elapsed() >= WAIT);
+
+    assert_eq!(parent.recv_from_peer().unwrap(), payload(1, 100));
+    assert_eq!(parent.recv_from_peer().unwrap(), payload(2, 100));
+    assert!(parent.send_data_timeout(&payload(3, 100), Duration::from_secs(5)).unwrap());
+    assert_eq!(parent.recv_from_peer().unwrap(), payload(3, 100));
+}
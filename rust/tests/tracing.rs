@@ -0,0 +1,110 @@
+#![cfg(feature = "tracing")]
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing_core::span::Current;
+use tracing::{Event, Metadata, Subscriber};
+
+use common::start_echo;
+
+type FieldMap = HashMap<String, String>;
+
+// Spans with their fields, and events, in order. Span ids are 1-based
+// indexes into `spans`; `entered` tracks the current one, which the crate
+// records late fields on.
+#[derive(Clone, Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<(&'static Metadata<'static>, FieldMap)>>>,
+    events: Arc<Mutex<Vec<FieldMap>>>,
+    entered: Arc<Mutex<Vec<Id>>>,
+}
+
+struct Fields<'a>(&'a mut FieldMap);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = HashMap::new();
+        span.record(&mut Fields(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        self.events.lock().unwrap().push(fields);
+    }
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+
+    fn current_span(&self) -> Current {
+        match self.entered.lock().unwrap().last() {
+            Some(id) => Current::new(id.clone(), self.spans.lock().unwrap()[id.into_u64() as usize - 1].0),
+            None => Current::none(),
+        }
+    }
+}
+
+#[test]
+fn send_and_receive_get_spans() {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let mut parent = start_echo(4096);
+        parent.send_to_peer(b"traced").unwrap();
+        assert_eq!(parent.recv_from_peer().unwrap(), b"traced");
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    let names: Vec<_> = spans.iter().map(|(metadata, _)| metadata.name()).collect();
+    assert_eq!(names, ["efdstream.send", "efdstream.receive"]);
+    let (send, receive) = (&spans[0].1, &spans[1].1);
+    assert_eq!(send["messaging.destination.name"], "\"p2c\"");
+    assert_eq!(send["messaging.message.body.size"], "6");
+    assert!(send.contains_key("efdstream.rtt_us"));
+    assert_eq!(receive["messaging.destination.name"], "\"c2p\"");
+    assert_eq!(receive["messaging.message.body.size"], "6");
+    assert!(recorder.events.lock().unwrap().is_empty());
+}
+
+#[test]
+fn errors_become_events() {
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let mut parent = start_echo(4096);
+        let too_big = vec![0; parent.usable_size() + 1];
+        assert!(parent.send_to_peer(&too_big).is_err());
+    });
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert!(events[0]["error"].contains("too large"), "{:?}", events[0]);
+}
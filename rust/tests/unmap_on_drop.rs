@@ -0,0 +1,33 @@
+mod common;
+
+use common::{echo_builder, payload};
+
+// Lines of /proc/self/maps backed by a memfd named with `prefix`.
+fn mappings(prefix: &str) -> Vec<String> {
+    std::fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains(&format!("/memfd:{}", prefix)))
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn drop_unmaps_every_region() {
+    let prefix = "unmap_on_drop_test";
+    let mut parent = echo_builder().shm_size(4096).shared_state_size(64).memfd_prefix(prefix).build();
+    parent.start().unwrap();
+    parent.send_to_peer(&payload(3, 1000)).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), payload(3, 1000));
+    // P2C, C2P and the shared state, each mapped by this process only.
+    assert!(mappings(prefix).len() >= 3, "{:?}", mappings(prefix));
+    let state = parent.shared_state().unwrap().as_ptr() as usize;
+    drop(parent);
+    assert_eq!(mappings(prefix), Vec::<String>::new());
+    let state_mapped = std::fs::read_to_string("/proc/self/maps").unwrap().lines().any(|line| {
+        let range = line.split_whitespace().next().unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        (usize::from_str_radix(start, 16).unwrap()..usize::from_str_radix(end, 16).unwrap()).contains(&state)
+    });
+    assert!(!state_mapped, "shared state at {:#x} still mapped", state);
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "io-uring")]
+
+mod common;
+
+use std::future::{poll_fn, Future};
+use std::sync::mpsc;
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+
+use efdstream::UringShmParent;
+
+use common::{payload, start_child, start_echo};
+
+#[tokio::test(flavor = "current_thread")]
+async fn round_trips_through_io_uring() {
+    let mut parent = UringShmParent::new(start_echo(4096)).unwrap();
+    for i in 0..5 {
+        let sent = payload(i, 100 * (i as usize + 1));
+        parent.send_data(&sent).await.unwrap();
+        assert_eq!(parent.read_data().await.unwrap(), sent);
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn dropped_read_keeps_the_message() {
+    let (go_tx, go_rx) = mpsc::channel::<()>();
+    let (parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        go_rx.recv().unwrap();
+        child.send_to_peer(b"first").unwrap();
+        child.send_to_peer(b"second").unwrap();
+    });
+    let mut parent = UringShmParent::new(parent).unwrap();
+
+    // Submitted, then left in flight until the doorbell rings and the read
+    // takes it; only then is the future dropped.
+    {
+        let mut read = Box::pin(parent.read_data());
+        assert!(poll_fn(|cx| Poll::Ready(read.as_mut().poll(cx))).await.is_pending());
+        go_tx.send(()).unwrap();
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(parent.read_data().await.unwrap(), b"first");
+    assert_eq!(parent.read_data().await.unwrap(), b"second");
+    child.join().unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn dropped_read_before_anything_arrives_consumes_nothing() {
+    let (go_tx, go_rx) = mpsc::channel::<()>();
+    let (parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        go_rx.recv().unwrap();
+        child.send_to_peer(b"late").unwrap();
+    });
+    let mut parent = UringShmParent::new(parent).unwrap();
+    {
+        let mut read = Box::pin(parent.read_data());
+        assert!(poll_fn(|cx| Poll::Ready(read.as_mut().poll(cx))).await.is_pending());
+    }
+    go_tx.send(()).unwrap();
+    assert_eq!(parent.read_data().await.unwrap(), b"late");
+    child.join().unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn dropped_send_is_acked_before_the_next() {
+    let (got_tx, got_rx) = mpsc::channel();
+    let (parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        // Slow enough that the first send is dropped while awaiting its ACK.
+        thread::sleep(Duration::from_millis(50));
+        for _ in 0..2 {
+            got_tx.send(child.recv_from_peer().unwrap()).unwrap();
+        }
+    });
+    let mut parent = UringShmParent::new(parent).unwrap();
+    {
+        let mut send = Box::pin(parent.send_data(b"dropped"));
+        assert!(poll_fn(|cx| Poll::Ready(send.as_mut().poll(cx))).await.is_pending());
+    }
+    parent.send_data(b"next").await.unwrap();
+    assert_eq!(got_rx.recv().unwrap(), b"dropped");
+    assert_eq!(got_rx.recv().unwrap(), b"next");
+    child.join().unwrap();
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use efdstream::EfdStreamError;
+
+use common::{payload, start_child, start_echo};
+
+#[test]
+fn slices_arrive_as_one_message() {
+    let mut parent = start_echo(4096);
+    let (header, body) = (payload(1, 16), payload(2, 3000));
+    parent.send_data_vectored(&[&[], &header, &[], &[], &body, &[]]).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), [header, body].concat());
+
+    parent.send_data_vectored(&[b"single"]).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"single");
+}
+
+#[test]
+fn parent_checks_the_total() {
+    let mut parent = start_echo(4096);
+    let half = payload(3, parent.usable_size() / 2 + 1);
+    let err = std::io::Error::from(parent.send_data_vectored(&[&half, &half]).unwrap_err());
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(matches!(parent.send_data_vectored(&[&[], &[]]), Err(EfdStreamError::ReservedLength { len: 0 })));
+
+    // Nothing was signalled, so the session carries on.
+    parent.send_to_peer(b"after").unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), b"after");
+}
+
+#[test]
+fn child_sends_slices_too() {
+    let (mut parent, child) = start_child(move |mut child| {
+        child.init().unwrap();
+        let big = payload(4, child.usable_size() / 2 + 1);
+        let err = std::io::Error::from(child.send_data_vectored(&[&big, &[], &big]).unwrap_err());
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        child.send_data_vectored(&[&[], b"head:", &[], &big[..100]]).unwrap();
+    });
+    assert_eq!(parent.recv_from_peer().unwrap(), [&b"head:"[..], &payload(4, 100)[..]].concat());
+    child.join().unwrap();
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use efdstream::ShmWriter;
+
+use common::{payload, CHILD};
+
+const FRAME: usize = 64;
+
+#[test]
+fn io_copy_round_trips_through_a_ring_child() {
+    let mut writer = ShmWriter::builder(CHILD).child_args(&["-mode", "echo"]).window(8).frame_size(FRAME).build();
+    writer.start().unwrap();
+    // Five whole frames and a partial one, all within the window, so the
+    // echoed replies fit in the C2P ring unread.
+    let data = payload(1, 5 * FRAME + 10);
+    assert_eq!(io::copy(&mut &data[..], &mut writer).unwrap(), data.len() as u64);
+    writer.flush().unwrap();
+
+    let mut echoed = Vec::new();
+    for _ in 0..6 {
+        echoed.extend(writer.parent().read_data().unwrap());
+    }
+    assert_eq!(echoed, data);
+}
+
+#[test]
+fn drop_does_not_wait_for_an_exited_child() {
+    // `true` exits at once and never reads a frame.
+    let mut writer = ShmWriter::builder("/bin/true").window(8).frame_size(FRAME).build();
+    writer.start().unwrap();
+    writer.write_all(&payload(2, 2 * FRAME + 10)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let (done, dropped) = mpsc::channel();
+    thread::spawn(move || {
+        drop(writer);
+        done.send(()).unwrap();
+    });
+    dropped.recv_timeout(Duration::from_secs(5)).expect("drop hung on the exited child");
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::OwnedFd;
+
+use efdstream::{Direction, ShmParent};
+
+use common::{payload, start_child_with};
+
+fn contents(memfd: &OwnedFd) -> Vec<u8> {
+    let file = File::from(memfd.try_clone().unwrap());
+    let mut bytes = vec![0u8; file.metadata().unwrap().len() as usize];
+    file.read_exact_at(&mut bytes, 0).unwrap();
+    bytes
+}
+
+// The child keeps dups of both memfds, so the regions outlive the parent's
+// mappings and show what the parent left in them.
+fn regions_after_drop(zero_on_drop: bool) -> (Vec<u8>, Vec<u8>) {
+    let builder = ShmParent::builder("unused").shm_size(4096).zero_on_drop(zero_on_drop);
+    let (mut parent, child) = start_child_with(builder, move |mut child| {
+        let request = child.recv_from_peer().unwrap();
+        child.send_to_peer(&request).unwrap();
+        let p2c = child.shm_fd(Direction::ParentToChild).unwrap().try_clone_to_owned().unwrap();
+        let c2p = child.shm_fd(Direction::ChildToParent).unwrap().try_clone_to_owned().unwrap();
+        (p2c, c2p)
+    });
+    parent.send_to_peer(&payload(7, 3000)).unwrap();
+    assert_eq!(parent.recv_from_peer().unwrap(), payload(7, 3000));
+    let (p2c, c2p) = child.join().unwrap();
+    drop(parent);
+    (contents(&p2c), contents(&c2p))
+}
+
+#[test]
+fn drop_zeroes_both_regions() {
+    let (p2c, c2p) = regions_after_drop(true);
+    assert!(p2c.iter().all(|&b| b == 0), "P2C region not zeroed");
+    assert!(c2p.iter().all(|&b| b == 0), "C2P region not zeroed");
+}
+
+#[test]
+fn drop_leaves_the_regions_by_default() {
+    let (p2c, c2p) = regions_after_drop(false);
+    assert_eq!(&p2c[..3000], &payload(7, 3000)[..]);
+    assert_eq!(&c2p[..3000], &payload(7, 3000)[..]);
+}